@@ -1,4 +1,10 @@
-use std::{collections::HashMap, os::fd::FromRawFd, path::PathBuf, time::Duration};
+use std::{
+    collections::HashMap,
+    os::fd::FromRawFd,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use smithay::{
     backend::{
@@ -10,43 +16,54 @@ use smithay::{
             self, compositor::DrmCompositor, DrmDevice, DrmDeviceFd, DrmError, DrmNode, NodeType,
         },
         egl::{EGLDevice, EGLDisplay},
+        input::InputEvent,
         libinput::{LibinputInputBackend, LibinputSessionInterface},
         renderer::{
             self,
             element::{
-                surface::WaylandSurfaceRenderElement,
+                surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
                 texture::{TextureBuffer, TextureRenderElement},
                 AsRenderElements,
             },
             gles::{GlesRenderer, GlesTexture},
             multigpu::{gbm::GbmGlesBackend, GpuManager, MultiRenderer, MultiTexture},
-            Bind, BufferType, ExportMem, Offscreen,
+            Bind, BufferType, ExportMem, ImportMem, Offscreen,
         },
         session::{libseat::LibSeatSession, Session},
         udev::{self, UdevBackend, UdevEvent},
         SwapBuffersError,
     },
-    desktop::{layer_map_for_output, space::SpaceElement, LayerSurface},
+    desktop::{
+        layer_map_for_output,
+        space::SpaceElement,
+        utils::{send_frames_surface_tree, OutputPresentationFeedback},
+        LayerSurface,
+    },
+    input::pointer::{CursorImageAttributes, CursorImageStatus},
     output::{Mode as WlMode, Output, PhysicalProperties},
     reexports::{
         calloop::{
             timer::{TimeoutAction, Timer},
-            EventLoop, RegistrationToken,
+            EventLoop, LoopHandle, RegistrationToken,
         },
         drm::{
-            control::{crtc, ModeTypeFlags},
+            control::{crtc, Device as ControlDevice, ModeTypeFlags},
             Device as DrmDeviceTrait, SystemError,
         },
-        input::Libinput,
+        input::{self, Libinput},
         nix::fcntl::OFlag,
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
         wayland_server::{
             backend::GlobalId,
             protocol::{wl_output::WlOutput, wl_shm},
-            Display,
+            Display, Resource,
         },
     },
-    utils::{DeviceFd, Point, Rectangle, Scale, Size, Transform},
-    wayland::{shell::wlr_layer::Layer, shm},
+    utils::{DeviceFd, Logical, Point, Rectangle, Scale, Size, Transform},
+    wayland::{
+        compositor::with_states, fractional_scale::with_fractional_scale, shell::wlr_layer::Layer,
+        shm,
+    },
 };
 use smithay_drm_extras::{
     drm_scanner::{DrmScanEvent, DrmScanner},
@@ -55,10 +72,17 @@ use smithay_drm_extras::{
 use tracing::{error, info, trace, warn};
 
 use crate::{
+    config::{AccelProfile, ClickMethod, ScrollMethod},
     delegate_screencopy_manager,
     protocols::screencopy::{frame::Screencopy, ScreencopyHandler, ScreencopyManagerState},
     state::{Backend, CalloopData, MagmaState, CONFIG},
-    utils::render::CustomRenderElements,
+    utils::{
+        cursor::{theme_and_size, Cursor},
+        render::{
+            refresh_interval, send_frames, take_presentation_feedback,
+            update_primary_scanout_output, CustomRenderElements, PASSTHROUGH_FRAME_WIDTH,
+        },
+    },
 };
 
 static CURSOR_DATA: &[u8] = include_bytes!("../../resources/cursor.rgba");
@@ -70,20 +94,151 @@ const SUPPORTED_FORMATS: &[Fourcc] = &[
     Fourcc::Argb8888,
 ];
 
-pub type GbmDrmCompositor =
-    DrmCompositor<GbmAllocator<DrmDeviceFd>, GbmDevice<DrmDeviceFd>, (), DrmDeviceFd>;
+pub type GbmDrmCompositor = DrmCompositor<
+    GbmAllocator<DrmDeviceFd>,
+    GbmDevice<DrmDeviceFd>,
+    Option<OutputPresentationFeedback>,
+    DrmDeviceFd,
+>;
 
 pub struct UdevData {
     pub session: LibSeatSession,
     _primary_gpu: DrmNode,
     gpus: GpuManager<GbmGlesBackend<GlesRenderer>>,
     devices: HashMap<DrmNode, Device>,
+    /// Theme cursor shown when no client set one, the builtin cursor is used without it
+    cursor: Option<Cursor>,
+    /// Connected input devices, to apply config changes to
+    input_devices: Vec<input::Device>,
+    /// Whether the crtcs are disabled to turn the outputs off
+    outputs_off: bool,
+    loop_handle: LoopHandle<'static, CalloopData<UdevData>>,
 }
 
 impl Backend for UdevData {
     fn seat_name(&self) -> String {
         self.session.seat()
     }
+
+    fn apply_input_config(&mut self) {
+        for device in &mut self.input_devices {
+            configure_input_device(device);
+        }
+    }
+
+    fn set_outputs_powered(&mut self, powered: bool) {
+        self.outputs_off = !powered;
+        for (node, device) in &mut self.devices {
+            for (crtc, surface) in &mut device.surfaces {
+                if powered {
+                    // the crtc has to be modeset again before the next frame
+                    if let Err(err) = surface.compositor.reset_state() {
+                        warn!("Failed to reset the state of {:?}: {}", crtc, err);
+                    }
+                    // rendering stopped with the outputs, so it has to be kicked off again
+                    let (node, crtc) = (*node, *crtc);
+                    self.loop_handle.insert_idle(move |data| {
+                        data.state.render(node, crtc, None).ok();
+                    });
+                } else if let Err(err) =
+                    device
+                        .drm
+                        .device_fd()
+                        .set_crtc(*crtc, None, (0, 0), &[], None)
+                {
+                    warn!("Failed to disable {:?}: {}", crtc, err);
+                }
+            }
+        }
+    }
+}
+
+/// Apply the pointer acceleration, scroll and touchpad settings `device` supports.
+fn configure_input_device(device: &mut input::Device) {
+    let method = match CONFIG.scroll.method {
+        ScrollMethod::TwoFinger => input::ScrollMethod::TwoFinger,
+        ScrollMethod::Edge => input::ScrollMethod::Edge,
+    };
+    if device.config_scroll_methods().contains(&method) {
+        if let Err(err) = device.config_scroll_set_method(method) {
+            warn!(
+                "Failed to set the scroll method of {}: {:?}",
+                device.name(),
+                err
+            );
+        }
+    }
+
+    let touchpad = &CONFIG.touchpad;
+    if device.config_tap_finger_count() > 0 {
+        if let Err(err) = device.config_tap_set_enabled(touchpad.tap_to_click) {
+            warn!("Failed to set tap to click of {}: {:?}", device.name(), err);
+        }
+    }
+    if device.config_dwt_is_available() {
+        if let Err(err) = device.config_dwt_set_enabled(touchpad.disable_while_typing) {
+            warn!(
+                "Failed to set disable while typing of {}: {:?}",
+                device.name(),
+                err
+            );
+        }
+    }
+    if let Some(click_method) = touchpad.click_method {
+        let method = match click_method {
+            ClickMethod::ButtonAreas => input::ClickMethod::ButtonAreas,
+            ClickMethod::ClickFinger => input::ClickMethod::Clickfinger,
+        };
+        if device.config_click_methods().contains(&method) {
+            if let Err(err) = device.config_click_set_method(method) {
+                warn!(
+                    "Failed to set the click method of {}: {:?}",
+                    device.name(),
+                    err
+                );
+            }
+        }
+    }
+    if device
+        .config_send_events_modes()
+        .contains(input::SendEventsMode::DISABLED_ON_EXTERNAL_MOUSE)
+    {
+        let mode = if touchpad.disable_on_external_mouse {
+            input::SendEventsMode::DISABLED_ON_EXTERNAL_MOUSE
+        } else {
+            input::SendEventsMode::ENABLED
+        };
+        if let Err(err) = device.config_send_events_set_mode(mode) {
+            warn!(
+                "Failed to set the send events mode of {}: {:?}",
+                device.name(),
+                err
+            );
+        }
+    }
+
+    if !device.config_accel_is_available() {
+        return;
+    }
+    let profile = match CONFIG.pointer.accel_profile {
+        AccelProfile::Flat => input::AccelProfile::Flat,
+        AccelProfile::Adaptive => input::AccelProfile::Adaptive,
+    };
+    if let Err(err) = device.config_accel_set_profile(profile) {
+        warn!(
+            "Failed to set the accel profile of {}: {:?}",
+            device.name(),
+            err
+        );
+    }
+    let speed = CONFIG.pointer.accel_speed.clamp(-1.0, 1.0);
+    if let Err(err) = device.config_accel_set_speed(speed) {
+        warn!(
+            "Failed to set the accel speed of {}: {:?}",
+            device.name(),
+            err
+        );
+    }
 }
 pub struct Device {
     pub surfaces: HashMap<crtc::Handle, Surface>,
@@ -101,6 +256,9 @@ pub struct Surface {
     compositor: GbmDrmCompositor,
     output: Output,
     pointer_texture: TextureBuffer<MultiTexture>,
+    /// Textures of the theme cursor frames drawn so far, by frame index
+    cursor_images: Vec<(usize, TextureBuffer<MultiTexture>)>,
+    wallpaper_texture: Option<TextureBuffer<MultiTexture>>,
 }
 
 pub fn init_udev() {
@@ -131,6 +289,10 @@ pub fn init_udev() {
         _primary_gpu: primary_gpu,
         gpus,
         devices: HashMap::new(),
+        cursor: Cursor::load(),
+        input_devices: Vec::new(),
+        outputs_off: false,
+        loop_handle: event_loop.handle(),
     };
 
     let mut state = MagmaState::new(
@@ -155,6 +317,16 @@ pub fn init_udev() {
     event_loop
         .handle()
         .insert_source(libinput_backend, move |event, _, calloopdata| {
+            let input_devices = &mut calloopdata.state.backend_data.input_devices;
+            match &event {
+                InputEvent::DeviceAdded { device } => {
+                    let mut device = device.clone();
+                    configure_input_device(&mut device);
+                    input_devices.push(device);
+                }
+                InputEvent::DeviceRemoved { device } => input_devices.retain(|d| d != device),
+                _ => {}
+            }
             calloopdata.state.process_input_event(event);
         })
         .unwrap();
@@ -193,15 +365,13 @@ pub fn init_udev() {
     let mut calloopdata = CalloopData { state, display };
 
     std::env::set_var("WAYLAND_DISPLAY", &calloopdata.state.socket_name);
+    // clients use the same cursor theme as the compositor
+    let (cursor_theme, cursor_size) = theme_and_size();
+    std::env::set_var("XCURSOR_THEME", cursor_theme);
+    std::env::set_var("XCURSOR_SIZE", cursor_size.to_string());
 
     for command in &CONFIG.autostart {
-        if let Err(err) = std::process::Command::new("/bin/sh")
-            .arg("-c")
-            .arg(command)
-            .spawn()
-        {
-            info!("{} {} {}", err, "Failed to spawn \"{}\"", command);
-        }
+        calloopdata.state.spawn(command, None);
     }
 
     event_loop
@@ -212,10 +382,44 @@ pub fn init_udev() {
                 .for_each(|e| e.refresh());
             data.display.flush_clients().unwrap();
             data.state.popup_manager.cleanup();
+            data.state.send_ipc_events();
         })
         .unwrap();
 }
 
+/// Loads the wallpaper configured for `output_name`, falling back to the global one.
+fn load_wallpaper<R>(renderer: &mut R, output_name: &str) -> Option<TextureBuffer<R::TextureId>>
+where
+    R: ImportMem,
+{
+    let image = CONFIG
+        .wallpapers
+        .get(output_name)
+        .into_iter()
+        .chain(CONFIG.wallpaper.as_ref())
+        .find_map(|path| match image::open(path) {
+            Ok(image) => Some(image.into_rgba8()),
+            Err(err) => {
+                warn!("Failed to load wallpaper {}: {}", path, err);
+                None
+            }
+        })?;
+    let (w, h) = image.dimensions();
+
+    TextureBuffer::from_memory(
+        renderer,
+        image.as_raw(),
+        Fourcc::Abgr8888,
+        (w as i32, h as i32),
+        false,
+        1,
+        Transform::Normal,
+        None,
+    )
+    .map_err(|err| warn!("Failed to upload wallpaper for {}: {:?}", output_name, err))
+    .ok()
+}
+
 pub fn primary_gpu(seat: &str) -> (DrmNode, PathBuf) {
     // TODO: can't this be in smithay?
     // primary_gpu() does the same thing anyway just without `NodeType::Render` check
@@ -340,12 +544,17 @@ impl MagmaState<UdevData> {
                 .as_mut()
                 .remove_node(&device.render_node);
 
-            for surface in device.surfaces.values() {
-                self.dh
-                    .disable_global::<MagmaState<UdevData>>(surface.global.clone());
-                for workspace in self.workspaces.iter() {
-                    workspace.remove_output(&surface.output)
-                }
+            let outputs: Vec<_> = device
+                .surfaces
+                .values()
+                .map(|surface| {
+                    self.dh
+                        .disable_global::<MagmaState<UdevData>>(surface.global.clone());
+                    surface.output.clone()
+                })
+                .collect();
+            for output in outputs {
+                self.remove_output(&output);
             }
         }
     }
@@ -357,13 +566,31 @@ impl MagmaState<UdevData> {
         &mut self,
         node: DrmNode,
         event: drm::DrmEvent,
-        _meta: &mut Option<drm::DrmEventMetadata>,
+        meta: &mut Option<drm::DrmEventMetadata>,
     ) {
         match event {
             drm::DrmEvent::VBlank(crtc) => {
                 let device = self.backend_data.devices.get_mut(&node).unwrap();
                 let surface = device.surfaces.get_mut(&crtc).unwrap();
-                surface.compositor.frame_submitted().ok();
+                if let Ok(Some(Some(mut feedback))) = surface.compositor.frame_submitted() {
+                    // the kernel reports when the vblank happened, if it uses the same clock
+                    let (time, flags) = match meta.as_ref().map(|meta| &meta.time) {
+                        Some(drm::DrmEventTime::Monotonic(time)) => (
+                            (*time).into(),
+                            wp_presentation_feedback::Kind::Vsync
+                                | wp_presentation_feedback::Kind::HwClock
+                                | wp_presentation_feedback::Kind::HwCompletion,
+                        ),
+                        _ => (self.clock.now(), wp_presentation_feedback::Kind::Vsync),
+                    };
+                    let sequence = meta.as_ref().map(|meta| meta.sequence).unwrap_or(0);
+                    feedback.presented(
+                        time,
+                        refresh_interval(&surface.output),
+                        sequence as u64,
+                        flags,
+                    );
+                }
                 self.render(node, crtc, None).ok();
             }
             drm::DrmEvent::Error(_) => {}
@@ -399,17 +626,21 @@ impl MagmaState<UdevData> {
                     connector.interface().as_str(),
                     connector.interface_id()
                 );
-                info!("{:#?}", connector
-                    .modes());
+                info!("{:#?}", connector.modes());
                 info!("New output connected, name: {}", name);
-                let drm_mode = if CONFIG.outputs.contains_key(&name) {
-                    let output_config = &CONFIG.outputs[&name];
-                    *connector
+                let output_config = CONFIG.outputs.get(&name);
+                let preferred_mode = *connector
+                    .modes()
+                    .iter()
+                    .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+                    .unwrap_or(&connector.modes()[0]);
+                let drm_mode = match output_config.and_then(|c| c.mode_size().map(|s| (c, s))) {
+                    Some((output_config, mode_size)) => connector
                         .modes()
                         .iter()
                         .filter(|mode| {
                             let (x, y) = mode.size();
-                            Size::from((x as i32, y as i32)) == output_config.mode_size()
+                            Size::from((x as i32, y as i32)) == mode_size
                         })
                         // and then select the closest refresh rate (e.g. to match 59.98 as 60)
                         .min_by_key(|mode| {
@@ -417,13 +648,15 @@ impl MagmaState<UdevData> {
                             info!("refresh: {}", refresh_rate);
                             (output_config.mode_refresh() as i32 - refresh_rate).abs()
                         })
-                        .expect("No matching mode found for output config")
-                } else {
-                    *connector
-                        .modes()
-                        .iter()
-                        .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
-                        .unwrap_or(&connector.modes()[0])
+                        .copied()
+                        .unwrap_or_else(|| {
+                            warn!(
+                                "No mode of {} matches its config, using the preferred one",
+                                name
+                            );
+                            preferred_mode
+                        }),
+                    None => preferred_mode,
                 };
 
                 let drm_surface = device
@@ -435,6 +668,29 @@ impl MagmaState<UdevData> {
                     .map(|info| (info.manufacturer, info.model))
                     .unwrap_or_else(|| ("Unknown".into(), "Unknown".into()));
 
+                let scale = match output_config.and_then(|c| c.scale()) {
+                    Some(scale) if scale.fract() == 0.0 => {
+                        smithay::output::Scale::Integer(scale as i32)
+                    }
+                    Some(scale) => smithay::output::Scale::Fractional(scale),
+                    None => smithay::output::Scale::Integer(1),
+                };
+
+                let transform = output_config
+                    .map(|c| Transform::from(c.transform))
+                    .unwrap_or(Transform::Normal);
+                // outputs without a configured position are lined up from left to right
+                let position = output_config.and_then(|c| c.position).unwrap_or_else(|| {
+                    let x = self
+                        .workspaces
+                        .outputs()
+                        .filter_map(|o| self.workspaces.output_geometry(o))
+                        .map(|geo| geo.loc.x + geo.size.w)
+                        .max()
+                        .unwrap_or(0);
+                    (x, 0)
+                });
+
                 let (w, h) = connector.size().unwrap_or((0, 0));
                 let output = Output::new(
                     name,
@@ -450,9 +706,9 @@ impl MagmaState<UdevData> {
                 output.set_preferred(output_mode);
                 output.change_current_state(
                     Some(output_mode),
-                    Some(Transform::Normal),
-                    Some(smithay::output::Scale::Integer(1)),
-                    None,
+                    Some(transform),
+                    Some(scale),
+                    Some(position.into()),
                 );
                 let render_formats = renderer
                     .as_mut()
@@ -518,6 +774,8 @@ impl MagmaState<UdevData> {
                 )
                 .unwrap();
 
+                let wallpaper_texture = load_wallpaper(&mut renderer, &output.name());
+
                 let surface = Surface {
                     _device_id: node,
                     _render_node: device.render_node,
@@ -525,11 +783,12 @@ impl MagmaState<UdevData> {
                     compositor,
                     output: output.clone(),
                     pointer_texture,
+                    cursor_images: Vec::new(),
+                    wallpaper_texture,
                 };
 
-                for workspace in self.workspaces.iter() {
-                    workspace.add_output(output.clone())
-                }
+                // windows mapped while there were no outputs are laid out once their workspace is shown
+                self.workspaces.add_output(output.clone());
 
                 device.surfaces.insert(crtc, surface);
 
@@ -538,7 +797,12 @@ impl MagmaState<UdevData> {
             DrmScanEvent::Disconnected {
                 crtc: Some(crtc), ..
             } => {
-                device.surfaces.remove(&crtc);
+                if let Some(surface) = device.surfaces.remove(&crtc) {
+                    info!("Output disconnected, name: {}", surface.output.name());
+                    self.dh
+                        .disable_global::<MagmaState<UdevData>>(surface.global);
+                    self.remove_output(&surface.output);
+                }
             }
             _ => {}
         }
@@ -552,6 +816,10 @@ impl MagmaState<UdevData> {
         crtc: crtc::Handle,
         screencopy: Option<Screencopy>,
     ) -> Result<bool, SwapBuffersError> {
+        // nothing is shown while the outputs are off, rendering resumes once they are turned on
+        if self.backend_data.outputs_off {
+            return Ok(false);
+        }
         let device = self.backend_data.devices.get_mut(&node).unwrap();
         let surface = device.surfaces.get_mut(&crtc).unwrap();
         let mut renderer = self
@@ -559,28 +827,146 @@ impl MagmaState<UdevData> {
             .gpus
             .single_renderer(&device.render_node)
             .unwrap();
-        let output = self.workspaces.current().outputs().next().unwrap();
+        let output = &surface.output.clone();
 
         let mut renderelements: Vec<CustomRenderElements<MultiRenderer<_, _>>> = vec![];
+        let is_screencopy = screencopy.is_some();
         let render_cursor = if let Some(screencopy) = &screencopy {
             screencopy.overlay_cursor
         } else {
             true
         };
-        if render_cursor {
-            renderelements.append(&mut vec![
-                CustomRenderElements::<MultiRenderer<_, _>>::from(
-                    TextureRenderElement::from_texture_buffer(
-                        self.pointer_location.to_physical(Scale::from(1.0)),
-                        &surface.pointer_texture,
-                        None,
-                        None,
-                        None,
-                    ),
-                ),
-            ]);
+        // the cursor texture is sized in logical pixels, so it only has to be
+        // placed according to the scale of the output it is drawn on
+        let output_scale = Scale::from(surface.output.current_scale().fractional_scale());
+        let output_loc = self
+            .workspaces
+            .output_geometry(&surface.output)
+            .map(|geo| geo.loc)
+            .unwrap_or_default();
+        let cursor_pos = self.pointer_location - output_loc.to_f64();
+        // the cursor surface may be gone without the client setting a new one
+        if let CursorImageStatus::Surface(cursor_surface) = &self.cursor_status {
+            if !cursor_surface.alive() {
+                self.cursor_status = CursorImageStatus::Default;
+            }
+        }
+        // every seat has a pointer of its own
+        let pointers = if render_cursor {
+            self.pointers()
+        } else {
+            Vec::new()
+        };
+        for (pointer_location, cursor_status) in &pointers {
+            let cursor_pos = *pointer_location - output_loc.to_f64();
+            match cursor_status {
+                CursorImageStatus::Surface(cursor_surface) => {
+                    let hotspot = with_states(cursor_surface, |states| {
+                        states
+                            .data_map
+                            .get::<Mutex<CursorImageAttributes>>()
+                            .unwrap()
+                            .lock()
+                            .unwrap()
+                            .hotspot
+                    });
+                    renderelements.extend(
+                        render_elements_from_surface_tree(
+                            &mut renderer,
+                            cursor_surface,
+                            (cursor_pos - hotspot.to_f64())
+                                .to_physical(output_scale)
+                                .to_i32_round(),
+                            output_scale,
+                        )
+                        .into_iter()
+                        .map(CustomRenderElements::Surface),
+                    );
+                }
+                CursorImageStatus::Hidden => {}
+                CursorImageStatus::Default => {
+                    let scale = surface.output.current_scale().integer_scale();
+                    let millis = self.start_time.elapsed().as_millis() as u32;
+                    let (texture, hotspot) = match &self.backend_data.cursor {
+                        Some(cursor) => {
+                            let (index, image) = cursor.image(scale as u32, millis);
+                            let hotspot = Point::<i32, Logical>::from((
+                                image.xhot as i32 / scale,
+                                image.yhot as i32 / scale,
+                            ));
+                            let cached = surface
+                                .cursor_images
+                                .iter()
+                                .find(|(cached, _)| *cached == index)
+                                .map(|(_, texture)| texture.clone());
+                            let texture = match cached {
+                                Some(texture) => texture,
+                                None => {
+                                    let texture = TextureBuffer::from_memory(
+                                        &mut renderer,
+                                        &image.pixels_rgba,
+                                        Fourcc::Abgr8888,
+                                        (image.width as i32, image.height as i32),
+                                        false,
+                                        scale,
+                                        Transform::Normal,
+                                        None,
+                                    )
+                                    .unwrap();
+                                    surface.cursor_images.push((index, texture.clone()));
+                                    texture
+                                }
+                            };
+                            (texture, hotspot)
+                        }
+                        None => (surface.pointer_texture.clone(), Point::default()),
+                    };
+                    renderelements.push(CustomRenderElements::<MultiRenderer<_, _>>::from(
+                        TextureRenderElement::from_texture_buffer(
+                            (cursor_pos - hotspot.to_f64()).to_physical(output_scale),
+                            &texture,
+                            None,
+                            None,
+                            None,
+                        ),
+                    ));
+                }
+            }
+        }
+
+        // the drag icon follows the pointer below the cursor
+        if let Some(icon) = self.dnd_icon.as_ref().filter(|icon| icon.alive()) {
+            renderelements.extend(
+                render_elements_from_surface_tree(
+                    &mut renderer,
+                    icon,
+                    cursor_pos.to_physical(output_scale).to_i32_round(),
+                    output_scale,
+                )
+                .into_iter()
+                .map(CustomRenderElements::Surface),
+            );
         }
 
+        if self.quit_requested.is_some() {
+            renderelements.extend(self.quit_message.render_elements(&mut renderer, output));
+        }
+        if self.passthrough {
+            renderelements.extend(self.passthrough_frame.render_elements(
+                output,
+                PASSTHROUGH_FRAME_WIDTH,
+                CONFIG.passthrough_frame,
+            ));
+        }
+
+        let workspace = self
+            .workspaces
+            .output_workspace(output)
+            .and_then(|id| self.workspaces.get(id));
+        // everything below a fullscreen window is hidden by it, so its buffer can be scanned
+        // out directly if nothing is drawn on top and the plane supports its format
+        let fullscreen = workspace.and_then(|ws| ws.fullscreen_window()).is_some();
+
         let layer_map = layer_map_for_output(output);
         let (lower, upper): (Vec<&LayerSurface>, Vec<&LayerSurface>) = layer_map
             .layers()
@@ -601,19 +987,23 @@ impl MagmaState<UdevData> {
                     >(
                         surface,
                         &mut renderer,
-                        loc.to_physical_precise_round(1),
-                        Scale::from(1.0),
+                        loc.to_physical_precise_round(output_scale),
+                        output_scale,
                     )
                     .into_iter()
                     .map(CustomRenderElements::Surface)
                 }),
         );
 
-        renderelements.extend(self.workspaces.current().render_elements(&mut renderer));
+        let focused = self.focused_window();
+        if let Some(workspace) = workspace {
+            renderelements.extend(workspace.render_elements(&mut renderer, focused.as_ref()));
+        }
 
         renderelements.extend(
             lower
                 .into_iter()
+                .filter(|_| !fullscreen)
                 .filter_map(|surface| {
                     layer_map
                         .layer_geometry(surface)
@@ -625,18 +1015,47 @@ impl MagmaState<UdevData> {
                     >(
                         surface,
                         &mut renderer,
-                        loc.to_physical_precise_round(1),
-                        Scale::from(1.0),
+                        loc.to_physical_precise_round(output_scale),
+                        output_scale,
                     )
                     .into_iter()
                     .map(CustomRenderElements::Surface)
                 }),
         );
 
+        if let Some(wallpaper) = surface.wallpaper_texture.as_ref().filter(|_| !fullscreen) {
+            // stretched over the whole output, below the background layer
+            let output_size = output
+                .current_mode()
+                .map(|mode| {
+                    mode.size
+                        .to_f64()
+                        .to_logical(output.current_scale().fractional_scale())
+                        .to_i32_round()
+                })
+                .unwrap_or_default();
+            renderelements.push(CustomRenderElements::from(
+                TextureRenderElement::from_texture_buffer(
+                    (0.0, 0.0),
+                    wallpaper,
+                    None,
+                    None,
+                    Some(output_size),
+                ),
+            ));
+        }
+
+        if CONFIG.debug_full_redraw {
+            // buffers without an age are redrawn completely
+            surface.compositor.reset_buffers();
+        }
+        let render_start = Instant::now();
         let frame_result = surface
             .compositor
             .render_frame::<_, _, GlesTexture>(&mut renderer, &renderelements, [0.1, 0.1, 0.1, 1.0])
             .unwrap();
+        let render_time = render_start.elapsed();
+        update_primary_scanout_output(output, workspace, &frame_result.states);
 
         // Copy framebuffer for screencopy.
         if let Some(mut screencopy) = screencopy {
@@ -710,15 +1129,23 @@ impl MagmaState<UdevData> {
         let rendered = frame_result.damage.is_some();
         let mut result = Ok(rendered);
         if rendered {
+            let feedback = take_presentation_feedback(output, workspace, &frame_result.states);
             let queueresult = surface
                 .compositor
-                .queue_frame(())
+                .queue_frame(Some(feedback))
                 .map_err(Into::<SwapBuffersError>::into);
             if let Err(queueresult) = queueresult {
                 result = Err(queueresult);
             }
         }
 
+        let frame_stats = self.frame_stats.entry(surface.output.name()).or_default();
+        match &result {
+            Ok(true) => frame_stats.record_frame(render_time),
+            Ok(false) => {}
+            Err(_) => frame_stats.record_dropped_frame(),
+        }
+
         let reschedule = match &result {
             Ok(has_rendered) => !has_rendered,
             Err(err) => {
@@ -765,14 +1192,50 @@ impl MagmaState<UdevData> {
                 .expect("failed to schedule frame timer");
         }
 
-        self.workspaces.current().windows().for_each(|window| {
-            window.send_frame(
+        for (_, cursor_status) in self.pointers() {
+            let CursorImageStatus::Surface(cursor_surface) = cursor_status else {
+                continue;
+            };
+            send_frames_surface_tree(
+                &cursor_surface,
                 output,
                 self.start_time.elapsed(),
                 Some(Duration::ZERO),
                 |_, _| Some(output.clone()),
             );
-        });
+        }
+        if let Some(icon) = &self.dnd_icon {
+            send_frames_surface_tree(
+                icon,
+                output,
+                self.start_time.elapsed(),
+                Some(Duration::ZERO),
+                |_, _| Some(output.clone()),
+            );
+        }
+
+        // screencopy renders in between the frames of the output, which already send them
+        if !is_screencopy {
+            send_frames(output, workspace, self.start_time.elapsed());
+        }
+
+        // surfaces render at the scale of the output they were last shown on
+        let scale = output.current_scale().fractional_scale();
+        workspace
+            .into_iter()
+            .flat_map(|ws| ws.windows())
+            .for_each(|window| {
+                window.with_surfaces(|_, states| {
+                    with_fractional_scale(states, |fractional| {
+                        fractional.set_preferred_scale(scale)
+                    });
+                });
+            });
+        for layer in layer_map_for_output(output).layers() {
+            layer.with_surfaces(|_, states| {
+                with_fractional_scale(states, |fractional| fractional.set_preferred_scale(scale));
+            });
+        }
         result
     }
 }
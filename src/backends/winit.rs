@@ -1,27 +1,32 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use smithay::{
     backend::{
         renderer::{
             damage::OutputDamageTracker,
-            element::{surface::WaylandSurfaceRenderElement, AsRenderElements},
+            element::{
+                surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+                AsRenderElements,
+            },
             gles::GlesRenderer,
         },
         winit::{self, WinitError, WinitEvent, WinitEventLoop, WinitGraphicsBackend},
     },
-    desktop::{layer_map_for_output, space::SpaceElement, LayerSurface},
+    desktop::{
+        layer_map_for_output, space::SpaceElement, utils::send_frames_surface_tree, LayerSurface,
+    },
     output::{Mode, Output, PhysicalProperties, Subpixel},
     reexports::{
         calloop::{
             timer::{TimeoutAction, Timer},
             EventLoop,
         },
-        wayland_server::Display,
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::{Display, Resource},
     },
-    utils::{Rectangle, Scale, Transform},
+    utils::{Scale, Transform},
     wayland::shell::wlr_layer::Layer,
 };
-use tracing::info;
 
 pub struct WinitData {
     backend: WinitGraphicsBackend<GlesRenderer>,
@@ -33,7 +38,13 @@ impl Backend for WinitData {
         "winit".to_string()
     }
 }
-use crate::state::{Backend, CalloopData, MagmaState, CONFIG};
+use crate::{
+    state::{Backend, CalloopData, MagmaState, CONFIG},
+    utils::render::{
+        refresh_interval, send_frames, take_presentation_feedback, update_primary_scanout_output,
+        CustomRenderElements, PASSTHROUGH_FRAME_WIDTH,
+    },
+};
 
 pub fn init_winit() {
     let mut event_loop: EventLoop<CalloopData<WinitData>> = EventLoop::try_new().unwrap();
@@ -82,10 +93,8 @@ pub fn init_winit() {
 
     let state = &mut data.state;
 
-    // map output to every workspace
-    for workspace in state.workspaces.iter() {
-        workspace.add_output(output.clone());
-    }
+    // the output shows the current workspace
+    state.workspaces.add_output(output.clone());
 
     std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
 
@@ -102,18 +111,12 @@ pub fn init_winit() {
         .unwrap();
 
     for command in &CONFIG.autostart {
-        if let Err(err) = std::process::Command::new("/bin/sh")
-            .arg("-c")
-            .arg(command)
-            .spawn()
-        {
-            info!("{} {} {}", err, "Failed to spawn \"{}\"", command);
-        }
+        state.spawn(command, None);
     }
 
     event_loop
-        .run(None, &mut data, move |_| {
-            // Magma is running
+        .run(None, &mut data, move |data| {
+            data.state.send_ipc_events();
         })
         .unwrap();
 }
@@ -138,11 +141,14 @@ pub fn winit_dispatch(
                 None,
                 None,
             );
+            // the buffers of the old size don't hold anything worth keeping
+            *full_redraw = 4;
         }
         WinitEvent::Input(event) => state.process_input_event(event),
         _ => (),
     });
 
+    let focused = state.focused_window();
     let winitdata = &mut state.backend_data;
 
     if let Err(WinitError::WindowClosed) = res {
@@ -153,16 +159,47 @@ pub fn winit_dispatch(
     }
 
     *full_redraw = full_redraw.saturating_sub(1);
-
-    let size = winitdata.backend.window_size().physical_size;
-    let damage = Rectangle::from_loc_and_size((0, 0), size);
+    // only the damage since the current buffer was last shown is redrawn, unless its age is unknown
+    let age = if *full_redraw > 0 || CONFIG.debug_full_redraw {
+        0
+    } else {
+        winitdata.backend.buffer_age().unwrap_or(0)
+    };
 
     winitdata.backend.bind().unwrap();
 
-    let mut renderelements: Vec<WaylandSurfaceRenderElement<_>> = vec![];
+    let mut renderelements: Vec<CustomRenderElements<GlesRenderer>> = vec![];
+
+    // the drag icon follows the pointer, the cursor itself is drawn by the host
+    if let Some(icon) = state.dnd_icon.as_ref().filter(|icon| icon.alive()) {
+        renderelements.extend(
+            render_elements_from_surface_tree(
+                winitdata.backend.renderer(),
+                icon,
+                state.pointer_location.to_physical(1.0).to_i32_round(),
+                Scale::from(1.0),
+            )
+            .into_iter()
+            .map(CustomRenderElements::Surface),
+        );
+    }
 
     let workspace = state.workspaces.current_mut();
     let output = workspace.outputs().next().unwrap();
+    if state.quit_requested.is_some() {
+        renderelements.extend(
+            state
+                .quit_message
+                .render_elements(winitdata.backend.renderer(), output),
+        );
+    }
+    if state.passthrough {
+        renderelements.extend(state.passthrough_frame.render_elements(
+            output,
+            PASSTHROUGH_FRAME_WIDTH,
+            CONFIG.passthrough_frame,
+        ));
+    }
     let layer_map = layer_map_for_output(output);
     let (lower, upper): (Vec<&LayerSurface>, Vec<&LayerSurface>) = layer_map
         .layers()
@@ -184,10 +221,13 @@ pub fn winit_dispatch(
                     loc.to_physical_precise_round(1),
                     Scale::from(1.0),
                 )
+                .into_iter()
+                .map(CustomRenderElements::Surface)
             }),
     );
 
-    renderelements.extend(workspace.render_elements(winitdata.backend.renderer()));
+    renderelements
+        .extend(workspace.render_elements(winitdata.backend.renderer(), focused.as_ref()));
 
     renderelements.extend(
         lower
@@ -204,29 +244,54 @@ pub fn winit_dispatch(
                     loc.to_physical_precise_round(1),
                     Scale::from(1.0),
                 )
+                .into_iter()
+                .map(CustomRenderElements::Surface)
             }),
     );
 
-    winitdata
+    let render_start = Instant::now();
+    let render_result = winitdata
         .damage_tracker
         .render_output(
             winitdata.backend.renderer(),
-            0,
+            age,
             &renderelements,
             [0.1, 0.1, 0.1, 1.0],
         )
         .unwrap();
 
-    winitdata.backend.submit(Some(&[damage])).unwrap();
+    update_primary_scanout_output(output, Some(&*workspace), &render_result.states);
+
+    // without damage the frame is skipped, the previous one is still shown
+    if let Some(damage) = &render_result.damage {
+        state
+            .frame_stats
+            .entry(output.name())
+            .or_default()
+            .record_frame(render_start.elapsed());
 
-    workspace.windows().for_each(|window| {
-        window.send_frame(
+        winitdata.backend.submit(Some(&damage[..])).unwrap();
+
+        // there is no vblank to wait for, the frame counts as presented once it is submitted
+        take_presentation_feedback(output, Some(&*workspace), &render_result.states).presented(
+            state.clock.now(),
+            refresh_interval(output),
+            0,
+            wp_presentation_feedback::Kind::Vsync,
+        );
+    }
+
+    send_frames(output, Some(&*workspace), state.start_time.elapsed());
+
+    if let Some(icon) = &state.dnd_icon {
+        send_frames_surface_tree(
+            icon,
             output,
             state.start_time.elapsed(),
             Some(Duration::ZERO),
             |_, _| Some(output.clone()),
-        )
-    });
+        );
+    }
 
     workspace.windows().for_each(|e| e.refresh());
     display.flush_clients().unwrap();
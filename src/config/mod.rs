@@ -1,42 +1,523 @@
-use std::{collections::HashMap, fs::OpenOptions};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
 
-use self::types::{deserialize_KeyModifiers, deserialize_Keysym, XkbConfig};
+use self::types::{
+    deserialize_KeyModifiers, deserialize_Keysym, deserialize_Outputs, deserialize_Regex, XkbConfig,
+};
+use regex::Regex;
 use serde::Deserialize;
-use smithay::utils::{Physical, Size};
+use smithay::utils::{Physical, Size, Transform};
+use tracing::{debug, info, warn};
 
 mod types;
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    /// Workspaces that always exist, others are created on demand and removed once empty
     pub workspaces: u8,
     pub keybindings: HashMap<KeyPattern, Action>,
 
+    /// Actions triggered by pointer buttons, the buttons are not sent to clients.
+    /// Bind `MoveGrab` and `ResizeGrab` here to drag and resize windows with the pointer.
+    #[serde(default = "default_mousebindings")]
+    pub mousebindings: HashMap<ButtonPattern, Action>,
+
     #[serde(default = "default_gaps")]
     pub gaps: (i32, i32),
 
+    /// Tiling layout of workspaces without their own
+    #[serde(default)]
+    pub layout: TilingLayout,
+
+    /// Most windows stacked in one column per layout, further windows open a new column.
+    /// Layouts without an entry, or with 0, stack any number of windows.
+    #[serde(default = "default_column_capacity")]
+    pub column_capacity: HashMap<TilingLayout, usize>,
+
+    /// Settings overriding the global defaults for single workspaces, keyed by workspace id
+    #[serde(default = "default_workspace_defaults")]
+    pub workspace_defaults: HashMap<u8, WorkspaceConfig>,
+
     #[serde(default = "default_xkb")]
     pub xkb: XkbConfig,
 
+    /// Milliseconds a key has to be held before it starts repeating
+    #[serde(default = "default_repeat_delay")]
+    pub repeat_delay: i32,
+
+    /// Key repeats per second
+    #[serde(default = "default_repeat_rate")]
+    pub repeat_rate: i32,
+
     #[serde(default = "default_autostart")]
-    pub autostart: Vec<String>,
+    pub autostart: Vec<SpawnCommand>,
+
+    /// Workspaces windows are opened on, keyed by app id
+    #[serde(default = "default_workspace_rules")]
+    pub workspace_rules: HashMap<String, WorkspaceRule>,
+
+    /// Rules applied to windows when they are mapped, in order
+    #[serde(default = "default_window_rules")]
+    pub window_rules: Vec<WindowRule>,
 
-    #[serde(default = "default_outputs")]
+    /// Output layout and modes, keyed by connector name, also accepting the older tuple form
+    #[serde(default = "default_outputs", deserialize_with = "deserialize_Outputs")]
     pub outputs: HashMap<String, OutputConfig>,
+
+    /// Additional seats, mapping a seat name to the names of its input devices
+    #[serde(default = "default_seats")]
+    pub seats: HashMap<String, Vec<String>>,
+
+    /// Programs, by process name, allowed to type and click through virtual keyboards and
+    /// pointers, e.g. `wtype` or `wayvnc`
+    #[serde(default = "default_virtual_input_clients")]
+    pub virtual_input_clients: Vec<String>,
+
+    /// Whether surfaces get focused by moving the pointer over them or by clicking them
+    #[serde(default)]
+    pub focus_mode: FocusMode,
+
+    /// Milliseconds the pointer has to rest on a surface before it gets focused
+    #[serde(default = "default_focus_delay")]
+    pub focus_delay: u64,
+
+    /// Milliseconds during which the same spawn command is not started again
+    #[serde(default = "default_spawn_cooldown")]
+    pub spawn_cooldown: u64,
+
+    /// Windows whose max size is below this size float instead of being tiled
+    #[serde(default = "default_float_below")]
+    pub float_below: (i32, i32),
+
+    /// Float windows that can't be resized, i.e. with a min size equal to their max size
+    #[serde(default = "default_float_fixed_size")]
+    pub float_fixed_size: bool,
+
+    /// Actions triggered by pressing and releasing a modifier on its own, delayed by
+    /// `double_tap_interval` for modifiers that also have a double tap action
+    #[serde(default = "default_modifier_taps")]
+    pub modifier_taps: HashMap<KeyModifier, Action>,
+
+    /// Actions triggered by tapping a modifier twice within `double_tap_interval` milliseconds
+    #[serde(default = "default_modifier_taps")]
+    pub modifier_double_taps: HashMap<KeyModifier, Action>,
+
+    #[serde(default = "default_double_tap_interval")]
+    pub double_tap_interval: u64,
+
+    /// Actions triggered by moving the pointer into a corner of an output
+    #[serde(default = "default_hot_corners")]
+    pub hot_corners: HashMap<Corner, Action>,
+
+    /// Actions triggered by touchpad gestures, these gestures are not sent to clients
+    #[serde(default = "default_gestures")]
+    pub gestures: HashMap<Gesture, Action>,
+
+    /// Raise floating windows above the other floating windows when clicked
+    #[serde(default = "default_raise_on_click")]
+    pub raise_on_click: bool,
+
+    /// Require Quit to be triggered twice within `quit_confirm_timeout` milliseconds
+    #[serde(default = "default_confirm_quit")]
+    pub confirm_quit: bool,
+
+    #[serde(default = "default_quit_confirm_timeout")]
+    pub quit_confirm_timeout: u64,
+
+    /// Resizing floating windows by scrolling while holding `modifiers`, off unless set so
+    /// clients keep receiving those scroll events
+    #[serde(default = "default_scroll_resize")]
+    pub scroll_resize: Option<ScrollResizeConfig>,
+
+    /// Seconds without input after which the session is idle, 0 disables idling
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+
+    /// Seconds without input after which the outputs are turned off, 0 keeps them on
+    #[serde(default = "default_dpms_timeout")]
+    pub dpms_timeout: u64,
+
+    /// Command run when the session becomes idle, e.g. a screen locker
+    #[serde(default = "default_idle_command")]
+    pub idle_command: Option<SpawnCommand>,
+
+    /// File the state snapshot of the Debug action is written to, in addition to the log
+    #[serde(default = "default_debug_dump_path")]
+    pub debug_dump_path: Option<String>,
+
+    /// Redraw whole outputs every frame instead of only their damaged regions
+    #[serde(default = "default_debug_full_redraw")]
+    pub debug_full_redraw: bool,
+
+    /// Opacity of the black overlay drawn over unfocused windows, 0 disables dimming
+    #[serde(default = "default_inactive_dim")]
+    pub inactive_dim: f32,
+
+    #[serde(default)]
+    pub border: BorderConfig,
+
+    /// RGBA color of the frame drawn along the edges of the outputs while keyboard
+    /// passthrough is active
+    #[serde(default = "default_passthrough_frame")]
+    pub passthrough_frame: [f32; 4],
+
+    /// Title bars of windows using server side decorations, which are left undecorated if unset
+    #[serde(default = "default_title_bar")]
+    pub title_bar: Option<TitleBarConfig>,
+
+    /// XCursor theme of the pointer, `XCURSOR_THEME` is used if unset
+    #[serde(default = "default_cursor_theme")]
+    pub cursor_theme: Option<String>,
+
+    /// Nominal size of the pointer, `XCURSOR_SIZE` is used if unset
+    #[serde(default = "default_cursor_size")]
+    pub cursor_size: Option<u32>,
+
+    /// Image drawn behind all windows on outputs without an entry in `wallpapers`
+    #[serde(default = "default_wallpaper")]
+    pub wallpaper: Option<String>,
+
+    /// Per output wallpapers, keyed by connector name
+    #[serde(default = "default_wallpapers")]
+    pub wallpapers: HashMap<String, String>,
+
+    #[serde(default)]
+    pub pointer: PointerConfig,
+
+    #[serde(default)]
+    pub scroll: ScrollConfig,
+
+    #[serde(default)]
+    pub touchpad: TouchpadConfig,
+}
+
+/// Resizing floating windows by scrolling over them while holding `modifiers`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScrollResizeConfig {
+    #[serde(deserialize_with = "deserialize_KeyModifiers")]
+    pub modifiers: KeyModifiers,
+    /// Amount of pixels added or removed per scroll step
+    pub step: i32,
+}
+
+/// Borders drawn around tiled windows.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BorderConfig {
+    /// Width in pixels, added to the inner gaps, 0 disables borders
+    #[serde(default)]
+    pub width: i32,
+    /// RGBA color of the border of the focused window
+    #[serde(default = "default_border_active")]
+    pub active: [f32; 4],
+    /// RGBA color of the borders of all other windows
+    #[serde(default = "default_border_inactive")]
+    pub inactive: [f32; 4],
 }
 
+impl Default for BorderConfig {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            active: default_border_active(),
+            inactive: default_border_inactive(),
+        }
+    }
+}
+
+/// Title bars drawn above windows that asked for server side decorations,
+/// with a close and a maximize button at their right end.
 #[derive(Debug, Deserialize, Clone)]
-pub struct OutputConfig((i32, i32), Option<u32>);
+pub struct TitleBarConfig {
+    #[serde(default = "default_title_bar_height")]
+    pub height: i32,
+    /// RGBA color of the title bar of the focused window
+    #[serde(default = "default_border_active")]
+    pub active: [f32; 4],
+    /// RGBA color of the title bars of all other windows
+    #[serde(default = "default_border_inactive")]
+    pub inactive: [f32; 4],
+    #[serde(default = "default_close_button")]
+    pub close_button: [f32; 4],
+    #[serde(default = "default_maximize_button")]
+    pub maximize_button: [f32; 4],
+    /// Clicking a title bar twice within this many milliseconds toggles maximizing its window
+    #[serde(default = "default_double_click_interval")]
+    pub double_click_interval: u64,
+    /// RGBA color of the window titles
+    #[serde(default = "default_title_text")]
+    pub text: [f32; 4],
+    /// Path of the font of the window titles, the sans serif font of fontconfig if unset.
+    /// Only read on startup.
+    #[serde(default = "default_title_font")]
+    pub font: Option<String>,
+}
+
+/// Acceleration of pointer devices, applied by libinput on the udev backend.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PointerConfig {
+    #[serde(default)]
+    pub accel_profile: AccelProfile,
+    /// Acceleration speed from -1.0 (slowest) to 1.0 (fastest)
+    #[serde(default)]
+    pub accel_speed: f64,
+    /// Factor applied to every relative pointer motion after acceleration
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f64,
+}
+
+impl Default for PointerConfig {
+    fn default() -> Self {
+        Self {
+            accel_profile: AccelProfile::default(),
+            accel_speed: 0.0,
+            sensitivity: default_sensitivity(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum AccelProfile {
+    /// Constant factor between device and pointer motion
+    Flat,
+    /// Faster device motion moves the pointer further
+    #[default]
+    Adaptive,
+}
+
+/// Scrolling of all pointer devices, `method` is applied by libinput on the udev backend.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScrollConfig {
+    /// Invert the scroll direction of touchpads, so the content follows the fingers
+    #[serde(default)]
+    pub natural_scroll: bool,
+    /// Factor applied to every scroll distance
+    #[serde(default = "default_scroll_factor")]
+    pub factor: f64,
+    /// How touchpads scroll
+    #[serde(default)]
+    pub method: ScrollMethod,
+}
+
+impl Default for ScrollConfig {
+    fn default() -> Self {
+        Self {
+            natural_scroll: false,
+            factor: default_scroll_factor(),
+            method: ScrollMethod::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ScrollMethod {
+    #[default]
+    TwoFinger,
+    Edge,
+}
+
+/// Settings of touchpads, applied by libinput on the udev backend.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TouchpadConfig {
+    /// Tapping with one, two or three fingers clicks the left, right or middle button
+    #[serde(default = "default_tap_to_click")]
+    pub tap_to_click: bool,
+    /// Ignore touchpads for a short time after a key was pressed
+    #[serde(default = "default_disable_while_typing")]
+    pub disable_while_typing: bool,
+    /// How clicks of touchpads without separate buttons are mapped to buttons,
+    /// the device default is used if unset
+    #[serde(default)]
+    pub click_method: Option<ClickMethod>,
+    /// Ignore touchpads while an external mouse is connected
+    #[serde(default)]
+    pub disable_on_external_mouse: bool,
+}
+
+impl Default for TouchpadConfig {
+    fn default() -> Self {
+        Self {
+            tap_to_click: default_tap_to_click(),
+            disable_while_typing: default_disable_while_typing(),
+            click_method: None,
+            disable_on_external_mouse: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ClickMethod {
+    /// The bottom area of the touchpad is split into left, middle and right buttons
+    ButtonAreas,
+    /// Clicking with one, two or three fingers clicks the left, right or middle button
+    ClickFinger,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct WorkspaceConfig {
+    #[serde(default)]
+    pub gaps: Option<(i32, i32)>,
+    #[serde(default)]
+    pub layout: Option<TilingLayout>,
+}
+
+impl Config {
+    /// The keyboard repeat delay and rate, replacing values that are not positive
+    /// with the defaults.
+    pub fn repeat_info(&self) -> (i32, i32) {
+        let delay = if self.repeat_delay > 0 {
+            self.repeat_delay
+        } else {
+            warn!("repeat_delay has to be positive, using the default");
+            default_repeat_delay()
+        };
+        let rate = if self.repeat_rate > 0 {
+            self.repeat_rate
+        } else {
+            warn!("repeat_rate has to be positive, using the default");
+            default_repeat_rate()
+        };
+        (delay, rate)
+    }
+
+    /// Gaps of workspace `id`, from `workspace_defaults` or the global ones.
+    pub fn workspace_gaps(&self, id: u8) -> (i32, i32) {
+        self.workspace_defaults
+            .get(&id)
+            .and_then(|defaults| defaults.gaps)
+            .unwrap_or(self.gaps)
+    }
+
+    /// Layout of workspace `id`, from `workspace_defaults` or the global one.
+    pub fn workspace_layout(&self, id: u8) -> TilingLayout {
+        self.workspace_defaults
+            .get(&id)
+            .and_then(|defaults| defaults.layout)
+            .unwrap_or(self.layout)
+    }
+
+    /// Most windows in one column of `layout`, if limited.
+    pub fn column_capacity(&self, layout: TilingLayout) -> Option<usize> {
+        self.column_capacity
+            .get(&layout)
+            .copied()
+            .filter(|&capacity| capacity > 0)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WorkspaceRule {
+    pub workspace: u8,
+    /// Switch to the workspace when the window opens
+    #[serde(default)]
+    pub switch: bool,
+}
+
+/// Settings for new windows whose app id and title match the given regular expressions.
+/// A pattern has to match the whole app id or title, unset patterns match any window.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WindowRule {
+    #[serde(default, deserialize_with = "deserialize_Regex")]
+    pub app_id: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_Regex")]
+    pub title: Option<Regex>,
+    /// Float or tile the window regardless of its size hints
+    #[serde(default)]
+    pub floating: Option<bool>,
+    #[serde(default)]
+    pub workspace: Option<u8>,
+    /// Initial size of floating windows
+    #[serde(default)]
+    pub size: Option<(i32, i32)>,
+    /// Initial location of floating windows, relative to the usable area of the output
+    #[serde(default)]
+    pub position: Option<(i32, i32)>,
+}
+
+impl WindowRule {
+    pub fn matches(&self, app_id: Option<&str>, title: Option<&str>) -> bool {
+        let matches = |pattern: &Option<Regex>, value: Option<&str>| match pattern {
+            Some(pattern) => value.is_some_and(|value| pattern.is_match(value)),
+            None => true,
+        };
+        matches(&self.app_id, app_id) && matches(&self.title, title)
+    }
+}
+
+/// Mode, placement and scale of an output, keyed by connector name in `outputs`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutputConfig {
+    /// Location of the top left corner in the global space,
+    /// outputs without one are placed right of the others
+    #[serde(default)]
+    pub position: Option<(i32, i32)>,
+    /// Mode size, the preferred mode of the output is used if unset
+    #[serde(default)]
+    pub resolution: Option<(i32, i32)>,
+    /// Refresh rate in mHz, the closest one available for the resolution is picked
+    #[serde(default)]
+    pub refresh: Option<u32>,
+    /// Scale factor, which may be fractional
+    #[serde(default)]
+    pub scale: Option<f64>,
+    #[serde(default)]
+    pub transform: OutputTransform,
+}
 
 impl OutputConfig {
-    pub fn mode_size(&self) -> Size<i32, Physical> {
-        self.0.into()
+    pub fn mode_size(&self) -> Option<Size<i32, Physical>> {
+        self.resolution.map(Size::from)
     }
 
     pub fn mode_refresh(&self) -> u32 {
-        self.1.unwrap_or(60_000)
+        self.refresh.unwrap_or(60_000)
+    }
+
+    pub fn scale(&self) -> Option<f64> {
+        self.scale.filter(|scale| *scale > 0.0)
+    }
+}
+
+/// Rotation of an output counter-clockwise, optionally flipped around the vertical axis first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum OutputTransform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl From<OutputTransform> for Transform {
+    fn from(transform: OutputTransform) -> Self {
+        match transform {
+            OutputTransform::Normal => Transform::Normal,
+            OutputTransform::Rotate90 => Transform::_90,
+            OutputTransform::Rotate180 => Transform::_180,
+            OutputTransform::Rotate270 => Transform::_270,
+            OutputTransform::Flipped => Transform::Flipped,
+            OutputTransform::Flipped90 => Transform::Flipped90,
+            OutputTransform::Flipped180 => Transform::Flipped180,
+            OutputTransform::Flipped270 => Transform::Flipped270,
+        }
     }
 }
 
 pub fn load_config() -> Config {
+    let path = config_path().expect("No config file found");
+    info!("Using config at {}", path.display());
+    try_load_config(&path).expect("Malformed config file")
+}
+
+/// The first existing config file location.
+pub fn config_path() -> Option<PathBuf> {
     let xdg = xdg::BaseDirectories::new().ok();
     let locations = if let Some(base) = xdg {
         vec![
@@ -47,26 +528,94 @@ pub fn load_config() -> Config {
         vec![]
     };
 
-    for path in locations {
-        dbg!("Trying config location: {}", path.display());
-        if path.exists() {
-            dbg!("Using config at {}", path.display());
-            return ron::de::from_reader(OpenOptions::new().read(true).open(path).unwrap())
-                .expect("Malformed config file");
-        }
+    locations.into_iter().find(|path| {
+        debug!("Trying config location: {}", path.display());
+        path.exists()
+    })
+}
+
+pub fn try_load_config(path: &Path) -> Result<Config, String> {
+    let file = OpenOptions::new()
+        .read(true)
+        .open(path)
+        .map_err(|err| err.to_string())?;
+    ron::de::from_reader(file).map_err(|err| err.to_string())
+}
+
+/// The current config, which can be swapped out at runtime.
+///
+/// Replaced configs are leaked, as references to them may still be held anywhere.
+/// Reloading is rare enough for this not to matter.
+pub struct ConfigHandle(RwLock<&'static Config>);
+
+impl ConfigHandle {
+    pub fn new(config: Config) -> Self {
+        Self(RwLock::new(Box::leak(Box::new(config))))
     }
-    panic!("No config file found")
+
+    /// Swap in a new config, returning the previous one, which stays valid.
+    pub fn replace(&self, config: Config) -> &'static Config {
+        std::mem::replace(&mut *self.0.write().unwrap(), Box::leak(Box::new(config)))
+    }
+}
+
+impl Deref for ConfigHandle {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        *self.0.read().unwrap()
+    }
+}
+
+fn default_sensitivity() -> f64 {
+    1.0
+}
+
+fn default_scroll_factor() -> f64 {
+    1.0
+}
+
+fn default_tap_to_click() -> bool {
+    true
+}
+
+fn default_disable_while_typing() -> bool {
+    true
 }
 
 fn default_gaps() -> (i32, i32) {
     (5, 5)
 }
 
+fn default_column_capacity() -> HashMap<TilingLayout, usize> {
+    HashMap::new()
+}
+
+fn default_workspace_defaults() -> HashMap<u8, WorkspaceConfig> {
+    HashMap::new()
+}
+
 fn default_xkb() -> XkbConfig {
     XkbConfig::default()
 }
 
-fn default_autostart() -> Vec<String> {
+fn default_repeat_delay() -> i32 {
+    200
+}
+
+fn default_repeat_rate() -> i32 {
+    25
+}
+
+fn default_autostart() -> Vec<SpawnCommand> {
+    vec![]
+}
+
+fn default_workspace_rules() -> HashMap<String, WorkspaceRule> {
+    HashMap::new()
+}
+
+fn default_window_rules() -> Vec<WindowRule> {
     vec![]
 }
 
@@ -74,7 +623,199 @@ fn default_outputs() -> HashMap<String, OutputConfig> {
     HashMap::new()
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+fn default_seats() -> HashMap<String, Vec<String>> {
+    HashMap::new()
+}
+
+fn default_virtual_input_clients() -> Vec<String> {
+    Vec::new()
+}
+
+fn default_focus_delay() -> u64 {
+    0
+}
+
+fn default_spawn_cooldown() -> u64 {
+    0
+}
+
+fn default_float_below() -> (i32, i32) {
+    (0, 0)
+}
+
+fn default_float_fixed_size() -> bool {
+    true
+}
+
+fn default_modifier_taps() -> HashMap<KeyModifier, Action> {
+    HashMap::new()
+}
+
+fn default_double_tap_interval() -> u64 {
+    300
+}
+
+fn default_hot_corners() -> HashMap<Corner, Action> {
+    HashMap::new()
+}
+
+fn default_mousebindings() -> HashMap<ButtonPattern, Action> {
+    HashMap::new()
+}
+
+fn default_gestures() -> HashMap<Gesture, Action> {
+    HashMap::new()
+}
+
+fn default_raise_on_click() -> bool {
+    true
+}
+
+fn default_confirm_quit() -> bool {
+    false
+}
+
+fn default_quit_confirm_timeout() -> u64 {
+    2000
+}
+
+fn default_idle_timeout() -> u64 {
+    0
+}
+
+fn default_dpms_timeout() -> u64 {
+    0
+}
+
+fn default_idle_command() -> Option<SpawnCommand> {
+    None
+}
+
+fn default_debug_dump_path() -> Option<String> {
+    None
+}
+
+fn default_debug_full_redraw() -> bool {
+    false
+}
+
+fn default_inactive_dim() -> f32 {
+    0.0
+}
+
+fn default_border_active() -> [f32; 4] {
+    [0.36, 0.6, 0.9, 1.0]
+}
+
+fn default_border_inactive() -> [f32; 4] {
+    [0.3, 0.3, 0.3, 1.0]
+}
+
+fn default_passthrough_frame() -> [f32; 4] {
+    [0.9, 0.3, 0.3, 1.0]
+}
+
+fn default_title_bar() -> Option<TitleBarConfig> {
+    None
+}
+
+fn default_title_bar_height() -> i32 {
+    24
+}
+
+fn default_close_button() -> [f32; 4] {
+    [0.9, 0.3, 0.3, 1.0]
+}
+
+fn default_maximize_button() -> [f32; 4] {
+    [0.9, 0.75, 0.3, 1.0]
+}
+
+fn default_double_click_interval() -> u64 {
+    400
+}
+
+fn default_title_text() -> [f32; 4] {
+    [0.9, 0.9, 0.9, 1.0]
+}
+
+fn default_title_font() -> Option<String> {
+    None
+}
+
+fn default_wallpaper() -> Option<String> {
+    None
+}
+
+fn default_cursor_theme() -> Option<String> {
+    None
+}
+
+fn default_cursor_size() -> Option<u32> {
+    None
+}
+
+fn default_wallpapers() -> HashMap<String, String> {
+    HashMap::new()
+}
+
+fn default_scroll_resize() -> Option<ScrollResizeConfig> {
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum FocusMode {
+    #[default]
+    Follow,
+    Click,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+pub enum TilingLayout {
+    #[default]
+    Bsp,
+    MasterStack,
+    Grid,
+}
+
+impl TilingLayout {
+    /// The layout following this one for CycleLayout.
+    pub fn next(self) -> Self {
+        match self {
+            TilingLayout::Bsp => TilingLayout::MasterStack,
+            TilingLayout::MasterStack => TilingLayout::Grid,
+            TilingLayout::Grid => TilingLayout::Bsp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A touchpad gesture with the given number of fingers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Gesture {
+    Swipe(u32, Direction),
+    /// Fingers moving towards each other
+    PinchIn(u32),
+    /// Fingers moving apart
+    PinchOut(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub enum KeyModifier {
     Ctrl,
     Alt,
@@ -103,15 +844,95 @@ pub struct KeyPattern {
     pub key: u32,
 }
 
+/// A pointer button pressed while holding `modifiers`, that might be
+/// handled by the compositor.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct ButtonPattern {
+    #[serde(deserialize_with = "deserialize_KeyModifiers")]
+    pub modifiers: KeyModifiers,
+    /// Linux input event code of the button, e.g. 272 for the left button
+    pub button: u32,
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 pub enum Action {
     Quit,
     Debug,
+    /// Read the config file again, like sending SIGUSR1 does
+    ReloadConfig,
     Close,
     Workspace(u8),
+    /// Drag the floating window under the pointer, only usable in `mousebindings`
+    MoveGrab,
+    /// Resize the window under the pointer from its closest edges, only usable in `mousebindings`
+    ResizeGrab,
+    /// Switch to the workspace with the next higher id, wrapping around
+    NextWorkspace,
+    /// Switch to the workspace with the next lower id, wrapping around
+    PreviousWorkspace,
     MoveWindow(u8),
     MoveAndSwitch(u8),
+    ToggleCarryWindow,
+    SwapWorkspaces(u8, u8),
+    RenameWorkspace(String),
+    CycleLayout,
     ToggleWindowFloating,
+    Fullscreen,
+    SwapWithMaster,
+    MoveFocus(Direction),
+    SwapWindow(Direction),
+    ResizeWindow {
+        direction: Direction,
+        amount: i32,
+    },
+    CenterWindow,
+    ToggleGaps,
+    IncreaseGaps(i32),
+    DecreaseGaps(i32),
+    ToggleFocusMode,
+    FocusNextFloating,
+    FocusOutputUnderPointer,
+    FocusOutput(Direction),
+    RaiseWindow,
+    LowerWindow,
+    ToggleAlwaysOnTop,
+    /// Focus the window that requested attention first, switching to its workspace
+    FocusUrgent,
+    /// Focus the previously focused window, switching to its workspace
+    FocusLastWindow,
+    /// Hide the focused window until it is brought back by ShowScratchpad
+    MoveToScratchpad,
+    /// Show the most recently hidden scratchpad window floating on the current output,
+    /// or hide the shown one again
+    ShowScratchpad,
+    TogglePassthrough,
+    ToggleConfinePointer,
     VTSwitch(i32),
-    Spawn(String),
+    Spawn(SpawnCommand),
+    SpawnOnWorkspace(u8, SpawnCommand),
+    FocusOrSpawn(String, SpawnCommand),
+}
+
+/// A command run with `/bin/sh -c`, either just the command line or with additional
+/// environment variables and the directory it is started in.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum SpawnCommand {
+    Command(String),
+    WithOptions {
+        command: String,
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+    },
+}
+
+impl SpawnCommand {
+    pub fn command(&self) -> &str {
+        match self {
+            SpawnCommand::Command(command) | SpawnCommand::WithOptions { command, .. } => command,
+        }
+    }
 }
@@ -1,9 +1,12 @@
+use regex::Regex;
 use serde::Deserialize;
 use smithay::input::keyboard::{
     keysyms as KeySyms, xkb, Keysym, ModifiersState, XkbConfig as WlXkbConfig,
 };
 
-use super::{KeyModifier, KeyModifiers};
+use std::collections::HashMap;
+
+use super::{KeyModifier, KeyModifiers, OutputConfig, OutputTransform};
 
 #[derive(Deserialize)]
 #[serde(transparent)]
@@ -34,6 +37,61 @@ where
     KeyModifiersDef::deserialize(deserializer).map(Into::into)
 }
 
+/// An entry of `outputs`, either a struct or the older tuple of mode size,
+/// refresh rate in mHz and an optional scale factor.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum OutputConfigDef {
+    // tried first, as structs with default fields would also take a tuple
+    Tuple((i32, i32), Option<u32>, #[serde(default)] Option<f64>),
+    Struct(OutputConfig),
+}
+
+impl From<OutputConfigDef> for OutputConfig {
+    fn from(src: OutputConfigDef) -> Self {
+        match src {
+            OutputConfigDef::Struct(config) => config,
+            OutputConfigDef::Tuple(resolution, refresh, scale) => OutputConfig {
+                position: None,
+                resolution: Some(resolution),
+                refresh,
+                scale,
+                transform: OutputTransform::default(),
+            },
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn deserialize_Outputs<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, OutputConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let outputs = HashMap::<String, OutputConfigDef>::deserialize(deserializer)?;
+    Ok(outputs
+        .into_iter()
+        .map(|(name, output)| (name, output.into()))
+        .collect())
+}
+
+/// An optional regular expression that has to match the whole string.
+#[allow(non_snake_case)]
+pub fn deserialize_Regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let Some(pattern) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    Regex::new(&format!("^(?:{})$", pattern))
+        .map(Some)
+        .map_err(<D::Error as Error>::custom)
+}
+
 #[allow(non_snake_case)]
 pub fn deserialize_Keysym<'de, D>(deserializer: D) -> Result<Keysym, D::Error>
 where
@@ -73,6 +131,18 @@ impl std::ops::AddAssign<KeyModifier> for KeyModifiers {
     }
 }
 
+impl KeyModifier {
+    pub fn from_keysym(keysym: Keysym) -> Option<Self> {
+        match keysym {
+            KeySyms::KEY_Control_L | KeySyms::KEY_Control_R => Some(KeyModifier::Ctrl),
+            KeySyms::KEY_Alt_L | KeySyms::KEY_Alt_R => Some(KeyModifier::Alt),
+            KeySyms::KEY_Shift_L | KeySyms::KEY_Shift_R => Some(KeyModifier::Shift),
+            KeySyms::KEY_Super_L | KeySyms::KEY_Super_R => Some(KeyModifier::Super),
+            _ => None,
+        }
+    }
+}
+
 impl PartialEq<ModifiersState> for KeyModifiers {
     fn eq(&self, other: &ModifiersState) -> bool {
         self.ctrl == other.ctrl
@@ -82,7 +152,9 @@ impl PartialEq<ModifiersState> for KeyModifiers {
     }
 }
 
+/// Empty fields use the system defaults, so only the settings that differ have to be set.
 #[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default)]
 pub struct XkbConfig {
     pub rules: String,
     pub model: String,
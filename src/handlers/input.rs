@@ -1,38 +1,148 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
 use smithay::{
     backend::input::{
-        self, AbsolutePositionEvent, Axis, AxisSource, Event, InputBackend, InputEvent, KeyState,
-        KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
+        self, AbsolutePositionEvent, Axis, AxisSource, ButtonState, Device, DeviceCapability,
+        Event, GestureBeginEvent, GestureEndEvent, GesturePinchUpdateEvent as _,
+        GestureSwipeUpdateEvent as _, InputBackend, InputEvent, KeyState, KeyboardKeyEvent,
+        PointerAxisEvent, PointerButtonEvent, PointerMotionEvent, ProximityState,
+        TabletToolButtonEvent, TabletToolEvent, TabletToolProximityEvent, TabletToolTipEvent,
+        TabletToolTipState, TouchEvent,
     },
+    desktop::{layer_map_for_output, Window},
     input::{
         keyboard::FilterResult,
-        pointer::{AxisFrame, ButtonEvent, MotionEvent, RelativeMotionEvent},
+        pointer::{
+            self, AxisFrame, ButtonEvent, CursorImageStatus, Focus, GrabStartData, MotionEvent,
+            RelativeMotionEvent,
+        },
+        Seat,
+    },
+    output::Output,
+    reexports::calloop::timer::{TimeoutAction, Timer},
+    utils::{Logical, Point, Rectangle, Serial, Size, SERIAL_COUNTER},
+    wayland::{
+        keyboard_shortcuts_inhibit::{KeyboardShortcutsInhibitor, KeyboardShortcutsInhibitorSeat},
+        pointer_constraints::{with_pointer_constraint, PointerConstraint},
+        seat::WaylandFocus,
+        shell::xdg::XdgShellHandler,
+        tablet_manager::{TabletDescriptor, TabletManagerState, TabletSeatTrait},
     },
-    utils::{Logical, Point, SERIAL_COUNTER},
 };
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
-    config::Action,
-    state::{Backend, MagmaState, CONFIG},
-    utils::focus::FocusTarget,
+    config::{Action, Corner, Direction, FocusMode, Gesture, KeyModifier, SpawnCommand},
+    handlers::ACTIVATION_TIMEOUT,
+    state::{Backend, MagmaState, CONFIG, SPAWN_WORKSPACE_TIMEOUT},
+    utils::{
+        focus::FocusTarget,
+        grabs::{edges_at, MoveSurfaceGrab, ResizeSurfaceGrab},
+        window::WindowExt,
+        workspace::MagmaWindow,
+    },
 };
 
+/// Scroll distance of one wheel step on devices that only report steps
+const DISCRETE_SCROLL_DISTANCE: f64 = 3.0;
+
+/// Distance the fingers have to move for a swipe to trigger its action
+const SWIPE_DISTANCE: f64 = 100.0;
+
+/// Change of the finger distance, relative to the start, for a pinch to trigger its action
+const PINCH_SCALE: f64 = 0.2;
+
+/// A touchpad gesture bound to an action in `CONFIG.gestures`, which clients don't see.
+pub enum BoundGesture {
+    Swipe {
+        fingers: u32,
+        delta: Point<f64, Logical>,
+    },
+    Pinch {
+        fingers: u32,
+        scale: f64,
+    },
+}
+
 impl<BackendData: Backend> MagmaState<BackendData> {
     pub fn process_input_event<I: InputBackend>(&mut self, event: InputEvent<I>) {
+        if !matches!(
+            event,
+            InputEvent::DeviceAdded { .. } | InputEvent::DeviceRemoved { .. }
+        ) {
+            self.notify_activity();
+        }
         match event {
             InputEvent::Keyboard { event, .. } => {
                 let serial = SERIAL_COUNTER.next_serial();
                 let time = Event::time_msec(&event);
+                let seat = self.seat_for_device(&event.device());
+                let inhibited = self.shortcuts_inhibited(&seat);
 
-                if let Some(action) = self.seat.get_keyboard().unwrap().input(
+                if let Some(action) = seat.get_keyboard().unwrap().input(
                     self,
                     event.key_code(),
                     event.state(),
                     serial,
                     time,
-                    |_, modifiers, handle| {
+                    |state, modifiers, handle| {
+                        // a modifier released without any other key pressed in between is a tap
+                        let modifier = handle
+                            .raw_syms()
+                            .iter()
+                            .find_map(|sym| KeyModifier::from_keysym(*sym));
+                        match (event.state(), modifier) {
+                            (KeyState::Pressed, modifier) => {
+                                if modifier.is_none() {
+                                    state.last_modifier_tap = None;
+                                }
+                                state.modifier_tap = modifier;
+                            }
+                            (KeyState::Released, Some(modifier)) => {
+                                if state.modifier_tap.take() == Some(modifier)
+                                    && !(state.passthrough || inhibited)
+                                {
+                                    let interval =
+                                        Duration::from_millis(CONFIG.double_tap_interval);
+                                    let double_tap = state.last_modifier_tap.take().is_some_and(
+                                        |(last, time)| {
+                                            last == modifier && time.elapsed() < interval
+                                        },
+                                    );
+                                    if double_tap {
+                                        // the single tap waiting for it doesn't count anymore
+                                        if let Some(token) = state.tap_timer.take() {
+                                            state.loop_handle.remove(token);
+                                        }
+                                        state.pending_action =
+                                            CONFIG.modifier_double_taps.get(&modifier).cloned();
+                                    } else {
+                                        state.last_modifier_tap = Some((modifier, Instant::now()));
+                                        match CONFIG.modifier_taps.get(&modifier).cloned() {
+                                            Some(action)
+                                                if CONFIG
+                                                    .modifier_double_taps
+                                                    .contains_key(&modifier) =>
+                                            {
+                                                state.defer_tap_action(action)
+                                            }
+                                            action => state.pending_action = action,
+                                        }
+                                    }
+                                }
+                            }
+                            (KeyState::Released, None) => {}
+                        }
+
                         for (binding, action) in CONFIG.keybindings.iter() {
+                            // while passthrough is active only the binding leaving it is handled
                             if event.state() == KeyState::Pressed
+                                && (!(state.passthrough || inhibited)
+                                    || *action == Action::TogglePassthrough)
                                 && binding.modifiers == *modifiers
                                 && handle.raw_syms().contains(&binding.key)
                             {
@@ -44,71 +154,155 @@ impl<BackendData: Backend> MagmaState<BackendData> {
                 ) {
                     self.handle_action(action);
                 };
+
+                if let Some(action) = self.pending_action.take() {
+                    self.handle_action(action);
+                }
             }
             InputEvent::PointerMotion { event } => {
                 let serial = SERIAL_COUNTER.next_serial();
-                let delta = (event.delta_x(), event.delta_y()).into();
-                self.pointer_location += delta;
+                let sensitivity = CONFIG.pointer.sensitivity;
+                let delta = (event.delta_x() * sensitivity, event.delta_y() * sensitivity).into();
+                let seat = self.seat_for_device(&event.device());
+                self.activate_pointer_seat(&seat);
+                let Some(ptr) = seat.get_pointer() else {
+                    return;
+                };
+
+                // the surface currently under the pointer may lock or confine it
+                let under = self.surface_under();
+                let mut locked = false;
+                let mut confine_region = None;
+                if let Some((surface, surface_loc)) = under
+                    .as_ref()
+                    .and_then(|(target, loc)| Some((target.wl_surface()?, *loc)))
+                {
+                    let pointer_location = self.pointer_location;
+                    with_pointer_constraint(&surface, &ptr, |constraint| match constraint {
+                        Some(constraint) if constraint.is_active() => {
+                            let local = (pointer_location - surface_loc.to_f64()).to_i32_round();
+                            if constraint.region().map_or(true, |r| r.contains(local)) {
+                                match &*constraint {
+                                    PointerConstraint::Locked(_) => locked = true,
+                                    PointerConstraint::Confined(confine) => {
+                                        confine_region = Some((
+                                            surface.clone(),
+                                            surface_loc,
+                                            confine.region().cloned(),
+                                        ))
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    });
+                }
+
+                // relative motion still reaches clients that locked the pointer
+                ptr.relative_motion(
+                    self,
+                    under.clone(),
+                    &RelativeMotionEvent {
+                        delta,
+                        delta_unaccel: event.delta_unaccel(),
+                        utime: event.time(),
+                    },
+                );
+                if locked {
+                    return;
+                }
 
                 // clamp to screen limits
                 // this event is never generated by winit
-                self.pointer_location = self.clamp_coords(self.pointer_location);
-
+                let previous_location = self.pointer_location;
+                self.pointer_location = self.clamp_coords(self.pointer_location + delta);
                 let under = self.surface_under();
 
-                self.set_input_focus_auto();
+                if let Some((surface, surface_loc, region)) = confine_region {
+                    // a confined pointer can't leave the surface or its confinement region
+                    let local = (self.pointer_location - surface_loc.to_f64()).to_i32_round();
+                    let inside = under
+                        .as_ref()
+                        .and_then(|(target, _)| target.wl_surface())
+                        .is_some_and(|s| s == surface)
+                        && region.map_or(true, |r| r.contains(local));
+                    if !inside {
+                        self.pointer_location = previous_location;
+                        return;
+                    }
+                }
 
-                if let Some(ptr) = self.seat.get_pointer() {
-                    ptr.motion(
-                        self,
-                        under.clone(),
-                        &MotionEvent {
-                            location: self.pointer_location,
-                            serial,
-                            time: event.time_msec(),
-                        },
-                    );
+                self.update_pointer_output();
+                self.reset_cursor_outside_surfaces(&under);
 
-                    ptr.relative_motion(
-                        self,
-                        under,
-                        &RelativeMotionEvent {
-                            delta,
-                            delta_unaccel: event.delta_unaccel(),
-                            utime: event.time(),
-                        },
-                    )
+                self.set_seat_focus_delayed(&seat);
+
+                ptr.motion(
+                    self,
+                    under.clone(),
+                    &MotionEvent {
+                        location: self.pointer_location,
+                        serial,
+                        time: event.time_msec(),
+                    },
+                );
+
+                // activate a constraint of the surface the pointer moved onto
+                if let Some((surface, surface_loc)) = under
+                    .as_ref()
+                    .and_then(|(target, loc)| Some((target.wl_surface()?, *loc)))
+                {
+                    let local = (self.pointer_location - surface_loc.to_f64()).to_i32_round();
+                    with_pointer_constraint(&surface, &ptr, |constraint| match constraint {
+                        Some(constraint) if !constraint.is_active() => {
+                            if constraint.region().map_or(true, |r| r.contains(local)) {
+                                constraint.activate();
+                            }
+                        }
+                        _ => {}
+                    });
                 }
+
+                self.update_hot_corner();
             }
             InputEvent::PointerMotionAbsolute { event, .. } => {
-                let output = self.workspaces.current().outputs().next().unwrap().clone();
-
-                let output_geo = self.workspaces.current().output_geometry(&output).unwrap();
-
-                let pos = event.position_transformed(output_geo.size) + output_geo.loc.to_f64();
+                // drop the event if there is no output to map it to
+                let Some(pos) = self.absolute_position(&event) else {
+                    return;
+                };
 
                 let serial = SERIAL_COUNTER.next_serial();
 
-                let pointer = self.seat.get_pointer().unwrap();
+                let seat = self.seat_for_device(&event.device());
+                self.activate_pointer_seat(&seat);
+                let pointer = seat.get_pointer().unwrap();
 
                 self.pointer_location = self.clamp_coords(pos);
+                self.update_pointer_output();
 
                 let under = self.surface_under();
+                self.reset_cursor_outside_surfaces(&under);
 
-                self.set_input_focus_auto();
+                self.set_seat_focus_delayed(&seat);
 
                 pointer.motion(
                     self,
                     under,
                     &MotionEvent {
-                        location: pos,
+                        location: self.pointer_location,
                         serial,
                         time: event.time_msec(),
                     },
                 );
+
+                self.update_hot_corner();
             }
             InputEvent::PointerButton { event, .. } => {
-                let pointer = self.seat.get_pointer().unwrap();
+                let seat = self.seat_for_device(&event.device());
+                // a modifier held while clicking or scrolling is not a tap
+                self.modifier_tap = None;
+                self.activate_pointer_seat(&seat);
+                let pointer = seat.get_pointer().unwrap();
 
                 let serial = SERIAL_COUNTER.next_serial();
 
@@ -116,7 +310,74 @@ impl<BackendData: Backend> MagmaState<BackendData> {
 
                 let button_state = event.state();
 
-                self.set_input_focus_auto();
+                // the release of a button whose press triggered a binding is not sent either
+                if button_state == ButtonState::Released {
+                    if let Some(index) = self.bound_buttons.iter().position(|b| *b == button) {
+                        self.bound_buttons.remove(index);
+                        return;
+                    }
+                }
+
+                if button_state == ButtonState::Pressed && !pointer.is_grabbed() {
+                    let modifiers = seat.get_keyboard().unwrap().modifier_state();
+                    let binding = CONFIG
+                        .mousebindings
+                        .iter()
+                        .find(|(binding, _)| {
+                            binding.button == button && binding.modifiers == modifiers
+                        })
+                        .map(|(_, action)| action.clone())
+                        .filter(|action| {
+                            !(self.passthrough || self.shortcuts_inhibited(&seat))
+                                || *action == Action::TogglePassthrough
+                        });
+                    match binding {
+                        Some(Action::MoveGrab) => {
+                            if self.start_move_grab(&seat, button, serial) {
+                                return;
+                            }
+                        }
+                        Some(Action::ResizeGrab) => {
+                            if self.start_resize_grab(&seat, button, serial) {
+                                return;
+                            }
+                        }
+                        Some(action) => {
+                            self.bound_buttons.push(button);
+                            self.handle_action(action);
+                            return;
+                        }
+                        None => {}
+                    }
+                }
+
+                if button_state == ButtonState::Pressed && !pointer.is_grabbed() {
+                    if let Some(magmawindow) = self
+                        .workspaces
+                        .current()
+                        .title_bar_under(self.pointer_location)
+                    {
+                        self.click_title_bar(&seat, magmawindow, button, serial);
+                        return;
+                    }
+                }
+
+                if CONFIG.raise_on_click && button_state == ButtonState::Pressed {
+                    if let Some(window) = self
+                        .workspaces
+                        .current()
+                        .magmawindow_under(self.pointer_location)
+                        .filter(|w| w.borrow().floating)
+                    {
+                        let window = window.borrow().window.clone();
+                        self.workspaces.current_mut().raise_window(&window);
+                    }
+                }
+
+                // the only way to change focus in click to focus mode
+                if button_state == ButtonState::Pressed {
+                    self.set_seat_focus_auto(&seat);
+                }
 
                 pointer.button(
                     self,
@@ -129,18 +390,55 @@ impl<BackendData: Backend> MagmaState<BackendData> {
                 );
             }
             InputEvent::PointerAxis { event, .. } => {
-                let horizontal_amount =
-                    event.amount(input::Axis::Horizontal).unwrap_or_else(|| {
-                        event
-                            .amount_discrete(input::Axis::Horizontal)
-                            .unwrap_or(0.0)
-                            * 3.0
-                    });
-                let vertical_amount = event.amount(input::Axis::Vertical).unwrap_or_else(|| {
-                    event.amount_discrete(input::Axis::Vertical).unwrap_or(0.0) * 3.0
-                });
-                let horizontal_amount_discrete = event.amount_discrete(input::Axis::Horizontal);
-                let vertical_amount_discrete = event.amount_discrete(input::Axis::Vertical);
+                let seat = self.seat_for_device(&event.device());
+                self.modifier_tap = None;
+                self.activate_pointer_seat(&seat);
+                if let Some(scroll_resize) = &CONFIG.scroll_resize {
+                    let modifiers = seat.get_keyboard().unwrap().modifier_state();
+                    let vertical_amount = event
+                        .amount(input::Axis::Vertical)
+                        .or_else(|| event.amount_discrete(input::Axis::Vertical))
+                        .unwrap_or(0.0);
+                    if scroll_resize.modifiers == modifiers && vertical_amount != 0.0 {
+                        if let Some(window) = self
+                            .workspaces
+                            .current()
+                            .magmawindow_under(self.pointer_location)
+                            .filter(|w| w.borrow().floating)
+                        {
+                            // scrolling up grows the window, scrolling down shrinks it
+                            let delta = if vertical_amount < 0.0 {
+                                scroll_resize.step
+                            } else {
+                                -scroll_resize.step
+                            };
+                            window.borrow_mut().resize_centered(delta);
+                            return;
+                        }
+                    }
+                }
+
+                // natural scrolling only makes sense when moving fingers over the content
+                let direction =
+                    if CONFIG.scroll.natural_scroll && event.source() == AxisSource::Finger {
+                        -1.0
+                    } else {
+                        1.0
+                    };
+                let factor = direction * CONFIG.scroll.factor;
+                let amount = |axis| {
+                    event.amount(axis).unwrap_or_else(|| {
+                        event.amount_discrete(axis).unwrap_or(0.0) * DISCRETE_SCROLL_DISTANCE
+                    }) * factor
+                };
+                let horizontal_amount = amount(input::Axis::Horizontal);
+                let vertical_amount = amount(input::Axis::Vertical);
+                let horizontal_amount_discrete = event
+                    .amount_discrete(input::Axis::Horizontal)
+                    .map(|d| d * direction);
+                let vertical_amount_discrete = event
+                    .amount_discrete(input::Axis::Vertical)
+                    .map(|d| d * direction);
 
                 {
                     let mut frame = AxisFrame::new(event.time_msec()).source(event.source());
@@ -160,60 +458,841 @@ impl<BackendData: Backend> MagmaState<BackendData> {
                     } else if event.source() == AxisSource::Finger {
                         frame = frame.stop(Axis::Vertical);
                     }
-                    self.seat.get_pointer().unwrap().axis(self, frame);
+                    seat.get_pointer().unwrap().axis(self, frame);
+                }
+            }
+            InputEvent::TouchDown { event, .. } => {
+                let Some(pos) = self.absolute_position(&event) else {
+                    return;
+                };
+                let seat = self.seat_for_device(&event.device());
+                let Some(touch) = seat.get_touch() else {
+                    return;
+                };
+                let serial = SERIAL_COUNTER.next_serial();
+                let under = self.surface_under_at(pos);
+
+                // only the first finger moves the focus, others may touch e.g. a keyboard panel
+                if self.touch_points == 0 {
+                    if let Some((target, _)) = &under {
+                        if let FocusTarget::Window(window) = target {
+                            if CONFIG.raise_on_click {
+                                // the window may be on another output than the current workspace
+                                if let Some(workspace) =
+                                    self.workspaces.workspace_from_window(window)
+                                {
+                                    workspace.raise_window(window);
+                                }
+                            }
+                        }
+                        self.set_seat_focus(&seat, target.clone());
+                    }
+                }
+                self.touch_points += 1;
+
+                if let Some((surface, surface_loc)) =
+                    under.and_then(|(target, loc)| Some((target.wl_surface()?, loc)))
+                {
+                    touch.down(
+                        serial,
+                        event.time_msec(),
+                        &surface,
+                        surface_loc,
+                        event.slot(),
+                        pos,
+                    );
+                }
+            }
+            InputEvent::TouchMotion { event, .. } => {
+                let Some(pos) = self.absolute_position(&event) else {
+                    return;
+                };
+                if let Some(touch) = self.seat_for_device(&event.device()).get_touch() {
+                    touch.motion(event.time_msec(), event.slot(), pos);
+                }
+            }
+            InputEvent::TouchUp { event, .. } => {
+                self.touch_points = self.touch_points.saturating_sub(1);
+                if let Some(touch) = self.seat_for_device(&event.device()).get_touch() {
+                    let serial = SERIAL_COUNTER.next_serial();
+                    touch.up(serial, event.time_msec(), event.slot());
+                }
+            }
+            InputEvent::TouchFrame { event, .. } => {
+                if let Some(touch) = self.seat_for_device(&event.device()).get_touch() {
+                    touch.frame();
+                }
+            }
+            InputEvent::TouchCancel { event, .. } => {
+                self.touch_points = 0;
+                if let Some(touch) = self.seat_for_device(&event.device()).get_touch() {
+                    touch.cancel();
+                }
+            }
+            InputEvent::TabletToolAxis { event, .. } => {
+                let seat = self.seat_for_device(&event.device());
+                self.activate_pointer_seat(&seat);
+                let tablet_seat = seat.tablet_seat();
+                let Some(pos) = self.map_to_active_output(|size| event.position_transformed(size))
+                else {
+                    return;
+                };
+                // the cursor follows the tool, there is no separate tool cursor
+                self.pointer_location = self.clamp_coords(pos);
+                self.update_pointer_output();
+                let under = self.surface_under();
+
+                let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
+                let tool = tablet_seat.get_tool(&event.tool());
+                if let (Some(tablet), Some(tool)) = (tablet, tool) {
+                    if event.pressure_has_changed() {
+                        tool.pressure(event.pressure());
+                    }
+                    if event.distance_has_changed() {
+                        tool.distance(event.distance());
+                    }
+                    if event.tilt_has_changed() {
+                        tool.tilt(event.tilt());
+                    }
+                    if event.slider_has_changed() {
+                        tool.slider_position(event.slider_position());
+                    }
+                    if event.rotation_has_changed() {
+                        tool.rotation(event.rotation());
+                    }
+                    if event.wheel_has_changed() {
+                        tool.wheel(event.wheel_delta(), event.wheel_delta_discrete());
+                    }
+                    tool.motion(
+                        self.pointer_location,
+                        under.and_then(|(target, loc)| Some((target.wl_surface()?, loc))),
+                        &tablet,
+                        SERIAL_COUNTER.next_serial(),
+                        event.time_msec(),
+                    );
+                }
+            }
+            InputEvent::TabletToolProximity { event, .. } => {
+                let seat = self.seat_for_device(&event.device());
+                self.activate_pointer_seat(&seat);
+                let tablet_seat = seat.tablet_seat();
+                let Some(pos) = self.map_to_active_output(|size| event.position_transformed(size))
+                else {
+                    return;
+                };
+                tablet_seat.add_tool::<Self>(&self.dh, &event.tool());
+                self.pointer_location = self.clamp_coords(pos);
+                self.update_pointer_output();
+                let under = self
+                    .surface_under()
+                    .and_then(|(target, loc)| Some((target.wl_surface()?, loc)));
+
+                let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
+                let tool = tablet_seat.get_tool(&event.tool());
+                if let (Some(tablet), Some(tool)) = (tablet, tool) {
+                    match (event.state(), under) {
+                        (ProximityState::In, Some(under)) => tool.proximity_in(
+                            self.pointer_location,
+                            under,
+                            &tablet,
+                            SERIAL_COUNTER.next_serial(),
+                            event.time_msec(),
+                        ),
+                        (ProximityState::Out, _) => tool.proximity_out(event.time_msec()),
+                        _ => {}
+                    }
+                }
+            }
+            InputEvent::TabletToolTip { event, .. } => {
+                let seat = self.seat_for_device(&event.device());
+                let Some(tool) = seat.tablet_seat().get_tool(&event.tool()) else {
+                    return;
+                };
+                match event.tip_state() {
+                    TabletToolTipState::Down => {
+                        tool.tip_down(SERIAL_COUNTER.next_serial(), event.time_msec());
+                        // touching down with the pen focuses like a click
+                        self.set_seat_focus_auto(&seat);
+                    }
+                    TabletToolTipState::Up => tool.tip_up(event.time_msec()),
+                }
+            }
+            InputEvent::TabletToolButton { event, .. } => {
+                let seat = self.seat_for_device(&event.device());
+                if let Some(tool) = seat.tablet_seat().get_tool(&event.tool()) {
+                    tool.button(
+                        event.button(),
+                        event.button_state(),
+                        SERIAL_COUNTER.next_serial(),
+                        event.time_msec(),
+                    );
+                }
+            }
+            InputEvent::GestureSwipeBegin { event, .. } => {
+                let fingers = event.fingers();
+                if CONFIG
+                    .gestures
+                    .keys()
+                    .any(|g| matches!(g, Gesture::Swipe(f, _) if *f == fingers))
+                {
+                    self.bound_gesture = Some(BoundGesture::Swipe {
+                        fingers,
+                        delta: (0.0, 0.0).into(),
+                    });
+                    return;
+                }
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_swipe_begin(
+                    self,
+                    &pointer::GestureSwipeBeginEvent {
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time: event.time_msec(),
+                        fingers,
+                    },
+                );
+            }
+            InputEvent::GestureSwipeUpdate { event, .. } => {
+                if let Some(BoundGesture::Swipe { delta, .. }) = &mut self.bound_gesture {
+                    *delta += Point::from((event.delta_x(), event.delta_y()));
+                    return;
+                }
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_swipe_update(
+                    self,
+                    &pointer::GestureSwipeUpdateEvent {
+                        time: event.time_msec(),
+                        delta: (event.delta_x(), event.delta_y()).into(),
+                    },
+                );
+            }
+            InputEvent::GestureSwipeEnd { event, .. } => {
+                if let Some(BoundGesture::Swipe { fingers, delta }) = self.bound_gesture.take() {
+                    let direction = if delta.x.abs() > delta.y.abs() {
+                        (delta.x.abs() >= SWIPE_DISTANCE).then(|| {
+                            if delta.x < 0.0 {
+                                Direction::Left
+                            } else {
+                                Direction::Right
+                            }
+                        })
+                    } else {
+                        (delta.y.abs() >= SWIPE_DISTANCE).then(|| {
+                            if delta.y < 0.0 {
+                                Direction::Up
+                            } else {
+                                Direction::Down
+                            }
+                        })
+                    };
+                    if let Some(direction) = direction.filter(|_| !event.cancelled()) {
+                        self.handle_gesture(Gesture::Swipe(fingers, direction));
+                    }
+                    return;
+                }
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_swipe_end(
+                    self,
+                    &pointer::GestureSwipeEndEvent {
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time: event.time_msec(),
+                        cancelled: event.cancelled(),
+                    },
+                );
+            }
+            InputEvent::GesturePinchBegin { event, .. } => {
+                let fingers = event.fingers();
+                if CONFIG.gestures.keys().any(
+                    |g| matches!(g, Gesture::PinchIn(f) | Gesture::PinchOut(f) if *f == fingers),
+                ) {
+                    self.bound_gesture = Some(BoundGesture::Pinch {
+                        fingers,
+                        scale: 1.0,
+                    });
+                    return;
+                }
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_pinch_begin(
+                    self,
+                    &pointer::GesturePinchBeginEvent {
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time: event.time_msec(),
+                        fingers,
+                    },
+                );
+            }
+            InputEvent::GesturePinchUpdate { event, .. } => {
+                if let Some(BoundGesture::Pinch { scale, .. }) = &mut self.bound_gesture {
+                    // the scale is relative to the start of the gesture
+                    *scale = event.scale();
+                    return;
+                }
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_pinch_update(
+                    self,
+                    &pointer::GesturePinchUpdateEvent {
+                        time: event.time_msec(),
+                        delta: (event.delta_x(), event.delta_y()).into(),
+                        scale: event.scale(),
+                        rotation: event.rotation(),
+                    },
+                );
+            }
+            InputEvent::GesturePinchEnd { event, .. } => {
+                if let Some(BoundGesture::Pinch { fingers, scale }) = self.bound_gesture.take() {
+                    if !event.cancelled() {
+                        if scale <= 1.0 - PINCH_SCALE {
+                            self.handle_gesture(Gesture::PinchIn(fingers));
+                        } else if scale >= 1.0 + PINCH_SCALE {
+                            self.handle_gesture(Gesture::PinchOut(fingers));
+                        }
+                    }
+                    return;
+                }
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_pinch_end(
+                    self,
+                    &pointer::GesturePinchEndEvent {
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time: event.time_msec(),
+                        cancelled: event.cancelled(),
+                    },
+                );
+            }
+            InputEvent::GestureHoldBegin { event, .. } => {
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_hold_begin(
+                    self,
+                    &pointer::GestureHoldBeginEvent {
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time: event.time_msec(),
+                        fingers: event.fingers(),
+                    },
+                );
+            }
+            InputEvent::GestureHoldEnd { event, .. } => {
+                let ptr = self.seat_for_device(&event.device()).get_pointer().unwrap();
+                ptr.gesture_hold_end(
+                    self,
+                    &pointer::GestureHoldEndEvent {
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time: event.time_msec(),
+                        cancelled: event.cancelled(),
+                    },
+                );
+            }
+            InputEvent::DeviceAdded { device } => {
+                let seat = self.seat_for_device(&device);
+                info!(
+                    "New input device {}, assigned to seat {}",
+                    device.name(),
+                    seat.name()
+                );
+                if device.has_capability(DeviceCapability::TabletTool) {
+                    // the protocol is only offered once there is a tablet to use it with
+                    if self.tablet_manager_state.is_none() {
+                        self.tablet_manager_state = Some(TabletManagerState::new::<Self>(&self.dh));
+                    }
+                    seat.tablet_seat()
+                        .add_tablet::<Self>(&self.dh, &TabletDescriptor::from(&device));
+                }
+            }
+            InputEvent::DeviceRemoved { device } => {
+                if device.has_capability(DeviceCapability::TabletTool) {
+                    let tablet_seat = self.seat_for_device(&device).tablet_seat();
+                    tablet_seat.remove_tablet(&TabletDescriptor::from(&device));
+                    if tablet_seat.count_tablets() == 0 {
+                        tablet_seat.clear_tools();
+                    }
                 }
             }
             _ => {}
         }
     }
 
-    fn clamp_coords(&self, pos: Point<f64, Logical>) -> Point<f64, Logical> {
-        if self.workspaces.current().outputs().next().is_none() {
+    pub fn clamp_coords(&self, pos: Point<f64, Logical>) -> Point<f64, Logical> {
+        let Some(active_geo) = self
+            .active_output()
+            .and_then(|o| self.workspaces.output_geometry(&o))
+        else {
             return pos;
-        }
+        };
 
+        // the pointer may move onto any output, otherwise it stays on the active one
         let (pos_x, pos_y) = pos.into();
-        let (max_x, max_y) = self
+        let (clamped_x, clamped_y) = if self
             .workspaces
-            .current()
-            .output_geometry(self.workspaces.current().outputs().next().unwrap())
-            .unwrap()
-            .size
-            .into();
-        let clamped_x = pos_x.max(0.0).min(max_x as f64);
-        let clamped_y = pos_y.max(0.0).min(max_y as f64);
+            .outputs()
+            .filter_map(|o| self.workspaces.output_geometry(o))
+            .any(|geo| geo.to_f64().contains(pos))
+        {
+            (pos_x, pos_y)
+        } else {
+            (
+                pos_x
+                    .max(active_geo.loc.x as f64)
+                    .min((active_geo.loc.x + active_geo.size.w) as f64),
+                pos_y
+                    .max(active_geo.loc.y as f64)
+                    .min((active_geo.loc.y + active_geo.size.h) as f64),
+            )
+        };
+
+        // keep the pointer within the focused window if confined
+        let confine_rec = self
+            .focused_window()
+            .filter(|_| self.confine_pointer)
+            .and_then(|w| self.workspaces.current().magmawindow(&w))
+            .map(|w| w.borrow().rec);
+        if let Some(rec) = confine_rec {
+            let clamped_x = clamped_x
+                .max(rec.loc.x as f64)
+                .min((rec.loc.x + rec.size.w) as f64);
+            let clamped_y = clamped_y
+                .max(rec.loc.y as f64)
+                .min((rec.loc.y + rec.size.h) as f64);
+            return (clamped_x, clamped_y).into();
+        }
+
         (clamped_x, clamped_y).into()
     }
 
+    /// Go back to the default cursor once the pointer left all client surfaces,
+    /// clients set their own cursor again when the pointer enters them.
+    fn reset_cursor_outside_surfaces(
+        &mut self,
+        under: &Option<(FocusTarget, Point<i32, Logical>)>,
+    ) {
+        if under.is_none() {
+            self.cursor_status = CursorImageStatus::Default;
+        }
+    }
+
+    /// Make the workspace of the output under the pointer the current one.
+    pub fn update_pointer_output(&mut self) {
+        let pos = self.pointer_location;
+        let output = self
+            .workspaces
+            .outputs()
+            .find(|o| {
+                self.workspaces
+                    .output_geometry(o)
+                    .is_some_and(|geo| geo.to_f64().contains(pos))
+            })
+            .cloned();
+        if let Some(output) = output {
+            if let Some(id) = self.workspaces.output_workspace(&output) {
+                self.workspaces.current = id;
+            }
+            self.focused_output = Some(output);
+        }
+    }
+
+    /// Focus `output` and the topmost window shown on it.
+    pub fn focus_output(&mut self, output: Output) {
+        if let Some(id) = self.workspaces.output_workspace(&output) {
+            self.workspaces.current = id;
+        }
+        let workspace = self.workspaces.current();
+        let window = workspace.output_geometry(&output).and_then(|geo| {
+            workspace
+                .magmawindows()
+                .find(|w| w.rec.overlaps(geo))
+                .map(|w| w.window.clone())
+        });
+        self.focused_output = Some(output);
+        if let Some(window) = window {
+            self.set_input_focus(FocusTarget::Window(window));
+        }
+    }
+
     pub fn set_input_focus(&mut self, target: FocusTarget) {
-        let keyboard = self.seat.get_keyboard().unwrap();
+        let seat = self.seat.clone();
+        self.set_seat_focus(&seat, target);
+    }
+
+    pub fn set_input_focus_auto(&mut self) {
+        let seat = self.seat.clone();
+        self.set_seat_focus_auto(&seat);
+    }
+
+    pub fn set_seat_focus(&mut self, seat: &Seat<Self>, target: FocusTarget) {
+        if let (true, FocusTarget::Window(window)) = (self.focus_mode, &target) {
+            if let Some(workspace) = self.workspaces.workspace_from_window(window) {
+                workspace.set_focused_only(Some(window.clone()));
+            }
+        }
+        if let FocusTarget::Window(window) = &target {
+            self.focus_history.retain(|w| w != window);
+            self.focus_history.push(window.clone());
+            if let Some(magmawindow) = self
+                .workspaces
+                .workspace_from_window(window)
+                .and_then(|workspace| workspace.magmawindow(window))
+            {
+                magmawindow.borrow_mut().urgent = None;
+            }
+        }
+        let keyboard = seat.get_keyboard().unwrap();
         let serial = SERIAL_COUNTER.next_serial();
         keyboard.set_focus(self, Some(target), serial);
     }
 
-    pub fn set_input_focus_auto(&mut self) {
+    /// Map the position reported by an absolute device, like a touchscreen or tablet,
+    /// onto the active output.
+    fn absolute_position<I: InputBackend, E: AbsolutePositionEvent<I>>(
+        &self,
+        event: &E,
+    ) -> Option<Point<f64, Logical>> {
+        self.map_to_active_output(|size| event.position_transformed(size))
+    }
+
+    /// Map a position on an absolute device, given by `position_transformed`
+    /// for the size of the device area, onto the active output.
+    fn map_to_active_output(
+        &self,
+        position_transformed: impl FnOnce(Size<i32, Logical>) -> Point<f64, Logical>,
+    ) -> Option<Point<f64, Logical>> {
+        let output = self.active_output()?;
+        let output_geo = self.workspaces.output_geometry(&output)?;
+        let transform = output.current_transform();
+
+        // absolute devices report positions on the untransformed panel,
+        // which has to be rotated like the output content
+        let panel_size = transform.invert().transform_size(output_geo.size);
+        Some(
+            transform
+                .invert()
+                .transform_point_in(position_transformed(panel_size), &panel_size.to_f64())
+                + output_geo.loc.to_f64(),
+        )
+    }
+
+    /// Start dragging the floating window under the pointer while `button` is held,
+    /// returning whether there was one.
+    fn start_move_grab(&mut self, seat: &Seat<Self>, button: u32, serial: Serial) -> bool {
+        let workspace = self.workspaces.current();
+        let Some(window) = workspace
+            .magmawindow_under(self.pointer_location)
+            .or_else(|| workspace.title_bar_under(self.pointer_location))
+            .filter(|w| w.borrow().floating)
+        else {
+            return false;
+        };
+        let (window, initial_window_location) = {
+            let window = window.borrow();
+            (window.window.clone(), window.rec.loc)
+        };
+        self.workspaces.current_mut().raise_window(&window);
+        self.set_seat_focus(seat, FocusTarget::Window(window.clone()));
+        let grab = MoveSurfaceGrab {
+            start_data: GrabStartData {
+                focus: None,
+                button,
+                location: self.pointer_location,
+            },
+            window,
+            initial_window_location,
+        };
+        seat.get_pointer()
+            .unwrap()
+            .set_grab(self, grab, serial, Focus::Clear);
+        true
+    }
+
+    /// Start resizing the window under the pointer from its closest edges while `button`
+    /// is held, returning whether there was one.
+    fn start_resize_grab(&mut self, seat: &Seat<Self>, button: u32, serial: Serial) -> bool {
+        let Some(window) = self
+            .workspaces
+            .current()
+            .magmawindow_under(self.pointer_location)
+        else {
+            return false;
+        };
+        let (window, initial_window_rec) = {
+            let window = window.borrow();
+            (window.window.clone(), window.rec)
+        };
+        self.set_seat_focus(seat, FocusTarget::Window(window.clone()));
+        let grab = ResizeSurfaceGrab {
+            start_data: GrabStartData {
+                focus: None,
+                button,
+                location: self.pointer_location,
+            },
+            window,
+            edges: edges_at(initial_window_rec, self.pointer_location),
+            initial_window_rec,
+            last_location: self.pointer_location,
+        };
+        seat.get_pointer()
+            .unwrap()
+            .set_grab(self, grab, serial, Focus::Clear);
+        true
+    }
+
+    /// Take `window` off its workspace into the scratchpad, keeping its size.
+    fn hide_in_scratchpad(&mut self, window: &Window) {
+        let Some(magmawindow) = self
+            .workspaces
+            .workspace_from_window(window)
+            .and_then(|workspace| workspace.remove_window(window))
+        else {
+            return;
+        };
+        self.scratchpad.push(magmawindow);
+        if self.scratchpad_shown.as_ref() == Some(window) {
+            self.scratchpad_shown = None;
+        }
+        self.workspaces.remove_unused();
+        self.set_input_focus_auto();
+    }
+
+    /// Close or maximize the window if one of the buttons of its title bar was pressed,
+    /// otherwise focus it and start dragging it if it floats.
+    fn click_title_bar(
+        &mut self,
+        seat: &Seat<Self>,
+        magmawindow: Rc<RefCell<MagmaWindow>>,
+        button: u32,
+        serial: Serial,
+    ) {
+        let (window, buttons, floating, maximized) = {
+            let magmawindow = magmawindow.borrow();
+            (
+                magmawindow.window.clone(),
+                magmawindow.title_bar_buttons(),
+                magmawindow.floating,
+                magmawindow.unmaximized.is_some(),
+            )
+        };
+        let pos = self.pointer_location;
+        match buttons {
+            Some((close, _)) if close.to_f64().contains(pos) => {
+                window.toplevel().send_close();
+            }
+            Some((_, maximize)) if maximize.to_f64().contains(pos) => {
+                self.toggle_maximized(&window, maximized);
+            }
+            _ if self.title_bar_double_click(&window) => {
+                self.toggle_maximized(&window, maximized);
+            }
+            _ => {
+                if floating {
+                    self.workspaces.current_mut().raise_window(&window);
+                    if self.start_move_grab(seat, button, serial) {
+                        return;
+                    }
+                }
+                self.set_seat_focus(seat, FocusTarget::Window(window));
+            }
+        }
+        // the client never saw the press, so it must not get the release either
+        self.bound_buttons.push(button);
+    }
+
+    /// Whether this click on the title bar of `window` completes a double click,
+    /// remembering it as the first click of the next one otherwise.
+    fn title_bar_double_click(&mut self, window: &Window) -> bool {
+        let interval = Duration::from_millis(
+            CONFIG
+                .title_bar
+                .as_ref()
+                .map_or(0, |title_bar| title_bar.double_click_interval),
+        );
+        let double_click = self
+            .last_title_bar_click
+            .take()
+            .is_some_and(|(last, time)| &last == window && time.elapsed() < interval);
+        if !double_click {
+            self.last_title_bar_click = Some((window.clone(), Instant::now()));
+        }
+        double_click
+    }
+
+    fn toggle_maximized(&mut self, window: &Window, maximized: bool) {
+        let toplevel = window.toplevel().clone();
+        if maximized {
+            self.unmaximize_request(toplevel);
+        } else {
+            self.maximize_request(toplevel);
+        }
+    }
+
+    fn handle_gesture(&mut self, gesture: Gesture) {
+        if let Some(action) = CONFIG.gestures.get(&gesture) {
+            self.handle_action(action.clone());
+        }
+    }
+
+    pub fn set_seat_focus_auto(&mut self, seat: &Seat<Self>) {
         let under = self.surface_under();
         if let Some(d) = under {
-            self.set_input_focus(d.0);
+            self.set_seat_focus(seat, d.0);
+        }
+    }
+
+    /// The active keyboard shortcuts inhibitor of the surface focused on `seat`.
+    fn shortcuts_inhibitor(&self, seat: &Seat<Self>) -> Option<KeyboardShortcutsInhibitor> {
+        let surface = seat.get_keyboard()?.current_focus()?.wl_surface()?;
+        seat.keyboard_shortcuts_inhibitor_for_surface(&surface)
+            .filter(|inhibitor| inhibitor.is_active())
+    }
+
+    fn shortcuts_inhibited(&self, seat: &Seat<Self>) -> bool {
+        self.shortcuts_inhibitor(seat).is_some()
+    }
+
+    /// Trigger the action of a hot corner when the pointer enters it.
+    /// It fires again only after the pointer left the corner.
+    fn update_hot_corner(&mut self) {
+        let pos = self.pointer_location;
+        let corner = self
+            .workspaces
+            .outputs()
+            .filter_map(|o| self.workspaces.output_geometry(o))
+            .find_map(|geo| corner_at(geo, pos));
+        if corner == self.hot_corner {
+            return;
+        }
+        self.hot_corner = corner;
+        if let Some(action) = corner.and_then(|c| CONFIG.hot_corners.get(&c)) {
+            self.handle_action(action.clone());
+        }
+    }
+
+    /// Focus the surface under the pointer once it stayed there for `CONFIG.focus_delay`
+    /// milliseconds. Every call restarts the delay. Does nothing in click to focus mode.
+    pub fn set_seat_focus_delayed(&mut self, seat: &Seat<Self>) {
+        if let Some(token) = self.focus_timer.take() {
+            self.loop_handle.remove(token);
         }
+
+        if CONFIG.focus_mode == FocusMode::Click {
+            return;
+        }
+
+        if CONFIG.focus_delay == 0 {
+            self.set_seat_focus_auto(seat);
+            return;
+        }
+
+        let under = self.surface_under().map(|(target, _)| target);
+        if under.is_none() || seat.get_keyboard().unwrap().current_focus() == under {
+            return;
+        }
+
+        let seat = seat.clone();
+        let timer = Timer::from_duration(Duration::from_millis(CONFIG.focus_delay));
+        self.focus_timer = self
+            .loop_handle
+            .insert_source(timer, move |_, _, data| {
+                data.state.focus_timer = None;
+                data.state.set_seat_focus_auto(&seat);
+                TimeoutAction::Drop
+            })
+            .ok();
+    }
+
+    /// Handle the action of a single modifier tap once no second tap followed within
+    /// `CONFIG.double_tap_interval` milliseconds, a double tap cancels it.
+    fn defer_tap_action(&mut self, action: Action) {
+        if let Some(token) = self.tap_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        let timer = Timer::from_duration(Duration::from_millis(CONFIG.double_tap_interval));
+        self.tap_timer = self
+            .loop_handle
+            .insert_source(timer, move |_, _, data| {
+                data.state.tap_timer = None;
+                data.state.handle_action(action.clone());
+                TimeoutAction::Drop
+            })
+            .ok();
     }
 
     pub fn handle_action(&mut self, action: Action) {
         match action {
-            Action::Quit => self.loop_signal.stop(),
-            Action::Debug => todo!(),
+            Action::Quit => {
+                let timeout = Duration::from_millis(CONFIG.quit_confirm_timeout);
+                let confirmed = self
+                    .quit_requested
+                    .take()
+                    .is_some_and(|requested| requested.elapsed() < timeout);
+                if !CONFIG.confirm_quit || confirmed {
+                    self.loop_signal.stop();
+                } else {
+                    self.quit_requested = Some(Instant::now());
+                    info!(
+                        "Quit requested, quit again within {}ms to confirm",
+                        CONFIG.quit_confirm_timeout
+                    );
+                    // hides the message again
+                    let _ = self.loop_handle.insert_source(
+                        Timer::from_duration(timeout),
+                        |_, _, data| {
+                            data.state.quit_requested = None;
+                            TimeoutAction::Drop
+                        },
+                    );
+                }
+            }
+            Action::Debug => {
+                let snapshot = self.debug_snapshot();
+                info!("{}", snapshot);
+                if let Some(path) = &CONFIG.debug_dump_path {
+                    if let Err(err) = std::fs::write(path, &snapshot) {
+                        warn!("Failed to write state snapshot to {}: {}", path, err);
+                    }
+                }
+            }
+            Action::ReloadConfig => self.reload_config(),
             Action::Close => {
-                if let Some(d) = self
-                    .workspaces
-                    .current()
-                    .window_under(self.pointer_location)
-                {
-                    d.0.toplevel().send_close()
+                // keyboard users expect the focused window to close, wherever the pointer is
+                let window = self.focused_window().or_else(|| {
+                    self.workspaces
+                        .current()
+                        .window_under(self.pointer_location)
+                        .map(|(w, _)| w.clone())
+                });
+                if let Some(window) = window {
+                    window.toplevel().send_close()
                 }
             }
             Action::Workspace(id) => {
-                self.workspaces.activate(id);
-                self.set_input_focus_auto();
+                let carried = self
+                    .focused_window()
+                    .filter(|w| self.carry_window && self.workspaces.current().contains_window(w));
+                match carried {
+                    Some(window) if id != self.workspaces.current => {
+                        self.workspaces.move_window_to_workspace(&window, id);
+                        self.workspaces.activate(id);
+                        self.set_input_focus(FocusTarget::Window(window));
+                    }
+                    _ => {
+                        self.workspaces.activate(id);
+                        self.set_input_focus_auto();
+                    }
+                }
+            }
+            Action::NextWorkspace => {
+                let current = self.workspaces.current;
+                let ids: Vec<u8> = self.workspaces.enumerate().map(|(id, _)| id).collect();
+                if let Some(id) = ids.iter().find(|id| **id > current).or(ids.first()) {
+                    self.handle_action(Action::Workspace(*id));
+                }
+            }
+            Action::PreviousWorkspace => {
+                let current = self.workspaces.current;
+                let ids: Vec<u8> = self.workspaces.enumerate().map(|(id, _)| id).collect();
+                if let Some(id) = ids.iter().rev().find(|id| **id < current).or(ids.last()) {
+                    self.handle_action(Action::Workspace(*id));
+                }
+            }
+            // these need the pressed button and are only handled in mousebindings
+            Action::MoveGrab | Action::ResizeGrab => {}
+            Action::ToggleCarryWindow => {
+                self.carry_window = !self.carry_window;
             }
             Action::MoveWindow(id) => {
                 let window = self
@@ -224,23 +1303,396 @@ impl<BackendData: Backend> MagmaState<BackendData> {
 
                 if let Some(window) = window {
                     self.workspaces.move_window_to_workspace(&window, id);
+                    // the moved window may have had the focus, which stays on this workspace
+                    self.set_input_focus_auto();
                 }
             }
             Action::MoveAndSwitch(u8) => {
                 self.handle_action(Action::MoveWindow(u8));
                 self.handle_action(Action::Workspace(u8));
             }
-            Action::ToggleWindowFloating => todo!(),
+            Action::SwapWorkspaces(a, b) => {
+                self.workspaces.swap_workspaces(a, b);
+                if self.workspaces.current == a || self.workspaces.current == b {
+                    self.set_input_focus_auto();
+                }
+            }
+            Action::RenameWorkspace(name) => {
+                let id = self.workspaces.current;
+                let name = Some(name).filter(|name| !name.is_empty());
+                if let Err(other) = self.workspaces.rename(id, name.clone()) {
+                    warn!(
+                        "Workspace {} is already called {:?}, not renaming workspace {}",
+                        other, name, id
+                    );
+                }
+            }
+            Action::Fullscreen => {
+                let Some(window) = self.focused_window() else {
+                    return;
+                };
+                let area = self
+                    .active_output()
+                    .and_then(|o| self.workspaces.current().output_geometry(&o));
+                let workspace = self.workspaces.current_mut();
+                let fullscreen = workspace
+                    .magmawindow(&window)
+                    .is_some_and(|w| w.borrow().unfullscreened.is_some());
+                workspace.set_fullscreen(&window, if fullscreen { None } else { area });
+            }
+            Action::ToggleWindowFloating => {
+                let pos = self.pointer_location;
+                let workspace = self.workspaces.current_mut();
+                let Some(magmawindow) = workspace.magmawindow_under(pos) else {
+                    return;
+                };
+                let window = magmawindow.borrow().window.clone();
+                if magmawindow.borrow().floating {
+                    // the window takes over the place of the tiled window below the pointer
+                    let below = workspace
+                        .magmawindows()
+                        .find(|w| !w.floating && w.rec.to_f64().contains(pos))
+                        .map(|w| w.window.clone());
+                    workspace.set_floating(&window, false);
+                    if let Some(below) = below {
+                        workspace.swap_windows(&window, &below);
+                    }
+                } else {
+                    workspace.set_floating(&window, true);
+                    let mut magmawindow = magmawindow.borrow_mut();
+                    if magmawindow.rec.is_empty() {
+                        if let Some(output) = workspace.outputs().next() {
+                            let mut zone = layer_map_for_output(output).non_exclusive_zone();
+                            zone.loc += output.current_location();
+                            magmawindow.rec.size = magmawindow
+                                .clamp_size(Size::from((zone.size.w / 2, zone.size.h / 2)));
+                            magmawindow.center_in(zone);
+                        }
+                    }
+                    let size = magmawindow.rec.size;
+                    window.toplevel().with_pending_state(|state| {
+                        state.size = Some(size);
+                    });
+                    window.toplevel().send_configure();
+                }
+            }
+            Action::MoveFocus(direction) => {
+                if let Some(window) = self
+                    .focused_window()
+                    .and_then(|w| self.workspaces.current().window_in_direction(&w, direction))
+                {
+                    self.set_input_focus(FocusTarget::Window(window));
+                }
+            }
+            Action::SwapWithMaster => {
+                let master = self.workspaces.current().master();
+                if let (Some(window), Some(master)) = (self.focused_window(), master) {
+                    if window != master {
+                        self.workspaces.current_mut().swap_windows(&window, &master);
+                        self.set_input_focus(FocusTarget::Window(window));
+                    }
+                }
+            }
+            Action::SwapWindow(direction) => {
+                if let Some(window) = self.focused_window() {
+                    let workspace = self.workspaces.current_mut();
+                    if let Some(neighbor) = workspace.window_in_direction(&window, direction) {
+                        // floating windows are not part of the layout, swap_windows ignores them
+                        workspace.swap_windows(&window, &neighbor);
+                        self.set_input_focus(FocusTarget::Window(window));
+                    }
+                }
+            }
+            Action::FocusNextFloating => {
+                // raising the lowest floating window cycles through all of them
+                let workspace = self.workspaces.current_mut();
+                if let Some(window) = workspace
+                    .magmawindows()
+                    .filter(|w| w.floating)
+                    .last()
+                    .map(|w| w.window.clone())
+                {
+                    workspace.raise_window(&window);
+                    self.set_input_focus(FocusTarget::Window(window));
+                }
+            }
+            Action::FocusOutputUnderPointer => {
+                if let Some(output) = self
+                    .workspaces
+                    .outputs()
+                    .find(|o| {
+                        self.workspaces
+                            .output_geometry(o)
+                            .is_some_and(|geo| geo.to_f64().contains(self.pointer_location))
+                    })
+                    .cloned()
+                {
+                    self.focus_output(output);
+                }
+            }
+            Action::FocusOutput(direction) => {
+                let workspaces = &self.workspaces;
+                let current = self
+                    .active_output()
+                    .and_then(|o| workspaces.output_geometry(&o));
+                if let Some(current) = current {
+                    let center = |geo: Rectangle<i32, Logical>| {
+                        (geo.loc.x + geo.size.w / 2, geo.loc.y + geo.size.h / 2)
+                    };
+                    let (x, y) = center(current);
+                    // the closest output whose center lies in the given direction
+                    let output = workspaces
+                        .outputs()
+                        .filter_map(|o| Some((o, center(workspaces.output_geometry(o)?))))
+                        .filter(|(_, (ox, oy))| match direction {
+                            Direction::Left => *ox < x,
+                            Direction::Right => *ox > x,
+                            Direction::Up => *oy < y,
+                            Direction::Down => *oy > y,
+                        })
+                        .min_by_key(|(_, (ox, oy))| (ox - x).abs() + (oy - y).abs())
+                        .map(|(o, _)| o.clone());
+                    if let Some(output) = output {
+                        self.focus_output(output);
+                    }
+                }
+            }
+            Action::ToggleAlwaysOnTop => {
+                let focused = self.focused_window();
+                let workspace = self.workspaces.current_mut();
+                if let Some((window, magmawindow)) =
+                    focused.and_then(|w| Some((w.clone(), workspace.magmawindow(&w)?)))
+                {
+                    let always_on_top = !magmawindow.borrow().always_on_top;
+                    workspace.set_always_on_top(&window, always_on_top);
+                }
+            }
+            Action::FocusUrgent => {
+                let urgent = self
+                    .workspaces
+                    .enumerate()
+                    .flat_map(|(id, workspace)| {
+                        workspace
+                            .magmawindows()
+                            .filter_map(move |w| Some((w.urgent?, id, w.window.clone())))
+                    })
+                    .min_by_key(|(since, _, _)| *since);
+                if let Some((_, id, window)) = urgent {
+                    self.workspaces.activate(id);
+                    self.set_input_focus(FocusTarget::Window(window));
+                }
+            }
+            Action::FocusLastWindow => {
+                let focused = self.focused_window();
+                // hidden scratchpad windows stay in the history but can't be focused
+                let last = self
+                    .focus_history
+                    .iter()
+                    .rev()
+                    .filter(|w| Some(*w) != focused.as_ref())
+                    .find_map(|w| Some((self.workspaces.workspace_id(w)?, w.clone())));
+                if let Some((id, window)) = last {
+                    if id != self.workspaces.current {
+                        self.workspaces.activate(id);
+                    }
+                    self.set_input_focus(FocusTarget::Window(window));
+                }
+            }
+            Action::MoveToScratchpad => {
+                if let Some(window) = self.focused_window() {
+                    self.hide_in_scratchpad(&window);
+                }
+            }
+            Action::ShowScratchpad => {
+                if let Some(window) = self.scratchpad_shown.clone() {
+                    match self.workspaces.workspace_id(&window) {
+                        // shown on the current workspace, so the toggle hides it
+                        Some(id) if id == self.workspaces.current => {
+                            self.hide_in_scratchpad(&window)
+                        }
+                        Some(_) => {
+                            let current = self.workspaces.current;
+                            self.workspaces.move_window_to_workspace(&window, current);
+                            self.workspaces.current_mut().raise_window(&window);
+                            self.set_input_focus(FocusTarget::Window(window));
+                        }
+                        None => self.scratchpad_shown = None,
+                    }
+                    return;
+                }
+                let Some(magmawindow) = self.scratchpad.pop() else {
+                    return;
+                };
+                {
+                    let mut magmawindow = magmawindow.borrow_mut();
+                    magmawindow.floating = true;
+                    if let Some(output) = self.active_output() {
+                        let mut zone = layer_map_for_output(&output).non_exclusive_zone();
+                        zone.loc += output.current_location();
+                        magmawindow.center_in(zone);
+                    }
+                }
+                let window = magmawindow.borrow().window.clone();
+                let workspace = self.workspaces.current_mut();
+                workspace.add_window(magmawindow);
+                workspace.raise_window(&window);
+                self.scratchpad_shown = Some(window.clone());
+                self.set_input_focus(FocusTarget::Window(window));
+            }
+            Action::RaiseWindow | Action::LowerWindow => {
+                let focused = self.focused_window();
+                let workspace = self.workspaces.current_mut();
+                if let Some(window) = focused.filter(|w| {
+                    workspace
+                        .magmawindow(w)
+                        .is_some_and(|w| w.borrow().floating)
+                }) {
+                    if action == Action::RaiseWindow {
+                        workspace.raise_window(&window);
+                    } else {
+                        workspace.lower_window(&window);
+                    }
+                }
+            }
+            Action::TogglePassthrough => {
+                // a client inhibiting shortcuts is overruled first
+                let seat = self.seat.clone();
+                if let Some(inhibitor) = self.shortcuts_inhibitor(&seat) {
+                    inhibitor.inactivate();
+                    info!("Keyboard shortcuts inhibitor of the focused window disabled");
+                    return;
+                }
+
+                self.passthrough = !self.passthrough;
+                if self.passthrough {
+                    info!("Keyboard passthrough enabled, all keys are sent to the focused window");
+                } else {
+                    info!("Keyboard passthrough disabled");
+                }
+            }
+            Action::ToggleConfinePointer => {
+                self.confine_pointer = !self.confine_pointer;
+                self.pointer_location = self.clamp_coords(self.pointer_location);
+            }
+            Action::CenterWindow => {
+                let workspace = self.workspaces.current();
+                if let (Some(window), Some(output)) = (
+                    self.focused_window()
+                        .and_then(|w| workspace.magmawindow(&w))
+                        .filter(|w| w.borrow().floating),
+                    self.active_output(),
+                ) {
+                    let mut zone = layer_map_for_output(&output).non_exclusive_zone();
+                    zone.loc += output.current_location();
+                    window.borrow_mut().center_in(zone);
+                }
+            }
+            Action::ToggleFocusMode => {
+                self.focus_mode = !self.focus_mode;
+                if self.focus_mode {
+                    let focused = self.focused_window();
+                    self.workspaces.current_mut().set_focused_only(focused);
+                } else {
+                    for workspace in self.workspaces.iter() {
+                        workspace.set_focused_only(None);
+                    }
+                }
+            }
+            Action::ToggleGaps => self.workspaces.current_mut().toggle_gaps(),
+            Action::IncreaseGaps(amount) => self.workspaces.current_mut().change_gaps(amount),
+            Action::DecreaseGaps(amount) => self.workspaces.current_mut().change_gaps(-amount),
+            Action::ResizeWindow { direction, amount } => {
+                if let Some(window) = self.focused_window() {
+                    if let Some(workspace) = self.workspaces.workspace_from_window(&window) {
+                        workspace.resize_window(&window, direction, amount);
+                    }
+                }
+            }
+            Action::CycleLayout => self.workspaces.current_mut().cycle_layout(),
             Action::Spawn(command) => {
-                if let Err(err) = std::process::Command::new("/bin/sh")
-                    .arg("-c")
-                    .arg(command.clone())
-                    .spawn()
+                if self.spawn_cooled_down(&command) {
+                    self.spawn_with_activation(&command);
+                }
+            }
+            Action::SpawnOnWorkspace(id, command) => {
+                if !self.spawn_cooled_down(&command) {
+                    return;
+                }
+                if let Some(pid) = self.spawn_with_activation(&command) {
+                    self.spawn_workspaces
+                        .retain(|_, (_, spawned)| spawned.elapsed() < SPAWN_WORKSPACE_TIMEOUT);
+                    self.spawn_workspaces.insert(pid, (id, Instant::now()));
+                }
+            }
+            Action::FocusOrSpawn(app_id, command) => {
+                match self
+                    .workspaces
+                    .find_window(|w| w.app_id().as_deref() == Some(app_id.as_str()))
                 {
-                    info!("{} {} {}", err, "Failed to spawn \"{}\"", command);
+                    Some((id, window)) => {
+                        // activated directly, Workspace would take a carried window along
+                        if id != self.workspaces.current {
+                            self.workspaces.activate(id);
+                        }
+                        self.set_input_focus(FocusTarget::Window(window));
+                    }
+                    None => self.handle_action(Action::Spawn(command)),
                 }
             }
             Action::VTSwitch(_) => todo!(),
         }
     }
+
+    /// Whether `command` may be started again, holding a spawn binding should not start the
+    /// command over and over.
+    fn spawn_cooled_down(&mut self, command: &SpawnCommand) -> bool {
+        let cooldown = Duration::from_millis(CONFIG.spawn_cooldown);
+        if self
+            .last_spawn
+            .get(command.command())
+            .is_some_and(|last| last.elapsed() < cooldown)
+        {
+            return false;
+        }
+        self.last_spawn
+            .insert(command.command().to_string(), Instant::now());
+        true
+    }
+
+    /// Run `command` with an activation token, so its window is focused once it maps.
+    fn spawn_with_activation(&mut self, command: &SpawnCommand) -> Option<u32> {
+        let (token, _) = self.xdg_activation_state.create_external_token(None);
+        let token = token.as_str().to_string();
+        let pid = self.spawn(command, Some(&token))?;
+        self.spawn_tokens
+            .retain(|(_, created)| created.elapsed() < ACTIVATION_TIMEOUT);
+        self.spawn_tokens.push((token, Instant::now()));
+        Some(pid)
+    }
+}
+
+/// Size of the area in each output corner that activates a hot corner
+const HOT_CORNER_SIZE: f64 = 2.0;
+
+fn corner_at(geo: Rectangle<i32, Logical>, pos: Point<f64, Logical>) -> Option<Corner> {
+    let geo = geo.to_f64();
+    // the pointer can rest on the right and bottom edge, so these are included
+    if pos.x < geo.loc.x
+        || pos.y < geo.loc.y
+        || pos.x > geo.loc.x + geo.size.w
+        || pos.y > geo.loc.y + geo.size.h
+    {
+        return None;
+    }
+    let left = pos.x < geo.loc.x + HOT_CORNER_SIZE;
+    let right = pos.x > geo.loc.x + geo.size.w - HOT_CORNER_SIZE;
+    let top = pos.y < geo.loc.y + HOT_CORNER_SIZE;
+    let bottom = pos.y > geo.loc.y + geo.size.h - HOT_CORNER_SIZE;
+    match (left, right, top, bottom) {
+        (true, _, true, _) => Some(Corner::TopLeft),
+        (_, true, true, _) => Some(Corner::TopRight),
+        (true, _, _, true) => Some(Corner::BottomLeft),
+        (_, true, _, true) => Some(Corner::BottomRight),
+        _ => None,
+    }
 }
@@ -1,32 +1,67 @@
+use std::{
+    os::fd::{AsRawFd, OwnedFd},
+    time::{Duration, Instant},
+};
+
 use smithay::{
-    backend::renderer::utils::on_commit_buffer_handler,
-    delegate_compositor, delegate_data_device, delegate_layer_shell, delegate_output,
-    delegate_primary_selection, delegate_seat, delegate_shm,
+    backend::{input::InputEvent, renderer::utils::on_commit_buffer_handler},
+    delegate_compositor, delegate_data_device, delegate_fractional_scale, delegate_idle_inhibit,
+    delegate_keyboard_shortcuts_inhibit, delegate_layer_shell, delegate_output,
+    delegate_pointer_constraints, delegate_pointer_gestures, delegate_presentation,
+    delegate_primary_selection, delegate_relative_pointer, delegate_seat, delegate_shm,
+    delegate_tablet_manager, delegate_viewporter, delegate_xdg_activation, delegate_xdg_foreign,
     desktop::{layer_map_for_output, LayerSurface},
-    input::{SeatHandler, SeatState},
+    input::{keyboard::xkb, pointer::PointerHandle, Seat, SeatHandler, SeatState},
     output::Output,
-    reexports::wayland_server::{
-        protocol::{wl_output::WlOutput, wl_surface::WlSurface},
-        Resource,
+    reexports::{
+        wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+        wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_source_v1::ZwlrDataControlSourceV1,
+        wayland_server::{
+            protocol::{wl_data_source::WlDataSource, wl_output::WlOutput, wl_surface::WlSurface},
+            Client, Resource,
+        },
     },
     wayland::{
         buffer::BufferHandler,
-        compositor::{get_parent, is_sync_subsurface, CompositorHandler, CompositorState},
+        compositor::{
+            get_parent, is_sync_subsurface, with_states, CompositorHandler, CompositorState,
+        },
         data_device::{
-            set_data_device_focus, ClientDndGrabHandler, DataDeviceHandler, ServerDndGrabHandler,
+            request_data_device_client_selection, set_data_device_focus, with_source_metadata,
+            ClientDndGrabHandler, DataDeviceHandler, ServerDndGrabHandler,
+        },
+        fractional_scale::{with_fractional_scale, FractionalScaleHandler},
+        idle_inhibit::IdleInhibitHandler,
+        keyboard_shortcuts_inhibit::{
+            KeyboardShortcutsInhibitHandler, KeyboardShortcutsInhibitState,
+            KeyboardShortcutsInhibitor,
+        },
+        pointer_constraints::{with_pointer_constraint, PointerConstraintsHandler},
+        primary_selection::{
+            request_primary_client_selection, set_primary_focus,
+            with_source_metadata as with_primary_source_metadata, PrimarySelectionHandler,
         },
-        primary_selection::{set_primary_focus, PrimarySelectionHandler},
         seat::WaylandFocus,
         shell::wlr_layer::{
             Layer, LayerSurface as WlrLayerSurface, WlrLayerShellHandler, WlrLayerShellState,
         },
         shm::{ShmHandler, ShmState},
+        xdg_activation::{
+            XdgActivationHandler, XdgActivationState, XdgActivationToken, XdgActivationTokenData,
+        },
+        xdg_foreign::{XdgForeignHandler, XdgForeignState},
     },
 };
+use tracing::{info, warn};
 
 use crate::{
-    state::{Backend, MagmaState},
-    utils::{focus::FocusTarget, tiling::bsp_update_layout},
+    delegate_data_control, delegate_virtual_keyboard, delegate_virtual_pointer,
+    protocols::{
+        data_control::{device::source_mime_types, DataControlHandler, DataControlState},
+        virtual_input::{VirtualInput, VirtualInputHandler},
+    },
+    state::{Backend, MagmaState, CONFIG},
+    utils::{focus::FocusTarget, tiling::update_layout},
 };
 
 pub mod input;
@@ -53,7 +88,18 @@ impl<BackendData: Backend> CompositorHandler for MagmaState<BackendData> {
             }
         };
         self.popup_manager.commit(surface);
-        xdg_shell::handle_commit(&self.workspaces, surface, &self.popup_manager);
+        if let Some((window, switch)) =
+            xdg_shell::handle_commit(&mut self.workspaces, surface, &self.popup_manager)
+        {
+            if switch {
+                let id = self.workspaces.workspace_id(&window).unwrap();
+                self.workspaces.activate(id);
+                self.set_input_focus(FocusTarget::Window(window));
+            } else {
+                // the window was focused when it was created on the current workspace
+                self.set_input_focus_auto();
+            }
+        }
     }
 }
 
@@ -85,9 +131,17 @@ impl<BackendData: Backend> SeatHandler for MagmaState<BackendData> {
 
     fn cursor_image(
         &mut self,
-        _seat: &smithay::input::Seat<Self>,
-        _image: smithay::input::pointer::CursorImageStatus,
+        seat: &smithay::input::Seat<Self>,
+        image: smithay::input::pointer::CursorImageStatus,
     ) {
+        if seat.name() == self.pointer_seat {
+            self.cursor_status = image;
+        } else {
+            self.seat_pointers
+                .entry(seat.name().to_string())
+                .or_insert((self.pointer_location, image.clone()))
+                .1 = image;
+        }
     }
     fn focus_changed(&mut self, seat: &smithay::input::Seat<Self>, focused: Option<&FocusTarget>) {
         let dh = &self.dh;
@@ -132,9 +186,45 @@ impl<BackendData: Backend> DataDeviceHandler for MagmaState<BackendData> {
     fn data_device_state(&self) -> &smithay::wayland::data_device::DataDeviceState {
         &self.data_device_state
     }
+
+    fn new_selection(&mut self, source: Option<WlDataSource>) {
+        self.data_control_state.set_source(None, false);
+        let Some(source) = source else {
+            self.clipboard.clear();
+            self.data_control_state
+                .set_selection::<Self>(&self.dh, None, false);
+            return;
+        };
+        let mime_types = with_source_metadata(&source, |metadata| metadata.mime_types.clone())
+            .unwrap_or_default();
+        // read the selection right away, the source is gone once its client exits
+        for (mime_type, fd) in self.clipboard.start(mime_types, &self.clipboard_sender) {
+            if let Err(err) = request_data_device_client_selection(&self.seat, mime_type, fd) {
+                warn!(?err, "Failed to request the selection");
+            }
+        }
+    }
+
+    fn send_selection(&mut self, mime_type: String, fd: OwnedFd) {
+        self.clipboard.send(&mime_type, fd);
+    }
 }
 
-impl<BackendData: Backend> ClientDndGrabHandler for MagmaState<BackendData> {}
+impl<BackendData: Backend> ClientDndGrabHandler for MagmaState<BackendData> {
+    fn started(
+        &mut self,
+        _source: Option<WlDataSource>,
+        icon: Option<WlSurface>,
+        _seat: smithay::input::Seat<Self>,
+    ) {
+        // smithay runs the grab and sends the events, only the icon is left to draw
+        self.dnd_icon = icon;
+    }
+
+    fn dropped(&mut self, _seat: smithay::input::Seat<Self>) {
+        self.dnd_icon = None;
+    }
+}
 impl<BackendData: Backend> ServerDndGrabHandler for MagmaState<BackendData> {}
 
 delegate_data_device!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
@@ -145,15 +235,125 @@ impl<BackendData: Backend> PrimarySelectionHandler for MagmaState<BackendData> {
     ) -> &smithay::wayland::primary_selection::PrimarySelectionState {
         &self.primary_selection_state
     }
+
+    fn new_selection(&mut self, source: Option<ZwpPrimarySelectionSourceV1>) {
+        self.data_control_state.set_source(None, true);
+        let Some(source) = source else {
+            self.primary_clipboard.clear();
+            self.data_control_state
+                .set_selection::<Self>(&self.dh, None, true);
+            return;
+        };
+        // the primary selection changes with every text selection, so it is only read
+        // while someone wants the copy
+        if !self.data_control_state.has_devices() {
+            self.primary_clipboard.clear();
+            return;
+        }
+        let mime_types =
+            with_primary_source_metadata(&source, |metadata| metadata.mime_types.clone())
+                .unwrap_or_default();
+        // the client keeps the selection, the copy is only offered to data control clients
+        for (mime_type, fd) in self
+            .primary_clipboard
+            .start(mime_types, &self.primary_clipboard_sender)
+        {
+            if let Err(err) = request_primary_client_selection(&self.seat, mime_type, fd) {
+                warn!(?err, "Failed to request the primary selection");
+            }
+        }
+    }
+
+    fn send_selection(&mut self, mime_type: String, fd: OwnedFd) {
+        self.primary_clipboard.send(&mime_type, fd);
+    }
 }
 
 delegate_primary_selection!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Wlr Data Control
+//
+
+impl<BackendData: Backend> DataControlHandler for MagmaState<BackendData> {
+    fn data_control_state(&mut self) -> &mut DataControlState {
+        &mut self.data_control_state
+    }
+
+    fn selection_mime_types(&self, primary: bool) -> Option<Vec<String>> {
+        if primary {
+            self.primary_clipboard.mime_types()
+        } else {
+            self.clipboard.mime_types()
+        }
+    }
+
+    fn new_selection(&mut self, source: Option<ZwlrDataControlSourceV1>, primary: bool) {
+        self.data_control_state.set_source(source.clone(), primary);
+        let (clipboard, sender) = if primary {
+            (&mut self.primary_clipboard, &self.primary_clipboard_sender)
+        } else {
+            (&mut self.clipboard, &self.clipboard_sender)
+        };
+        let Some(source) = source else {
+            clipboard.clear();
+            self.data_control_state
+                .set_selection::<Self>(&self.dh, None, primary);
+            return;
+        };
+        // the selection is taken over once the source has been read
+        for (mime_type, fd) in clipboard.start(source_mime_types(&source), sender) {
+            source.send(mime_type, fd.as_raw_fd());
+        }
+    }
+
+    fn send_selection(&mut self, mime_type: String, fd: OwnedFd, primary: bool) {
+        if primary {
+            self.primary_clipboard.send(&mime_type, fd);
+        } else {
+            self.clipboard.send(&mime_type, fd);
+        }
+    }
+}
+
+delegate_data_control!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
 //
 // Wl Output & Xdg Output
 //
 
 delegate_output!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
 
+//
+// Fractional Scale & Viewporter
+//
+
+impl<BackendData: Backend> FractionalScaleHandler for MagmaState<BackendData> {
+    fn new_fractional_scale(&mut self, surface: WlSurface) {
+        // start out with the focused output, the renderer corrects it once the
+        // surface is shown on another one
+        let scale = self
+            .workspaces
+            .current()
+            .outputs()
+            .next()
+            .map(|o| o.current_scale().fractional_scale())
+            .unwrap_or(1.0);
+        with_states(&surface, |states| {
+            with_fractional_scale(states, |fractional| fractional.set_preferred_scale(scale))
+        });
+    }
+}
+
+delegate_fractional_scale!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+delegate_viewporter!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Presentation Time
+//
+
+delegate_presentation!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
 impl<BackendData: Backend> WlrLayerShellHandler for MagmaState<BackendData> {
     fn shell_state(&mut self) -> &mut WlrLayerShellState {
         &mut self.layer_shell_state
@@ -163,20 +363,31 @@ impl<BackendData: Backend> WlrLayerShellHandler for MagmaState<BackendData> {
         &mut self,
         surface: WlrLayerSurface,
         output: Option<WlOutput>,
-        _layer: Layer,
+        layer: Layer,
         namespace: String,
     ) {
-        let output = output
+        let output = match output
             .as_ref()
             .and_then(Output::from_resource)
-            .unwrap_or_else(|| self.workspaces.current().outputs().next().unwrap().clone());
+            .or_else(|| self.workspaces.current().outputs().next().cloned())
+        {
+            Some(output) => output,
+            None => {
+                // there is no output to show the layer surface on
+                surface.send_close();
+                return;
+            }
+        };
         let mut map = layer_map_for_output(&output);
         let layer_surface = LayerSurface::new(surface, namespace);
         map.map_layer(&layer_surface).unwrap();
-        self.set_input_focus(FocusTarget::LayerSurface(layer_surface));
         drop(map);
+        // wallpapers and the like below the windows never take the keyboard
+        if matches!(layer, Layer::Top | Layer::Overlay) {
+            self.set_input_focus(FocusTarget::LayerSurface(layer_surface));
+        }
         for workspace in self.workspaces.iter() {
-            bsp_update_layout(workspace);
+            update_layout(workspace);
         }
     }
 
@@ -193,9 +404,241 @@ impl<BackendData: Backend> WlrLayerShellHandler for MagmaState<BackendData> {
         }
         self.set_input_focus_auto();
         for workspace in self.workspaces.iter() {
-            bsp_update_layout(workspace);
+            update_layout(workspace);
         }
     }
 }
 
 delegate_layer_shell!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+impl<BackendData: Backend> KeyboardShortcutsInhibitHandler for MagmaState<BackendData> {
+    fn keyboard_shortcuts_inhibit_state(&mut self) -> &mut KeyboardShortcutsInhibitState {
+        &mut self.keyboard_shortcuts_inhibit_state
+    }
+
+    fn new_inhibitor(&mut self, inhibitor: KeyboardShortcutsInhibitor) {
+        // the TogglePassthrough binding stays available to take the keyboard back
+        inhibitor.activate();
+    }
+}
+
+delegate_keyboard_shortcuts_inhibit!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Xdg Foreign
+//
+
+impl<BackendData: Backend> XdgForeignHandler for MagmaState<BackendData> {
+    fn xdg_foreign_state(&mut self) -> &mut XdgForeignState {
+        &mut self.xdg_foreign_state
+    }
+}
+
+delegate_xdg_foreign!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Xdg Activation
+//
+
+/// How long an activation token stays valid
+pub const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl<BackendData: Backend> XdgActivationHandler for MagmaState<BackendData> {
+    fn activation_state(&mut self) -> &mut XdgActivationState {
+        &mut self.xdg_activation_state
+    }
+
+    fn request_activation(
+        &mut self,
+        token: XdgActivationToken,
+        token_data: XdgActivationTokenData,
+        surface: WlSurface,
+    ) {
+        // only spawned apps and clients that just got input from a seat may take the focus,
+        // anything else would steal it from what the user is doing
+        self.spawn_tokens
+            .retain(|(_, created)| created.elapsed() < ACTIVATION_TIMEOUT);
+        let valid = token_data.timestamp.elapsed() < ACTIVATION_TIMEOUT;
+        let spawned = self.spawn_tokens.iter().any(|(t, _)| t == token.as_str());
+        // the input has to have gone to the client that requested the token
+        let from_input = token_data
+            .serial
+            .as_ref()
+            .and_then(|(_, seat)| Seat::<Self>::from_resource(seat))
+            .is_some_and(|seat| {
+                let keyboard_focus = seat
+                    .get_keyboard()
+                    .and_then(|keyboard| keyboard.current_focus());
+                let pointer_focus = seat
+                    .get_pointer()
+                    .and_then(|pointer| pointer.current_focus());
+                [keyboard_focus, pointer_focus]
+                    .into_iter()
+                    .flatten()
+                    .any(|focus| {
+                        focus
+                            .wl_surface()
+                            .and_then(|surface| surface.client())
+                            .is_some_and(|client| Some(client.id()) == token_data.client_id)
+                    })
+            });
+        // spawned windows that map on another workspace don't pull the user over there
+        let elsewhere = self
+            .workspaces
+            .find_window(|w| w.toplevel().wl_surface() == &surface)
+            .is_some_and(|(id, _)| id != self.workspaces.current);
+        if !valid || !((spawned && !elsewhere) || from_input) {
+            // the window still gets marked, so it can be found with FocusUrgent
+            if let Some(magmawindow) = self
+                .workspaces
+                .find_window(|w| w.toplevel().wl_surface() == &surface)
+                .filter(|(_, window)| self.focused_window().as_ref() != Some(window))
+                .and_then(|(id, window)| self.workspaces.get(id)?.magmawindow(&window))
+            {
+                let mut magmawindow = magmawindow.borrow_mut();
+                if magmawindow.urgent.is_none() {
+                    magmawindow.urgent = Some(Instant::now());
+                }
+            }
+            info!("Denied an activation request, marked the window as urgent");
+            return;
+        }
+        self.spawn_tokens.retain(|(t, _)| t != token.as_str());
+
+        if let Some((id, window)) = self
+            .workspaces
+            .find_window(|w| w.toplevel().wl_surface() == &surface)
+        {
+            self.workspaces.activate(id);
+            self.set_input_focus(FocusTarget::Window(window));
+        }
+    }
+
+    fn destroy_activation(
+        &mut self,
+        token: XdgActivationToken,
+        _token_data: XdgActivationTokenData,
+        _surface: WlSurface,
+    ) {
+        self.spawn_tokens.retain(|(t, _)| t != token.as_str());
+    }
+}
+
+delegate_xdg_activation!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Idle Inhibit
+//
+
+impl<BackendData: Backend> IdleInhibitHandler for MagmaState<BackendData> {
+    fn inhibit(&mut self, surface: WlSurface) {
+        self.idle_inhibitors.push(surface);
+    }
+
+    fn uninhibit(&mut self, surface: WlSurface) {
+        self.idle_inhibitors.retain(|s| s != &surface);
+    }
+}
+
+delegate_idle_inhibit!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Pointer Constraints
+//
+
+impl<BackendData: Backend> PointerConstraintsHandler for MagmaState<BackendData> {
+    fn new_constraint(&mut self, surface: &WlSurface, pointer: &PointerHandle<Self>) {
+        // constraints only apply while the surface has the pointer focus
+        if pointer
+            .current_focus()
+            .and_then(|focus| focus.wl_surface())
+            .as_ref()
+            == Some(surface)
+        {
+            with_pointer_constraint(surface, pointer, |constraint| {
+                if let Some(constraint) = constraint {
+                    constraint.activate();
+                }
+            });
+        }
+    }
+}
+
+delegate_pointer_constraints!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Relative Pointer
+//
+
+delegate_relative_pointer!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Pointer Gestures
+//
+
+delegate_pointer_gestures!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Tablet Manager
+//
+
+delegate_tablet_manager!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+
+//
+// Virtual Keyboard and Pointer
+//
+
+impl<BackendData: Backend> VirtualInputHandler for MagmaState<BackendData> {
+    fn virtual_input_allowed(&self, client: &Client) -> bool {
+        let Ok(credentials) = client.get_credentials(&self.dh) else {
+            return false;
+        };
+        let Ok(name) = std::fs::read_to_string(format!("/proc/{}/comm", credentials.pid)) else {
+            return false;
+        };
+        let allowed = CONFIG
+            .virtual_input_clients
+            .iter()
+            .any(|c| c == name.trim_end());
+        if !allowed {
+            warn!(
+                "{} is not in virtual_input_clients, ignoring its virtual devices",
+                name.trim_end()
+            );
+        }
+        allowed
+    }
+
+    fn seat_keymap(&self) -> Option<xkb::Keymap> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let names = &CONFIG.xkb;
+        xkb::Keymap::new_from_names(
+            &context,
+            &names.rules,
+            &names.model,
+            &names.layout,
+            &names.variant,
+            names.options.clone(),
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        // like the seat keyboards, which fall back to the default keymap
+        .or_else(|| {
+            xkb::Keymap::new_from_names(
+                &context,
+                "",
+                "",
+                "",
+                "",
+                None,
+                xkb::KEYMAP_COMPILE_NO_FLAGS,
+            )
+        })
+    }
+
+    fn virtual_input(&mut self, event: InputEvent<VirtualInput>) {
+        self.process_input_event(event);
+    }
+}
+
+delegate_virtual_keyboard!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
+delegate_virtual_pointer!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
@@ -3,14 +3,18 @@ use smithay::{
     desktop::{
         PopupKind, PopupManager, WindowSurfaceType, {layer_map_for_output, Window},
     },
+    output::Output,
     reexports::{
         wayland_protocols::xdg::{
             decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
             shell::server::xdg_toplevel::State as ToplevelState,
         },
-        wayland_server::protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+        wayland_server::{
+            protocol::{wl_output::WlOutput, wl_seat::WlSeat, wl_surface::WlSurface},
+            Resource,
+        },
     },
-    utils::Serial,
+    utils::{Point, Serial, Size, SERIAL_COUNTER},
     wayland::{
         compositor::with_states,
         shell::{
@@ -27,10 +31,13 @@ use std::{cell::RefCell, rc::Rc, sync::Mutex};
 use tracing::warn;
 
 use crate::{
-    state::{Backend, MagmaState},
+    config::WindowRule,
+    state::{Backend, MagmaState, CONFIG, SPAWN_WORKSPACE_TIMEOUT},
     utils::{
         focus::FocusTarget,
-        workspace::{MagmaWindow, Workspaces},
+        tiling::update_layout,
+        window::WindowExt,
+        workspace::{MagmaWindow, Workspace, Workspaces},
     },
 };
 
@@ -40,28 +47,61 @@ impl<BackendData: Backend> XdgShellHandler for MagmaState<BackendData> {
     }
 
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
+        let spawn_workspace = self.take_spawn_workspace(&surface);
         let window = Window::new(surface);
-        self.workspaces
-            .current_mut()
-            .add_window(Rc::new(RefCell::new(MagmaWindow {
-                window: window.clone(),
-                rec: window.geometry(),
-            })));
-        self.set_input_focus(FocusTarget::Window(window));
+        let magmawindow = Rc::new(RefCell::new(MagmaWindow::new(window.clone())));
+        match spawn_workspace.filter(|id| *id != self.workspaces.current) {
+            Some(id) => self.workspaces.get_or_create(id).add_window(magmawindow),
+            None => {
+                self.workspaces.current_mut().add_window(magmawindow);
+                self.set_input_focus(FocusTarget::Window(window));
+            }
+        }
     }
     fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        self.focus_history.retain(|w| w.toplevel() != &surface);
+        if self
+            .scratchpad_shown
+            .as_ref()
+            .is_some_and(|w| w.toplevel() == &surface)
+        {
+            self.scratchpad_shown = None;
+        }
+        // hidden windows are not on any workspace
+        let hidden = self.scratchpad.len();
+        self.scratchpad
+            .retain(|w| w.borrow().window.toplevel() != &surface);
+        if self.scratchpad.len() != hidden {
+            return;
+        }
+
+        let parent = surface.parent();
         let window = self
             .workspaces
             .all_windows()
             .find(|w| w.toplevel() == &surface)
             .unwrap()
             .clone();
+        let focused = self.focused_window().as_ref() == Some(&window);
 
         self.workspaces
             .workspace_from_window(&window)
             .unwrap()
             .remove_window(&window);
-        self.set_input_focus_auto();
+        self.workspaces.remove_unused();
+
+        // closing a dialog gives the focus back to the window it belongs to
+        let parent = parent.and_then(|parent| {
+            self.workspaces
+                .all_windows()
+                .find(|w| w.toplevel().wl_surface() == &parent)
+                .map(|w| w.clone())
+        });
+        match parent {
+            Some(parent) => self.set_input_focus(FocusTarget::Window(parent)),
+            None if focused => self.focus_after_close(),
+            None => {}
+        }
     }
     fn new_popup(&mut self, surface: PopupSurface, positioner: PositionerState) {
         surface.with_pending_state(|state| {
@@ -72,18 +112,212 @@ impl<BackendData: Backend> XdgShellHandler for MagmaState<BackendData> {
         }
     }
 
+    fn maximize_request(&mut self, surface: ToplevelSurface) {
+        let Some(window) = self
+            .workspaces
+            .all_windows()
+            .find(|w| w.toplevel() == &surface)
+            .map(|w| w.clone())
+        else {
+            return;
+        };
+        let workspace = self.workspaces.workspace_from_window(&window).unwrap();
+        let magmawindow = workspace.magmawindow(&window).unwrap();
+        let Some(output) = workspace.outputs().next().cloned() else {
+            return;
+        };
+        if magmawindow.borrow().unmaximized.is_some() {
+            return;
+        }
+
+        // maximized windows float over the tiled ones, covering the work area
+        let floating = magmawindow.borrow().floating;
+        workspace.set_floating(&window, true);
+        let mut zone = layer_map_for_output(&output).non_exclusive_zone();
+        zone.loc += output.current_location();
+        let mut magmawindow = magmawindow.borrow_mut();
+        let title_bar_height = magmawindow.title_bar_height();
+        zone.loc.y += title_bar_height;
+        zone.size.h -= title_bar_height;
+        magmawindow.unmaximized = Some((magmawindow.rec, floating));
+        magmawindow.rec = zone;
+        surface.with_pending_state(|state| {
+            state.states.set(ToplevelState::Maximized);
+            state.size = Some(zone.size);
+        });
+        surface.send_configure();
+    }
+
+    fn unmaximize_request(&mut self, surface: ToplevelSurface) {
+        let Some(window) = self
+            .workspaces
+            .all_windows()
+            .find(|w| w.toplevel() == &surface)
+            .map(|w| w.clone())
+        else {
+            return;
+        };
+        let workspace = self.workspaces.workspace_from_window(&window).unwrap();
+        let magmawindow = workspace.magmawindow(&window).unwrap();
+        let Some((rec, floating)) = magmawindow.borrow_mut().unmaximized.take() else {
+            return;
+        };
+
+        surface.with_pending_state(|state| {
+            state.states.unset(ToplevelState::Maximized);
+        });
+        if floating {
+            magmawindow.borrow_mut().rec = rec;
+            surface.with_pending_state(|state| {
+                state.size = Some(rec.size);
+            });
+            surface.send_configure();
+        } else {
+            // re-tiling sends the new size
+            workspace.set_floating(&window, false);
+        }
+    }
+
+    fn fullscreen_request(&mut self, surface: ToplevelSurface, output: Option<WlOutput>) {
+        let Some(window) = self
+            .workspaces
+            .all_windows()
+            .find(|w| w.toplevel() == &surface)
+            .map(|w| w.clone())
+        else {
+            return;
+        };
+        let workspace = self.workspaces.workspace_from_window(&window).unwrap();
+        let area = output
+            .as_ref()
+            .and_then(Output::from_resource)
+            .or_else(|| workspace.outputs().next().cloned())
+            .and_then(|o| workspace.output_geometry(&o));
+        if area.is_some() {
+            workspace.set_fullscreen(&window, area);
+        }
+    }
+
+    fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self
+            .workspaces
+            .all_windows()
+            .find(|w| w.toplevel() == &surface)
+            .map(|w| w.clone())
+        {
+            let workspace = self.workspaces.workspace_from_window(&window).unwrap();
+            workspace.set_fullscreen(&window, None);
+        }
+    }
+
     fn grab(&mut self, _surface: PopupSurface, _seat: WlSeat, _serial: Serial) {
         // TODO popup grabs
     }
 }
 
+impl<BackendData: Backend> MagmaState<BackendData> {
+    /// Move the focus of a closed window to the most recently focused window of the current
+    /// workspace, or its topmost window, clearing it if the workspace is empty.
+    fn focus_after_close(&mut self) {
+        let current = self.workspaces.current;
+        let next = self
+            .focus_history
+            .iter()
+            .rev()
+            .find(|w| self.workspaces.workspace_id(w) == Some(current))
+            .cloned()
+            .or_else(|| {
+                self.workspaces
+                    .current()
+                    .windows()
+                    .next()
+                    .map(|w| w.clone())
+            });
+        match next {
+            Some(window) => self.set_input_focus(FocusTarget::Window(window)),
+            None => {
+                let keyboard = self.seat.get_keyboard().unwrap();
+                keyboard.set_focus(self, None, SERIAL_COUNTER.next_serial());
+            }
+        }
+    }
+
+    /// Workspace requested by SpawnOnWorkspace for the process owning `surface`
+    /// or one of its parent processes, only applied to its first window.
+    fn take_spawn_workspace(&mut self, surface: &ToplevelSurface) -> Option<u8> {
+        self.spawn_workspaces
+            .retain(|_, (_, spawned)| spawned.elapsed() < SPAWN_WORKSPACE_TIMEOUT);
+        if self.spawn_workspaces.is_empty() {
+            return None;
+        }
+        let client = surface.wl_surface().client()?;
+        let mut pid = client.get_credentials(&self.dh).ok()?.pid as u32;
+        loop {
+            if let Some((id, _)) = self.spawn_workspaces.remove(&pid) {
+                return Some(id);
+            }
+            pid = parent_pid(pid).filter(|ppid| *ppid > 1)?;
+        }
+    }
+}
+
+/// Center a dialog over its parent once its size is known, keeping the size the client chose
+/// unless it doesn't fit on the output.
+fn center_dialog(workspace: &Workspace, window: &Window) {
+    let Some(magmawindow) = workspace.magmawindow(window) else {
+        return;
+    };
+    let size = window.geometry().size;
+    if size.w <= 0 || size.h <= 0 {
+        return;
+    }
+    let Some(parent_rec) = magmawindow.borrow_mut().center_over.take() else {
+        return;
+    };
+    let zone = workspace.outputs().next().map(|output| {
+        let mut zone = layer_map_for_output(output).non_exclusive_zone();
+        zone.loc += output.current_location();
+        zone
+    });
+    let mut magmawindow = magmawindow.borrow_mut();
+    magmawindow.rec.size = size;
+    if let Some(zone) = zone.filter(|zone| size.w > zone.size.w || size.h > zone.size.h) {
+        magmawindow.rec.size = magmawindow.clamp_size(Size::from((
+            size.w.min(zone.size.w),
+            size.h.min(zone.size.h),
+        )));
+        let size = magmawindow.rec.size;
+        window.toplevel().with_pending_state(|state| {
+            state.size = Some(size);
+        });
+        window.toplevel().send_configure();
+    }
+    magmawindow.center_in(parent_rec);
+}
+
+/// Read the parent of a process from `/proc`.
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name may contain spaces, the fields after it don't
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
 delegate_xdg_shell!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
 
 // Should be called on `WlSurface::commit`
-pub fn handle_commit(workspaces: &Workspaces, surface: &WlSurface, popup_manager: &PopupManager) {
+// Returns a window moved to another workspace by `CONFIG.workspace_rules`
+// and whether to switch to that workspace
+pub fn handle_commit(
+    workspaces: &mut Workspaces,
+    surface: &WlSurface,
+    popup_manager: &PopupManager,
+) -> Option<(Window, bool)> {
+    let mut moved = None;
     if let Some(window) = workspaces
         .all_windows()
         .find(|w| w.toplevel().wl_surface() == surface)
+        .map(|w| w.clone())
     {
         let initial_configure_sent = with_states(surface, |states| {
             states
@@ -95,22 +329,119 @@ pub fn handle_commit(workspaces: &Workspaces, surface: &WlSurface, popup_manager
                 .initial_configure_sent
         });
         if !initial_configure_sent {
-            let toplevel = window.toplevel();
-            toplevel.with_pending_state(|state| {
-                state.states.set(ToplevelState::TiledLeft);
-                state.states.set(ToplevelState::TiledRight);
-                state.states.set(ToplevelState::TiledTop);
-                state.states.set(ToplevelState::TiledBottom);
+            // the app id is known by now, so the window can be sent to its assigned workspace
+            if let Some(rule) = window
+                .app_id()
+                .and_then(|app_id| CONFIG.workspace_rules.get(&app_id))
+            {
+                if workspaces.workspace_id(&window) != Some(rule.workspace) {
+                    workspaces.move_window_to_workspace(&window, rule.workspace);
+                    moved = Some((window.clone(), rule.switch));
+                }
+            }
+
+            // matching window rules apply in order, later ones overriding earlier ones
+            let (app_id, title) = (window.app_id(), window.title());
+            let rules: Vec<&WindowRule> = CONFIG
+                .window_rules
+                .iter()
+                .filter(|rule| rule.matches(app_id.as_deref(), title.as_deref()))
+                .collect();
+            if let Some(id) = rules.iter().rev().find_map(|rule| rule.workspace) {
+                if workspaces.workspace_id(&window) != Some(id) {
+                    workspaces.move_window_to_workspace(&window, id);
+                    moved = Some((window.clone(), false));
+                }
+            }
+            let rule_floating = rules.iter().rev().find_map(|rule| rule.floating);
+            let rule_size = rules.iter().rev().find_map(|rule| rule.size);
+            let rule_position = rules.iter().rev().find_map(|rule| rule.position);
+
+            // dialogs, including those of other clients like portals imported via xdg-foreign,
+            // float over their parent on its workspace instead of taking a tile
+            let parent_rec = window.toplevel().parent().and_then(|parent| {
+                let parent = workspaces
+                    .all_windows()
+                    .find(|w| w.toplevel().wl_surface() == &parent)
+                    .map(|w| w.clone())?;
+                let parent_id = workspaces.workspace_id(&parent)?;
+                if workspaces.workspace_id(&window) != Some(parent_id) {
+                    workspaces.move_window_to_workspace(&window, parent_id);
+                    moved = Some((window.clone(), false));
+                }
+                let rec = workspaces
+                    .workspace_from_window(&parent)?
+                    .magmawindow(&parent)?
+                    .borrow()
+                    .rec;
+                Some(rec)
             });
+            let workspace = workspaces.workspace_from_window(&window).unwrap();
+            let magmawindow = workspace.magmawindow(&window).unwrap();
+            if let Some(parent_rec) = parent_rec {
+                // the client picks the size of its dialog, which is only known once it commits
+                // a buffer, so it is centered over the parent then
+                workspace.set_floating(&window, true);
+                let mut magmawindow = magmawindow.borrow_mut();
+                magmawindow.center_over = Some(parent_rec);
+                window.toplevel().with_pending_state(|state| {
+                    state.size = None;
+                });
+            } else if rule_floating.unwrap_or_else(|| should_auto_float(&magmawindow.borrow())) {
+                // the size hints are known by now, so small utility windows can leave the tiling
+                workspace.set_floating(&window, true);
+                let zone = workspace.outputs().next().map(|output| {
+                    let mut zone = layer_map_for_output(output).non_exclusive_zone();
+                    zone.loc += output.current_location();
+                    zone
+                });
+                let mut magmawindow = magmawindow.borrow_mut();
+                let (_, max_size) = magmawindow.size_hints();
+                let size = match (rule_size, zone) {
+                    (Some(size), _) => Size::from(size),
+                    // windows floated by a rule may not have a max size
+                    (None, Some(zone)) if max_size.w == 0 || max_size.h == 0 => {
+                        Size::from((zone.size.w / 2, zone.size.h / 2))
+                    }
+                    _ => max_size,
+                };
+                magmawindow.rec.size = magmawindow.clamp_size(size);
+                if let Some(zone) = zone {
+                    match rule_position {
+                        Some(position) => magmawindow.rec.loc = zone.loc + Point::from(position),
+                        None => magmawindow.center_in(zone),
+                    }
+                }
+                let size = magmawindow.rec.size;
+                window.toplevel().with_pending_state(|state| {
+                    state.size = Some(size);
+                });
+            }
+
+            let toplevel = window.toplevel();
+            if !magmawindow.borrow().floating {
+                toplevel.with_pending_state(|state| {
+                    state.states.set(ToplevelState::TiledLeft);
+                    state.states.set(ToplevelState::TiledRight);
+                    state.states.set(ToplevelState::TiledTop);
+                    state.states.set(ToplevelState::TiledBottom);
+                });
+            }
             toplevel.send_configure();
+        } else if let Some(workspace) = workspaces.workspace_from_window(&window) {
+            center_dialog(workspace, &window);
         }
     }
 
-    if let Some(output) = workspaces.current().outputs().find(|o| {
-        let map = layer_map_for_output(o);
-        map.layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
-            .is_some()
-    }) {
+    if let Some(output) = workspaces
+        .outputs()
+        .find(|o| {
+            let map = layer_map_for_output(o);
+            map.layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+                .is_some()
+        })
+        .cloned()
+    {
         let initial_configure_sent = with_states(surface, |states| {
             states
                 .data_map
@@ -120,11 +451,13 @@ pub fn handle_commit(workspaces: &Workspaces, surface: &WlSurface, popup_manager
                 .unwrap()
                 .initial_configure_sent
         });
-        let mut map = layer_map_for_output(output);
+        let mut map = layer_map_for_output(&output);
+        let zone = map.non_exclusive_zone();
 
         // arrange the layers before sending the initial configure
         // to respect any size the client may have sent
         map.arrange();
+        let zone_changed = map.non_exclusive_zone() != zone;
         // send the initial configure if relevant
         if !initial_configure_sent {
             let layer = map
@@ -133,6 +466,14 @@ pub fn handle_commit(workspaces: &Workspaces, surface: &WlSurface, popup_manager
 
             layer.layer_surface().send_configure();
         }
+        drop(map);
+
+        // windows must not overlap a bar that reserved an exclusive zone
+        if zone_changed {
+            for workspace in workspaces.iter() {
+                update_layout(workspace);
+            }
+        }
     };
 
     if let Some(popup) = popup_manager.find_popup(surface) {
@@ -152,6 +493,17 @@ pub fn handle_commit(workspaces: &Workspaces, surface: &WlSurface, popup_manager
             popup.send_configure().expect("initial configure failed");
         }
     };
+
+    moved
+}
+
+/// Whether a window is too small or fixed in size to be tiled.
+fn should_auto_float(window: &MagmaWindow) -> bool {
+    let (min_size, max_size) = window.size_hints();
+    let fixed_size = max_size.w > 0 && max_size.h > 0 && min_size == max_size;
+    let (float_w, float_h) = CONFIG.float_below;
+    let small = max_size.w > 0 && max_size.h > 0 && max_size.w < float_w && max_size.h < float_h;
+    (CONFIG.float_fixed_size && fixed_size) || small
 }
 
 // Disable decorations
@@ -164,14 +516,37 @@ impl<BackendData: Backend> XdgDecorationHandler for MagmaState<BackendData> {
         toplevel.send_configure();
     }
 
-    fn request_mode(
-        &mut self,
-        _toplevel: ToplevelSurface,
-        _mode: smithay::reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
-    ) {
+    fn request_mode(&mut self, toplevel: ToplevelSurface, mode: Mode) {
+        // without title bars there is nothing to draw, so clients keep their decorations off
+        let mode = if CONFIG.title_bar.is_some() {
+            mode
+        } else {
+            Mode::ServerSide
+        };
+        self.set_decoration_mode(&toplevel, mode);
+    }
+
+    fn unset_mode(&mut self, toplevel: ToplevelSurface) {
+        self.set_decoration_mode(&toplevel, Mode::ServerSide);
     }
+}
 
-    fn unset_mode(&mut self, _toplevel: ToplevelSurface) {}
+impl<BackendData: Backend> MagmaState<BackendData> {
+    /// Configure `toplevel` to be decorated by the client or by us, making room for the title bar.
+    fn set_decoration_mode(&mut self, toplevel: &ToplevelSurface, mode: Mode) {
+        toplevel.with_pending_state(|state| {
+            state.decoration_mode = Some(mode);
+        });
+        toplevel.send_configure();
+        let window = self
+            .workspaces
+            .all_windows()
+            .find(|w| w.toplevel() == toplevel)
+            .map(|w| w.clone());
+        if let Some(workspace) = window.and_then(|w| self.workspaces.workspace_from_window(&w)) {
+            update_layout(workspace);
+        }
+    }
 }
 
 delegate_xdg_decoration!(@<BackendData: Backend + 'static> MagmaState<BackendData>);
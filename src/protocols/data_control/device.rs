@@ -0,0 +1,117 @@
+//! wlr-data-control devices, sources and offers.
+
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::sync::Mutex;
+
+use smithay::reexports::wayland_protocols_wlr::data_control::v1::server::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+use smithay::reexports::wayland_server::{Client, DataInit, Dispatch, DisplayHandle, Resource};
+
+use crate::protocols::data_control::{DataControlHandler, DataControlState};
+
+/// Mime types offered by a data control source.
+#[derive(Default)]
+pub struct DataControlSourceData {
+    mime_types: Mutex<Vec<String>>,
+}
+
+/// Which selection an offer belongs to.
+pub struct DataControlOfferData {
+    pub primary: bool,
+}
+
+/// The mime types `source` offers.
+pub fn source_mime_types(source: &ZwlrDataControlSourceV1) -> Vec<String> {
+    source
+        .data::<DataControlSourceData>()
+        .map(|data| data.mime_types.lock().unwrap().clone())
+        .unwrap_or_default()
+}
+
+impl<D> Dispatch<ZwlrDataControlDeviceV1, (), D> for DataControlState
+where
+    D: Dispatch<ZwlrDataControlDeviceV1, ()>,
+    D: DataControlHandler,
+    D: 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        device: &ZwlrDataControlDeviceV1,
+        request: zwlr_data_control_device_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_data_control_device_v1::Request::SetSelection { source } => {
+                state.new_selection(source, false);
+            }
+            zwlr_data_control_device_v1::Request::SetPrimarySelection { source } => {
+                state.new_selection(source, true);
+            }
+            zwlr_data_control_device_v1::Request::Destroy => {
+                state
+                    .data_control_state()
+                    .devices
+                    .retain(|other| other != device);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrDataControlSourceV1, DataControlSourceData, D> for DataControlState
+where
+    D: Dispatch<ZwlrDataControlSourceV1, DataControlSourceData>,
+    D: DataControlHandler,
+    D: 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _source: &ZwlrDataControlSourceV1,
+        request: zwlr_data_control_source_v1::Request,
+        data: &DataControlSourceData,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_data_control_source_v1::Request::Offer { mime_type } => {
+                data.mime_types.lock().unwrap().push(mime_type);
+            }
+            zwlr_data_control_source_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrDataControlOfferV1, DataControlOfferData, D> for DataControlState
+where
+    D: Dispatch<ZwlrDataControlOfferV1, DataControlOfferData>,
+    D: DataControlHandler,
+    D: 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _offer: &ZwlrDataControlOfferV1,
+        request: zwlr_data_control_offer_v1::Request,
+        data: &DataControlOfferData,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_data_control_offer_v1::Request::Receive { mime_type, fd } => {
+                // the fd is passed on to us by the protocol and ours to close
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                state.send_selection(mime_type, fd, data.primary);
+            }
+            zwlr_data_control_offer_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! wlr-data-control protocol.
+
+use std::os::fd::OwnedFd;
+
+use _data_control::zwlr_data_control_device_v1::ZwlrDataControlDeviceV1;
+use _data_control::zwlr_data_control_manager_v1::{Request, ZwlrDataControlManagerV1};
+use _data_control::zwlr_data_control_offer_v1::ZwlrDataControlOfferV1;
+use _data_control::zwlr_data_control_source_v1::ZwlrDataControlSourceV1;
+use smithay::reexports::wayland_protocols_wlr::data_control::v1::server as _data_control;
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::protocols::data_control::device::{DataControlOfferData, DataControlSourceData};
+
+pub mod device;
+
+const MANAGER_VERSION: u32 = 2;
+
+/// Devices of all data control clients, which are told about every new selection.
+pub struct DataControlState {
+    devices: Vec<ZwlrDataControlDeviceV1>,
+    /// Source of the selection, if it was set through data control
+    selection_source: Option<ZwlrDataControlSourceV1>,
+    /// Source of the primary selection, if it was set through data control
+    primary_source: Option<ZwlrDataControlSourceV1>,
+}
+
+impl DataControlState {
+    pub fn new<D>(display: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwlrDataControlManagerV1, ()>,
+        D: Dispatch<ZwlrDataControlManagerV1, ()>,
+        D: Dispatch<ZwlrDataControlDeviceV1, ()>,
+        D: Dispatch<ZwlrDataControlSourceV1, DataControlSourceData>,
+        D: Dispatch<ZwlrDataControlOfferV1, DataControlOfferData>,
+        D: DataControlHandler,
+        D: 'static,
+    {
+        display.create_global::<D, ZwlrDataControlManagerV1, _>(MANAGER_VERSION, ());
+
+        Self {
+            devices: Vec::new(),
+            selection_source: None,
+            primary_source: None,
+        }
+    }
+
+    /// Offer a new selection with `mime_types` to all devices, `None` clears it.
+    pub fn set_selection<D>(
+        &mut self,
+        display: &DisplayHandle,
+        mime_types: Option<&[String]>,
+        primary: bool,
+    ) where
+        D: Dispatch<ZwlrDataControlOfferV1, DataControlOfferData>,
+        D: 'static,
+    {
+        self.devices.retain(|device| device.is_alive());
+        for device in &self.devices {
+            offer_selection::<D>(display, device, mime_types, primary);
+        }
+    }
+
+    /// Replace the data control source of a selection, cancelling the previous one.
+    ///
+    /// `None` means the selection was set by a regular client or cleared.
+    pub fn set_source(&mut self, source: Option<ZwlrDataControlSourceV1>, primary: bool) {
+        let current = if primary {
+            &mut self.primary_source
+        } else {
+            &mut self.selection_source
+        };
+        if let Some(old) = std::mem::replace(current, source) {
+            if current.as_ref() != Some(&old) {
+                old.cancelled();
+            }
+        }
+    }
+
+    /// Whether any data control client is listening for selections.
+    pub fn has_devices(&mut self) -> bool {
+        self.devices.retain(|device| device.is_alive());
+        !self.devices.is_empty()
+    }
+
+    /// Whether the selection was set through data control.
+    pub fn has_source(&self, primary: bool) -> bool {
+        if primary {
+            self.primary_source.is_some()
+        } else {
+            self.selection_source.is_some()
+        }
+    }
+}
+
+/// Send a new offer for the selection to `device`, or clear it for `None`.
+fn offer_selection<D>(
+    display: &DisplayHandle,
+    device: &ZwlrDataControlDeviceV1,
+    mime_types: Option<&[String]>,
+    primary: bool,
+) where
+    D: Dispatch<ZwlrDataControlOfferV1, DataControlOfferData>,
+    D: 'static,
+{
+    // the primary selection was added in version 2
+    if primary && device.version() < 2 {
+        return;
+    }
+
+    let offer = mime_types.and_then(|mime_types| {
+        let client = display.get_client(device.id()).ok()?;
+        let offer = client
+            .create_resource::<ZwlrDataControlOfferV1, _, D>(
+                display,
+                device.version(),
+                DataControlOfferData { primary },
+            )
+            .ok()?;
+        device.data_offer(&offer);
+        for mime_type in mime_types {
+            offer.offer(mime_type.clone());
+        }
+        Some(offer)
+    });
+
+    if primary {
+        device.primary_selection(offer.as_ref());
+    } else {
+        device.selection(offer.as_ref());
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrDataControlManagerV1, (), D> for DataControlState
+where
+    D: GlobalDispatch<ZwlrDataControlManagerV1, ()>,
+    D: Dispatch<ZwlrDataControlManagerV1, ()>,
+    D: Dispatch<ZwlrDataControlDeviceV1, ()>,
+    D: Dispatch<ZwlrDataControlSourceV1, DataControlSourceData>,
+    D: Dispatch<ZwlrDataControlOfferV1, DataControlOfferData>,
+    D: DataControlHandler,
+    D: 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _display: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwlrDataControlManagerV1>,
+        _manager_state: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(manager, ());
+    }
+}
+
+impl<D> Dispatch<ZwlrDataControlManagerV1, (), D> for DataControlState
+where
+    D: GlobalDispatch<ZwlrDataControlManagerV1, ()>,
+    D: Dispatch<ZwlrDataControlManagerV1, ()>,
+    D: Dispatch<ZwlrDataControlDeviceV1, ()>,
+    D: Dispatch<ZwlrDataControlSourceV1, DataControlSourceData>,
+    D: Dispatch<ZwlrDataControlOfferV1, DataControlOfferData>,
+    D: DataControlHandler,
+    D: 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _manager: &ZwlrDataControlManagerV1,
+        request: Request,
+        _data: &(),
+        display: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            Request::CreateDataSource { id } => {
+                data_init.init(id, DataControlSourceData::default());
+            }
+            Request::GetDataDevice { id, .. } => {
+                // there is a single clipboard shared by all seats
+                let device = data_init.init(id, ());
+                for primary in [false, true] {
+                    let mime_types = state.selection_mime_types(primary);
+                    offer_selection::<D>(display, &device, mime_types.as_deref(), primary);
+                }
+                state.data_control_state().devices.push(device);
+            }
+            Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Handler trait for wlr-data-control.
+pub trait DataControlHandler {
+    fn data_control_state(&mut self) -> &mut DataControlState;
+
+    /// Mime types of the current selection, `None` if there is none.
+    fn selection_mime_types(&self, primary: bool) -> Option<Vec<String>>;
+
+    /// A data control client replaced the selection, `None` clears it.
+    fn new_selection(&mut self, source: Option<ZwlrDataControlSourceV1>, primary: bool);
+
+    /// A data control client wants to receive the selection as `mime_type`.
+    fn send_selection(&mut self, mime_type: String, fd: OwnedFd, primary: bool);
+}
+
+#[allow(missing_docs)]
+#[macro_export]
+macro_rules! delegate_data_control {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1: ()
+        ] => $crate::protocols::data_control::DataControlState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1: ()
+        ] => $crate::protocols::data_control::DataControlState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_device_v1::ZwlrDataControlDeviceV1: ()
+        ] => $crate::protocols::data_control::DataControlState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_source_v1::ZwlrDataControlSourceV1: $crate::protocols::data_control::device::DataControlSourceData
+        ] => $crate::protocols::data_control::DataControlState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_offer_v1::ZwlrDataControlOfferV1: $crate::protocols::data_control::device::DataControlOfferData
+        ] => $crate::protocols::data_control::DataControlState);
+    };
+}
@@ -1 +1,3 @@
+pub mod data_control;
 pub mod screencopy;
+pub mod virtual_input;
@@ -0,0 +1,283 @@
+//! virtual-keyboard protocol.
+//!
+//! Keys are translated from the keymap sent by the client to the keycodes of the seat keymap
+//! producing the same keysyms, so keybindings match them the same way as physical keys.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    os::{
+        fd::{FromRawFd, OwnedFd},
+        unix::fs::FileExt,
+    },
+    sync::Mutex,
+};
+
+use _virtual_keyboard::zwp_virtual_keyboard_manager_v1::{
+    self, Request as ManagerRequest, ZwpVirtualKeyboardManagerV1,
+};
+use _virtual_keyboard::zwp_virtual_keyboard_v1::{self, Request, ZwpVirtualKeyboardV1};
+use smithay::backend::input::{InputEvent, KeyState};
+use smithay::input::keyboard::{keysyms, xkb};
+use smithay::reexports::wayland_protocols_misc::zwp_virtual_keyboard_v1::server as _virtual_keyboard;
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use tracing::warn;
+
+use super::{VirtualDevice, VirtualInputHandler, VirtualKeyboardKeyEvent};
+
+const MANAGER_VERSION: u32 = 1;
+
+pub struct VirtualKeyboardManagerState;
+
+/// The device keys of a virtual keyboard come from.
+pub struct VirtualKeyboardData {
+    device: VirtualDevice,
+    /// Translation of the keymap sent by the client, keys may only be sent once it was
+    keymap: Mutex<Option<KeyTranslation>>,
+}
+
+/// Evdev keycodes of a client keymap mapped to those of the seat keymap producing the same
+/// keysym, and whether that needs Shift.
+#[derive(Debug, Default)]
+struct KeyTranslation {
+    keys: HashMap<u32, (u32, bool)>,
+    shift: Option<u32>,
+}
+
+/// Xkb keycodes are evdev keycodes offset by 8
+const EVDEV_OFFSET: u32 = 8;
+
+impl KeyTranslation {
+    fn new(client: &xkb::Keymap, seat: &xkb::Keymap) -> Self {
+        // keys producing the keysym without modifiers are preferred over those needing Shift
+        let mut seat_keys = HashMap::new();
+        for level in 0..2 {
+            for keycode in seat.min_keycode().max(EVDEV_OFFSET)..=seat.max_keycode() {
+                for sym in seat.key_get_syms_by_level(keycode, 0, level) {
+                    seat_keys
+                        .entry(*sym)
+                        .or_insert((keycode - EVDEV_OFFSET, level == 1));
+                }
+            }
+        }
+        let keys = (client.min_keycode().max(EVDEV_OFFSET)..=client.max_keycode())
+            .filter_map(|keycode| {
+                let sym = client.key_get_syms_by_level(keycode, 0, 0).first()?;
+                Some((keycode - EVDEV_OFFSET, *seat_keys.get(sym)?))
+            })
+            .collect();
+        KeyTranslation {
+            keys,
+            shift: seat_keys
+                .get(&keysyms::KEY_Shift_L)
+                .map(|(keycode, _)| *keycode),
+        }
+    }
+}
+
+/// Compile the keymap a client sent and translate it to the keymap of the seat.
+fn load_keymap(
+    format: u32,
+    fd: OwnedFd,
+    size: u32,
+    seat_keymap: Option<xkb::Keymap>,
+) -> Result<KeyTranslation, String> {
+    if format != xkb::KEYMAP_FORMAT_TEXT_V1 {
+        return Err(format!("unsupported keymap format {}", format));
+    }
+    let mut buffer = vec![0; size as usize];
+    // read at an offset, the file offset is shared with the client
+    File::from(fd)
+        .read_exact_at(&mut buffer, 0)
+        .map_err(|err| err.to_string())?;
+    let keymap = String::from_utf8(buffer)
+        .map_err(|err| err.to_string())?
+        .trim_end_matches('\0')
+        .to_string();
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    let keymap = xkb::Keymap::new_from_string(
+        &context,
+        keymap,
+        xkb::KEYMAP_FORMAT_TEXT_V1,
+        xkb::KEYMAP_COMPILE_NO_FLAGS,
+    )
+    .ok_or("failed to compile the keymap")?;
+    let seat_keymap = seat_keymap.ok_or("the seat has no keymap")?;
+    Ok(KeyTranslation::new(&keymap, &seat_keymap))
+}
+
+impl VirtualKeyboardManagerState {
+    pub fn new<D>(display: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwpVirtualKeyboardManagerV1, ()>,
+        D: Dispatch<ZwpVirtualKeyboardManagerV1, ()>,
+        D: Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData>,
+        D: VirtualInputHandler,
+        D: 'static,
+    {
+        display.create_global::<D, ZwpVirtualKeyboardManagerV1, _>(MANAGER_VERSION, ());
+
+        Self
+    }
+}
+
+impl<D> GlobalDispatch<ZwpVirtualKeyboardManagerV1, (), D> for VirtualKeyboardManagerState
+where
+    D: GlobalDispatch<ZwpVirtualKeyboardManagerV1, ()>,
+    D: Dispatch<ZwpVirtualKeyboardManagerV1, ()>,
+    D: Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData>,
+    D: VirtualInputHandler,
+    D: 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _display: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwpVirtualKeyboardManagerV1>,
+        _manager_state: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(manager, ());
+    }
+}
+
+impl<D> Dispatch<ZwpVirtualKeyboardManagerV1, (), D> for VirtualKeyboardManagerState
+where
+    D: GlobalDispatch<ZwpVirtualKeyboardManagerV1, ()>,
+    D: Dispatch<ZwpVirtualKeyboardManagerV1, ()>,
+    D: Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData>,
+    D: VirtualInputHandler,
+    D: 'static,
+{
+    fn request(
+        state: &mut D,
+        client: &Client,
+        manager: &ZwpVirtualKeyboardManagerV1,
+        request: ManagerRequest,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            ManagerRequest::CreateVirtualKeyboard { id, .. } => {
+                data_init.init(
+                    id,
+                    VirtualKeyboardData {
+                        device: VirtualDevice::new(true),
+                        keymap: Mutex::new(None),
+                    },
+                );
+                if !state.virtual_input_allowed(client) {
+                    manager.post_error(
+                        zwp_virtual_keyboard_manager_v1::Error::Unauthorized,
+                        "virtual keyboards are not allowed for this client",
+                    );
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData, D> for VirtualKeyboardManagerState
+where
+    D: Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData>,
+    D: VirtualInputHandler,
+    D: 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        keyboard: &ZwpVirtualKeyboardV1,
+        request: Request,
+        data: &VirtualKeyboardData,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            Request::Keymap { format, fd, size } => {
+                // the fd is passed on to us by the protocol and ours to close
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                match load_keymap(format, fd, size, state.seat_keymap()) {
+                    Ok(translation) => *data.keymap.lock().unwrap() = Some(translation),
+                    Err(err) => {
+                        warn!("Failed to load the keymap of a virtual keyboard: {}", err);
+                        // keys can't be translated, but the client did send a keymap
+                        *data.keymap.lock().unwrap() = Some(KeyTranslation::default());
+                    }
+                }
+            }
+            Request::Key {
+                time,
+                key,
+                state: key_state,
+            } => {
+                let keymap = data.keymap.lock().unwrap();
+                let Some(keymap) = keymap.as_ref() else {
+                    keyboard.post_error(
+                        zwp_virtual_keyboard_v1::Error::NoKeymap,
+                        "no keymap was set",
+                    );
+                    return;
+                };
+                let Some((key, shift)) = keymap.keys.get(&key).copied() else {
+                    warn!(
+                        "Key {} of a virtual keyboard has no equivalent in the seat keymap",
+                        key
+                    );
+                    return;
+                };
+                let key_state = if key_state == 1 {
+                    KeyState::Pressed
+                } else {
+                    KeyState::Released
+                };
+                // keysyms on the Shift level are typed with Shift held around the press
+                let shift = keymap
+                    .shift
+                    .filter(|_| shift && key_state == KeyState::Pressed);
+                drop(keymap);
+                let events = shift
+                    .map(|shift| (shift, KeyState::Pressed))
+                    .into_iter()
+                    .chain([(key, key_state)])
+                    .chain(shift.map(|shift| (shift, KeyState::Released)));
+                for (key, key_state) in events {
+                    state.virtual_input(InputEvent::Keyboard {
+                        event: VirtualKeyboardKeyEvent {
+                            device: data.device.clone(),
+                            time,
+                            key,
+                            state: key_state,
+                        },
+                    });
+                }
+            }
+            // the seat keyboard tracks the modifiers from the keys it gets
+            Request::Modifiers { .. } => {}
+            Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[allow(missing_docs)]
+#[macro_export]
+macro_rules! delegate_virtual_keyboard {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_misc::zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1: ()
+        ] => $crate::protocols::virtual_input::keyboard::VirtualKeyboardManagerState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_misc::zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1: ()
+        ] => $crate::protocols::virtual_input::keyboard::VirtualKeyboardManagerState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_misc::zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1: $crate::protocols::virtual_input::keyboard::VirtualKeyboardData
+        ] => $crate::protocols::virtual_input::keyboard::VirtualKeyboardManagerState);
+    };
+}
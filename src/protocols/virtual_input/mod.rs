@@ -0,0 +1,283 @@
+//! Virtual keyboards and pointers of the virtual-keyboard and wlr-virtual-pointer protocols.
+//!
+//! Their events are turned into regular input events of the [`VirtualInput`] backend,
+//! so they are handled like those of physical devices, keybindings included.
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use smithay::{
+    backend::input::{
+        AbsolutePositionEvent, Axis, AxisSource, ButtonState, Device, DeviceCapability, Event,
+        InputBackend, InputEvent, KeyState, KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent,
+        PointerMotionAbsoluteEvent, PointerMotionEvent, UnusedEvent,
+    },
+    input::keyboard::xkb,
+    reexports::wayland_server::Client,
+};
+
+pub mod keyboard;
+pub mod pointer;
+
+/// Input backend of the devices created by clients.
+#[derive(Debug)]
+pub struct VirtualInput;
+
+impl InputBackend for VirtualInput {
+    type Device = VirtualDevice;
+    type KeyboardKeyEvent = VirtualKeyboardKeyEvent;
+    type PointerAxisEvent = VirtualPointerAxisEvent;
+    type PointerButtonEvent = VirtualPointerButtonEvent;
+    type PointerMotionEvent = VirtualPointerMotionEvent;
+    type PointerMotionAbsoluteEvent = VirtualPointerMotionAbsoluteEvent;
+    type GestureSwipeBeginEvent = UnusedEvent;
+    type GestureSwipeUpdateEvent = UnusedEvent;
+    type GestureSwipeEndEvent = UnusedEvent;
+    type GesturePinchBeginEvent = UnusedEvent;
+    type GesturePinchUpdateEvent = UnusedEvent;
+    type GesturePinchEndEvent = UnusedEvent;
+    type GestureHoldBeginEvent = UnusedEvent;
+    type GestureHoldEndEvent = UnusedEvent;
+    type TouchDownEvent = UnusedEvent;
+    type TouchUpEvent = UnusedEvent;
+    type TouchMotionEvent = UnusedEvent;
+    type TouchCancelEvent = UnusedEvent;
+    type TouchFrameEvent = UnusedEvent;
+    type TabletToolAxisEvent = UnusedEvent;
+    type TabletToolProximityEvent = UnusedEvent;
+    type TabletToolTipEvent = UnusedEvent;
+    type TabletToolButtonEvent = UnusedEvent;
+    type SpecialEvent = UnusedEvent;
+}
+
+/// A virtual keyboard or pointer, named after its kind so `CONFIG.seats` can refer to them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VirtualDevice {
+    id: u32,
+    keyboard: bool,
+}
+
+impl VirtualDevice {
+    fn new(keyboard: bool) -> Self {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            keyboard,
+        }
+    }
+}
+
+impl Device for VirtualDevice {
+    fn id(&self) -> String {
+        format!("virtual-{}", self.id)
+    }
+
+    fn name(&self) -> String {
+        if self.keyboard {
+            "virtual keyboard".to_string()
+        } else {
+            "virtual pointer".to_string()
+        }
+    }
+
+    fn has_capability(&self, capability: DeviceCapability) -> bool {
+        match capability {
+            DeviceCapability::Keyboard => self.keyboard,
+            DeviceCapability::Pointer => !self.keyboard,
+            _ => false,
+        }
+    }
+
+    fn usb_id(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn syspath(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub struct VirtualKeyboardKeyEvent {
+    device: VirtualDevice,
+    /// Milliseconds, as sent by the client
+    time: u32,
+    key: u32,
+    state: KeyState,
+}
+
+impl Event<VirtualInput> for VirtualKeyboardKeyEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        self.device.clone()
+    }
+}
+
+impl KeyboardKeyEvent<VirtualInput> for VirtualKeyboardKeyEvent {
+    fn key_code(&self) -> u32 {
+        self.key
+    }
+
+    fn state(&self) -> KeyState {
+        self.state
+    }
+
+    fn count(&self) -> u32 {
+        match self.state {
+            KeyState::Pressed => 1,
+            KeyState::Released => 0,
+        }
+    }
+}
+
+pub struct VirtualPointerMotionEvent {
+    device: VirtualDevice,
+    time: u32,
+    dx: f64,
+    dy: f64,
+}
+
+impl Event<VirtualInput> for VirtualPointerMotionEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        self.device.clone()
+    }
+}
+
+impl PointerMotionEvent<VirtualInput> for VirtualPointerMotionEvent {
+    fn delta_x(&self) -> f64 {
+        self.dx
+    }
+
+    fn delta_y(&self) -> f64 {
+        self.dy
+    }
+
+    fn delta_x_unaccel(&self) -> f64 {
+        self.dx
+    }
+
+    fn delta_y_unaccel(&self) -> f64 {
+        self.dy
+    }
+}
+
+pub struct VirtualPointerMotionAbsoluteEvent {
+    device: VirtualDevice,
+    time: u32,
+    x: u32,
+    y: u32,
+    /// Size of the space `x` and `y` are in, which is stretched over the output
+    x_extent: u32,
+    y_extent: u32,
+}
+
+impl Event<VirtualInput> for VirtualPointerMotionAbsoluteEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        self.device.clone()
+    }
+}
+
+impl AbsolutePositionEvent<VirtualInput> for VirtualPointerMotionAbsoluteEvent {
+    fn x(&self) -> f64 {
+        self.x as f64
+    }
+
+    fn y(&self) -> f64 {
+        self.y as f64
+    }
+
+    fn x_transformed(&self, width: i32) -> f64 {
+        self.x as f64 * width as f64 / self.x_extent.max(1) as f64
+    }
+
+    fn y_transformed(&self, height: i32) -> f64 {
+        self.y as f64 * height as f64 / self.y_extent.max(1) as f64
+    }
+}
+
+impl PointerMotionAbsoluteEvent<VirtualInput> for VirtualPointerMotionAbsoluteEvent {}
+
+pub struct VirtualPointerButtonEvent {
+    device: VirtualDevice,
+    time: u32,
+    button: u32,
+    state: ButtonState,
+}
+
+impl Event<VirtualInput> for VirtualPointerButtonEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        self.device.clone()
+    }
+}
+
+impl PointerButtonEvent<VirtualInput> for VirtualPointerButtonEvent {
+    fn button_code(&self) -> u32 {
+        self.button
+    }
+
+    fn state(&self) -> ButtonState {
+        self.state
+    }
+}
+
+/// Scrolling along one axis, a stop is sent as an amount of 0.
+pub struct VirtualPointerAxisEvent {
+    device: VirtualDevice,
+    time: u32,
+    axis: Axis,
+    amount: f64,
+    discrete: Option<f64>,
+    source: AxisSource,
+}
+
+impl Event<VirtualInput> for VirtualPointerAxisEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        self.device.clone()
+    }
+}
+
+impl PointerAxisEvent<VirtualInput> for VirtualPointerAxisEvent {
+    fn amount(&self, axis: Axis) -> Option<f64> {
+        (axis == self.axis).then_some(self.amount)
+    }
+
+    fn amount_discrete(&self, axis: Axis) -> Option<f64> {
+        self.discrete.filter(|_| axis == self.axis)
+    }
+
+    fn source(&self) -> AxisSource {
+        self.source
+    }
+}
+
+/// Handler trait for virtual keyboards and pointers.
+pub trait VirtualInputHandler {
+    /// Whether `client` may create virtual devices, which can type and click anywhere.
+    fn virtual_input_allowed(&self, client: &Client) -> bool;
+
+    /// The keymap of the seat, keys of virtual keyboards are translated to.
+    fn seat_keymap(&self) -> Option<xkb::Keymap>;
+
+    /// An event of a virtual device, to be handled like any other input.
+    fn virtual_input(&mut self, event: InputEvent<VirtualInput>);
+}
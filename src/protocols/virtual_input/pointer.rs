@@ -0,0 +1,221 @@
+//! wlr-virtual-pointer protocol.
+
+use std::sync::Mutex;
+
+use _virtual_pointer::zwlr_virtual_pointer_manager_v1::{
+    Request as ManagerRequest, ZwlrVirtualPointerManagerV1,
+};
+use _virtual_pointer::zwlr_virtual_pointer_v1::{Request, ZwlrVirtualPointerV1};
+use smithay::backend::input::{Axis, AxisSource, ButtonState, InputEvent};
+use smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server as _virtual_pointer;
+use smithay::reexports::wayland_server::protocol::wl_pointer;
+use smithay::reexports::wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, WEnum,
+};
+
+use super::{
+    VirtualDevice, VirtualInputHandler, VirtualPointerAxisEvent, VirtualPointerButtonEvent,
+    VirtualPointerMotionAbsoluteEvent, VirtualPointerMotionEvent,
+};
+
+const MANAGER_VERSION: u32 = 2;
+
+pub struct VirtualPointerManagerState;
+
+/// The device events of a virtual pointer come from.
+pub struct VirtualPointerData {
+    device: VirtualDevice,
+    /// Whether the client may use virtual pointers, the protocol has no error to refuse them
+    allowed: bool,
+    /// Source of the following axis events
+    axis_source: Mutex<AxisSource>,
+}
+
+impl VirtualPointerManagerState {
+    pub fn new<D>(display: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwlrVirtualPointerManagerV1, ()>,
+        D: Dispatch<ZwlrVirtualPointerManagerV1, ()>,
+        D: Dispatch<ZwlrVirtualPointerV1, VirtualPointerData>,
+        D: VirtualInputHandler,
+        D: 'static,
+    {
+        display.create_global::<D, ZwlrVirtualPointerManagerV1, _>(MANAGER_VERSION, ());
+
+        Self
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrVirtualPointerManagerV1, (), D> for VirtualPointerManagerState
+where
+    D: GlobalDispatch<ZwlrVirtualPointerManagerV1, ()>,
+    D: Dispatch<ZwlrVirtualPointerManagerV1, ()>,
+    D: Dispatch<ZwlrVirtualPointerV1, VirtualPointerData>,
+    D: VirtualInputHandler,
+    D: 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _display: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwlrVirtualPointerManagerV1>,
+        _manager_state: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(manager, ());
+    }
+}
+
+impl<D> Dispatch<ZwlrVirtualPointerManagerV1, (), D> for VirtualPointerManagerState
+where
+    D: GlobalDispatch<ZwlrVirtualPointerManagerV1, ()>,
+    D: Dispatch<ZwlrVirtualPointerManagerV1, ()>,
+    D: Dispatch<ZwlrVirtualPointerV1, VirtualPointerData>,
+    D: VirtualInputHandler,
+    D: 'static,
+{
+    fn request(
+        state: &mut D,
+        client: &Client,
+        _manager: &ZwlrVirtualPointerManagerV1,
+        request: ManagerRequest,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            // absolute motion is mapped to the active output like for any other device
+            ManagerRequest::CreateVirtualPointer { id, .. }
+            | ManagerRequest::CreateVirtualPointerWithOutput { id, .. } => {
+                data_init.init(
+                    id,
+                    VirtualPointerData {
+                        device: VirtualDevice::new(false),
+                        allowed: state.virtual_input_allowed(client),
+                        axis_source: Mutex::new(AxisSource::Wheel),
+                    },
+                );
+            }
+            ManagerRequest::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrVirtualPointerV1, VirtualPointerData, D> for VirtualPointerManagerState
+where
+    D: Dispatch<ZwlrVirtualPointerV1, VirtualPointerData>,
+    D: VirtualInputHandler,
+    D: 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _pointer: &ZwlrVirtualPointerV1,
+        request: Request,
+        data: &VirtualPointerData,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        if !data.allowed {
+            return;
+        }
+        let device = data.device.clone();
+        let axis_event = |time, axis: WEnum<wl_pointer::Axis>, amount, discrete| {
+            let axis = match axis {
+                WEnum::Value(wl_pointer::Axis::HorizontalScroll) => Axis::Horizontal,
+                _ => Axis::Vertical,
+            };
+            InputEvent::PointerAxis {
+                event: VirtualPointerAxisEvent {
+                    device: data.device.clone(),
+                    time,
+                    axis,
+                    amount,
+                    discrete,
+                    source: *data.axis_source.lock().unwrap(),
+                },
+            }
+        };
+        let event = match request {
+            Request::Motion { time, dx, dy } => InputEvent::PointerMotion {
+                event: VirtualPointerMotionEvent {
+                    device,
+                    time,
+                    dx,
+                    dy,
+                },
+            },
+            Request::MotionAbsolute {
+                time,
+                x,
+                y,
+                x_extent,
+                y_extent,
+            } => InputEvent::PointerMotionAbsolute {
+                event: VirtualPointerMotionAbsoluteEvent {
+                    device,
+                    time,
+                    x,
+                    y,
+                    x_extent,
+                    y_extent,
+                },
+            },
+            Request::Button {
+                time,
+                button,
+                state: button_state,
+            } => InputEvent::PointerButton {
+                event: VirtualPointerButtonEvent {
+                    device,
+                    time,
+                    button,
+                    state: match button_state {
+                        WEnum::Value(wl_pointer::ButtonState::Pressed) => ButtonState::Pressed,
+                        _ => ButtonState::Released,
+                    },
+                },
+            },
+            Request::Axis { time, axis, value } => axis_event(time, axis, value, None),
+            Request::AxisDiscrete {
+                time,
+                axis,
+                value,
+                discrete,
+            } => axis_event(time, axis, value, Some(discrete as f64)),
+            Request::AxisStop { time, axis } => axis_event(time, axis, 0.0, None),
+            Request::AxisSource { axis_source } => {
+                *data.axis_source.lock().unwrap() = match axis_source {
+                    WEnum::Value(wl_pointer::AxisSource::Finger) => AxisSource::Finger,
+                    WEnum::Value(wl_pointer::AxisSource::Continuous) => AxisSource::Continuous,
+                    WEnum::Value(wl_pointer::AxisSource::WheelTilt) => AxisSource::WheelTilt,
+                    _ => AxisSource::Wheel,
+                };
+                return;
+            }
+            // every event is handled on its own, the seat pointer sends frames itself
+            Request::Frame | Request::Destroy => return,
+            _ => unreachable!(),
+        };
+        state.virtual_input(event);
+    }
+}
+
+#[allow(missing_docs)]
+#[macro_export]
+macro_rules! delegate_virtual_pointer {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1: ()
+        ] => $crate::protocols::virtual_input::pointer::VirtualPointerManagerState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1: ()
+        ] => $crate::protocols::virtual_input::pointer::VirtualPointerManagerState);
+
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1: $crate::protocols::virtual_input::pointer::VirtualPointerData
+        ] => $crate::protocols::virtual_input::pointer::VirtualPointerManagerState);
+    };
+}
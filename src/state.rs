@@ -1,36 +1,82 @@
-use std::{ffi::OsString, os::fd::AsRawFd, sync::Arc, time::Instant};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::OsString,
+    os::fd::AsRawFd,
+    process::{Child, Command},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use once_cell::sync::Lazy;
 use smithay::{
+    backend::input::Device,
     desktop::{
-        layer_map_for_output, {PopupManager, Window},
+        layer_map_for_output, WindowSurfaceType, {PopupManager, Window},
     },
-    input::{keyboard::XkbConfig, Seat, SeatState},
+    input::{keyboard::XkbConfig, pointer::CursorImageStatus, Seat, SeatState},
+    output::Output,
     reexports::{
-        calloop::{generic::Generic, Interest, LoopHandle, LoopSignal, Mode, PostAction},
+        calloop::{
+            channel::{self, Event, Sender},
+            generic::Generic,
+            signals::{Signal, Signals},
+            timer::{TimeoutAction, Timer},
+            Interest, LoopHandle, LoopSignal, Mode, PostAction, RegistrationToken,
+        },
         wayland_server::{
             backend::{ClientData, ClientId, DisconnectReason},
-            Display, DisplayHandle,
+            protocol::wl_surface::WlSurface,
+            Display, DisplayHandle, Resource,
         },
     },
-    utils::{Logical, Point},
+    utils::{Clock, Logical, Monotonic, Point},
     wayland::{
-        compositor::CompositorState,
-        data_device::DataDeviceState,
+        compositor::{get_parent, CompositorState},
+        data_device::{set_data_device_selection, DataDeviceState},
+        fractional_scale::FractionalScaleManagerState,
+        idle_inhibit::IdleInhibitManagerState,
+        keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitState,
         output::OutputManagerState,
-        primary_selection::PrimarySelectionState,
+        pointer_constraints::PointerConstraintsState,
+        pointer_gestures::PointerGesturesState,
+        presentation::PresentationState,
+        primary_selection::{set_primary_selection, PrimarySelectionState},
+        relative_pointer::RelativePointerManagerState,
         shell::{
             wlr_layer::{Layer as WlrLayer, WlrLayerShellState},
             xdg::{decoration::XdgDecorationState, XdgShellState},
         },
         shm::ShmState,
         socket::ListeningSocketSource,
+        tablet_manager::TabletManagerState,
+        viewporter::ViewporterState,
+        xdg_activation::XdgActivationState,
+        xdg_foreign::XdgForeignState,
     },
 };
-use tracing::warn;
+use tracing::{error, info, warn};
 
-use crate::config::{load_config, Config};
-use crate::utils::{focus::FocusTarget, workspace::Workspaces};
+use crate::config::{
+    config_path, load_config, try_load_config, Action, ConfigHandle, Corner, KeyModifier,
+    SpawnCommand,
+};
+use crate::handlers::input::BoundGesture;
+use crate::protocols::{
+    data_control::DataControlState,
+    virtual_input::{keyboard::VirtualKeyboardManagerState, pointer::VirtualPointerManagerState},
+};
+use crate::utils::{
+    clipboard::{ClipboardCache, SelectionData},
+    focus::FocusTarget,
+    ipc::IpcState,
+    render::{Message, OutputFrame},
+    stats::FrameStats,
+    tiling::update_layout,
+    window::WindowExt,
+    workspace::{MagmaWindow, Workspaces},
+};
 
 pub struct CalloopData<BackendData: Backend + 'static> {
     pub state: MagmaState<BackendData>,
@@ -39,14 +85,28 @@ pub struct CalloopData<BackendData: Backend + 'static> {
 
 pub trait Backend {
     fn seat_name(&self) -> String;
+
+    /// Apply the input device settings of the current config to all connected devices
+    fn apply_input_config(&mut self) {}
+
+    /// Turn all outputs on or off, e.g. to save power while the session is idle
+    fn set_outputs_powered(&mut self, _powered: bool) {}
 }
 
-pub static CONFIG: Lazy<Config> = Lazy::new(load_config);
+/// How often the idle timeout is checked
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a process started by SpawnOnWorkspace has to map its first window
+pub const SPAWN_WORKSPACE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub static CONFIG: Lazy<ConfigHandle> = Lazy::new(|| ConfigHandle::new(load_config()));
 
 pub struct MagmaState<BackendData: Backend + 'static> {
     pub dh: DisplayHandle,
     pub backend_data: BackendData,
     pub start_time: Instant,
+    /// Clock the presentation times are reported in
+    pub clock: Clock<Monotonic>,
     pub loop_handle: LoopHandle<'static, CalloopData<BackendData>>,
     pub loop_signal: LoopSignal,
 
@@ -60,14 +120,109 @@ pub struct MagmaState<BackendData: Backend + 'static> {
     pub primary_selection_state: PrimarySelectionState,
     pub seat_state: SeatState<MagmaState<BackendData>>,
     pub layer_shell_state: WlrLayerShellState,
+    pub keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
+    pub xdg_foreign_state: XdgForeignState,
+    pub virtual_keyboard_manager_state: VirtualKeyboardManagerState,
+    pub virtual_pointer_manager_state: VirtualPointerManagerState,
+    pub idle_inhibit_manager_state: IdleInhibitManagerState,
+    pub pointer_constraints_state: PointerConstraintsState,
+    pub relative_pointer_manager_state: RelativePointerManagerState,
+    pub pointer_gestures_state: PointerGesturesState,
+    pub data_control_state: DataControlState,
+    pub xdg_activation_state: XdgActivationState,
+    pub fractional_scale_manager_state: FractionalScaleManagerState,
+    pub viewporter_state: ViewporterState,
+    pub presentation_state: PresentationState,
+    /// Only created once a tablet is connected
+    pub tablet_manager_state: Option<TabletManagerState>,
     pub popup_manager: PopupManager,
 
     pub seat: Seat<Self>,
     pub seat_name: String,
+    /// Additional seats from `CONFIG.seats`, the primary `seat` is not included
+    pub seats: Vec<Seat<Self>>,
     pub socket_name: OsString,
 
     pub workspaces: Workspaces,
+    /// Location of the pointer of `pointer_seat`
     pub pointer_location: Point<f64, Logical>,
+    /// Seat that last used its pointer, which `pointer_location` and `cursor_status` belong to
+    pub pointer_seat: String,
+    /// Pointer location and cursor of the other seats, keyed by seat name
+    pub seat_pointers: HashMap<String, (Point<f64, Logical>, CursorImageStatus)>,
+    /// Fingers currently touching a touchscreen
+    pub touch_points: usize,
+    /// Pressed buttons that triggered a mousebinding, whose release is not sent to clients
+    pub bound_buttons: Vec<u32>,
+    /// Touchpad gesture in progress that triggers an action instead of reaching clients
+    pub bound_gesture: Option<BoundGesture>,
+    /// Cursor requested by the client under the pointer of `pointer_seat`
+    pub cursor_status: CursorImageStatus,
+    /// Icon of the ongoing drag and drop, drawn at the pointer
+    pub dnd_icon: Option<WlSurface>,
+    /// Output keyboard focus and output related actions apply to
+    pub focused_output: Option<Output>,
+    pub focus_timer: Option<RegistrationToken>,
+    /// Forward all keys to the focused client instead of handling keybindings
+    pub passthrough: bool,
+    /// Drawn around the outputs while `passthrough` is active
+    pub passthrough_frame: OutputFrame,
+    /// Keep the pointer within the focused window
+    pub confine_pointer: bool,
+    /// When each spawn command was last started, used for `CONFIG.spawn_cooldown`
+    pub last_spawn: HashMap<String, Instant>,
+    /// Workspaces the first window of processes started by SpawnOnWorkspace goes to and when
+    /// they were started, keyed by pid
+    pub spawn_workspaces: HashMap<u32, (u8, Instant)>,
+    /// Spawned processes, reaped once they exit
+    pub children: Vec<Child>,
+    /// Activation tokens handed to spawned processes and when, which may take the focus
+    /// once they map
+    pub spawn_tokens: Vec<(String, Instant)>,
+    /// Render timings, keyed by output name
+    pub frame_stats: HashMap<String, FrameStats>,
+    /// Modifier that was pressed without any other key so far
+    pub modifier_tap: Option<KeyModifier>,
+    /// Last modifier tap and when it happened, to detect double taps
+    pub last_modifier_tap: Option<(KeyModifier, Instant)>,
+    /// Timer of a single modifier tap whose action waits for a possible second tap
+    pub tap_timer: Option<RegistrationToken>,
+    /// Action triggered from within the keyboard filter, to be handled after it
+    pub pending_action: Option<Action>,
+    /// Window whose title bar was last clicked and when, to detect double clicks
+    pub last_title_bar_click: Option<(Window, Instant)>,
+    /// Hot corner the pointer is currently in
+    pub hot_corner: Option<Corner>,
+    /// When quitting was last requested while waiting for the confirmation
+    pub quit_requested: Option<Instant>,
+    /// Shown while `quit_requested` waits for the confirmation
+    pub quit_message: Message,
+    /// Take the focused window along when switching workspaces
+    pub carry_window: bool,
+    /// Focused windows, the most recently focused last
+    pub focus_history: Vec<Window>,
+    /// Windows hidden by MoveToScratchpad, the most recent last
+    pub scratchpad: Vec<Rc<RefCell<MagmaWindow>>>,
+    /// Scratchpad window currently shown by ShowScratchpad
+    pub scratchpad_shown: Option<Window>,
+    /// When the last input event arrived
+    pub last_input: Instant,
+    /// Whether `CONFIG.idle_timeout` passed without any input
+    pub idle: bool,
+    /// Whether the outputs are on, they are turned off after `CONFIG.dpms_timeout`
+    pub outputs_powered: bool,
+    /// Surfaces preventing the session from going idle
+    pub idle_inhibitors: Vec<WlSurface>,
+    /// Show only the focused window of the current workspace
+    pub focus_mode: bool,
+    /// Copy of the clipboard selection, kept after its source client exits
+    pub clipboard: ClipboardCache,
+    pub clipboard_sender: Sender<SelectionData>,
+    /// Copy of the primary selection, read for data control clients
+    pub primary_clipboard: ClipboardCache,
+    pub primary_clipboard_sender: Sender<SelectionData>,
+    /// Socket for scripts and status bars, `None` if it couldn't be created
+    pub ipc: Option<IpcState>,
 }
 
 impl<BackendData: Backend> MagmaState<BackendData> {
@@ -92,27 +247,104 @@ impl<BackendData: Backend> MagmaState<BackendData> {
         let seat_name = backend_data.seat_name();
         let mut seat = seat_state.new_wl_seat(&dh, seat_name.clone());
         let layer_shell_state = WlrLayerShellState::new::<Self>(&dh);
+        let keyboard_shortcuts_inhibit_state = KeyboardShortcutsInhibitState::new::<Self>(&dh);
+        let xdg_foreign_state = XdgForeignState::new::<Self>(&dh);
+        let virtual_keyboard_manager_state = VirtualKeyboardManagerState::new::<Self>(&dh);
+        let virtual_pointer_manager_state = VirtualPointerManagerState::new::<Self>(&dh);
+        let idle_inhibit_manager_state = IdleInhibitManagerState::new::<Self>(&dh);
+        let pointer_constraints_state = PointerConstraintsState::new::<Self>(&dh);
+        let relative_pointer_manager_state = RelativePointerManagerState::new::<Self>(&dh);
+        let pointer_gestures_state = PointerGesturesState::new::<Self>(&dh);
+        let data_control_state = DataControlState::new::<Self>(&dh);
+        let xdg_activation_state = XdgActivationState::new::<Self>(&dh);
+        let fractional_scale_manager_state = FractionalScaleManagerState::new::<Self>(&dh);
+        let viewporter_state = ViewporterState::new::<Self>(&dh);
+        let clock = Clock::new().expect("Failed to init the clock.");
+        let presentation_state = PresentationState::new::<Self>(&dh, clock.id() as u32);
 
-        let conf = CONFIG.xkb.clone();
-        if let Err(err) = seat.add_keyboard((&conf).into(), 200, 25) {
-            warn!(
-                ?err,
-                "Failed to load provided xkb config. Trying default...",
-            );
-            seat.add_keyboard(XkbConfig::default(), 200, 25)
-                .expect("Failed to load xkb configuration files");
-        }
-        seat.add_pointer();
+        Self::init_seat_devices(&mut seat);
+
+        let seats = CONFIG
+            .seats
+            .keys()
+            .filter(|name| **name != seat_name)
+            .map(|name| {
+                let mut seat = seat_state.new_wl_seat(&dh, name.clone());
+                Self::init_seat_devices(&mut seat);
+                seat
+            })
+            .collect();
 
         let workspaces = Workspaces::new(CONFIG.workspaces);
 
         let socket_name = Self::init_wayland_listener(&mut loop_handle, display);
+        let ipc = IpcState::new(&loop_handle, &socket_name.to_string_lossy());
+
+        let (clipboard_sender, clipboard_channel) = channel::channel();
+        loop_handle
+            .insert_source(clipboard_channel, |event, _, data| {
+                let state = &mut data.state;
+                if let Event::Msg(selection) = event {
+                    // take over the selection once it is fully read
+                    if let Some(mime_types) = state.clipboard.insert(selection) {
+                        state.data_control_state.set_selection::<Self>(
+                            &state.dh,
+                            Some(&mime_types),
+                            false,
+                        );
+                        set_data_device_selection(&state.dh, &state.seat, mime_types);
+                    }
+                }
+            })
+            .expect("Failed to init the clipboard event source.");
+
+        let (primary_clipboard_sender, primary_clipboard_channel) = channel::channel();
+        loop_handle
+            .insert_source(primary_clipboard_channel, |event, _, data| {
+                let state = &mut data.state;
+                if let Event::Msg(selection) = event {
+                    if let Some(mime_types) = state.primary_clipboard.insert(selection) {
+                        state.data_control_state.set_selection::<Self>(
+                            &state.dh,
+                            Some(&mime_types),
+                            true,
+                        );
+                        // text selected in a client stays owned by it, so it isn't deselected
+                        if state.data_control_state.has_source(true) {
+                            set_primary_selection(&state.dh, &state.seat, mime_types);
+                        }
+                    }
+                }
+            })
+            .expect("Failed to init the primary selection event source.");
+
+        // reap spawned processes once they exit so they don't linger as zombies
+        let child_signal = Signals::new(&[Signal::SIGCHLD])
+            .expect("Failed to block SIGCHLD for reaping spawned processes.");
+        loop_handle
+            .insert_source(child_signal, |_, _, data| data.state.reap_children())
+            .expect("Failed to init the child exit signal.");
+
+        // reload the config on SIGUSR1, e.g. from `pkill -USR1 magmawm` after editing it
+        let reload_signal =
+            Signals::new(&[Signal::SIGUSR1]).expect("Failed to block SIGUSR1 for config reloads.");
+        loop_handle
+            .insert_source(reload_signal, |_, _, data| data.state.reload_config())
+            .expect("Failed to init the config reload signal.");
+
+        loop_handle
+            .insert_source(Timer::from_duration(IDLE_POLL_INTERVAL), |_, _, data| {
+                data.state.check_idle();
+                TimeoutAction::ToDuration(IDLE_POLL_INTERVAL)
+            })
+            .expect("Failed to init the idle timer.");
 
         Self {
             loop_handle,
             dh,
             backend_data,
             start_time,
+            clock,
             seat_name,
             socket_name,
             compositor_state,
@@ -126,11 +358,287 @@ impl<BackendData: Backend> MagmaState<BackendData> {
             data_device_state,
             primary_selection_state,
             layer_shell_state,
+            keyboard_shortcuts_inhibit_state,
+            xdg_foreign_state,
+            virtual_keyboard_manager_state,
+            virtual_pointer_manager_state,
+            idle_inhibit_manager_state,
+            pointer_constraints_state,
+            relative_pointer_manager_state,
+            pointer_gestures_state,
+            data_control_state,
+            xdg_activation_state,
+            fractional_scale_manager_state,
+            viewporter_state,
+            presentation_state,
+            tablet_manager_state: None,
             seat,
+            seats,
             workspaces,
             pointer_location: Point::from((0.0, 0.0)),
+            pointer_seat: seat_name.clone(),
+            seat_pointers: HashMap::new(),
+            touch_points: 0,
+            bound_buttons: Vec::new(),
+            bound_gesture: None,
+            cursor_status: CursorImageStatus::Default,
+            dnd_icon: None,
+            focused_output: None,
+            focus_timer: None,
+            passthrough: false,
+            passthrough_frame: OutputFrame::default(),
+            confine_pointer: false,
+            last_spawn: HashMap::new(),
+            spawn_workspaces: HashMap::new(),
+            children: Vec::new(),
+            spawn_tokens: Vec::new(),
+            frame_stats: HashMap::new(),
+            modifier_tap: None,
+            last_modifier_tap: None,
+            tap_timer: None,
+            pending_action: None,
+            last_title_bar_click: None,
+            hot_corner: None,
+            quit_requested: None,
+            quit_message: Message::new("Quit again to confirm"),
+            carry_window: false,
+            focus_history: Vec::new(),
+            scratchpad: Vec::new(),
+            scratchpad_shown: None,
+            last_input: Instant::now(),
+            idle: false,
+            outputs_powered: true,
+            idle_inhibitors: Vec::new(),
+            focus_mode: false,
+            clipboard: ClipboardCache::default(),
+            clipboard_sender,
+            primary_clipboard: ClipboardCache::default(),
+            primary_clipboard_sender,
+            ipc,
+        }
+    }
+    /// Read the config file again, keeping the current config if the new one is invalid.
+    pub fn reload_config(&mut self) {
+        let Some(path) = config_path() else {
+            error!("No config file found, keeping the current config");
+            return;
+        };
+        match try_load_config(&path) {
+            Ok(config) => {
+                let old = CONFIG.replace(config);
+                self.workspaces.remove_unused();
+                let (repeat_delay, repeat_rate) = CONFIG.repeat_info();
+                for seat in std::iter::once(&self.seat).chain(&self.seats) {
+                    if let Some(keyboard) = seat.get_keyboard() {
+                        keyboard.change_repeat_info(repeat_rate, repeat_delay);
+                    }
+                }
+                for (id, workspace) in self.workspaces.enumerate_mut() {
+                    workspace.update_config(id, old);
+                    update_layout(workspace);
+                }
+                self.backend_data.apply_input_config();
+                info!("Reloaded config from {}", path.display());
+            }
+            Err(err) => error!("Invalid config, keeping the current one: {}", err),
         }
     }
+
+    /// Run `CONFIG.idle_command` once no input arrived for `CONFIG.idle_timeout` seconds,
+    /// and turn the outputs off after `CONFIG.dpms_timeout` seconds, unless a surface
+    /// inhibits idling.
+    fn check_idle(&mut self) {
+        self.idle_inhibitors.retain(|surface| surface.alive());
+        if self
+            .idle_inhibitors
+            .iter()
+            .any(|surface| self.is_surface_shown(surface))
+        {
+            // the timeouts start once the last inhibitor goes away or is hidden
+            self.last_input = Instant::now();
+            return;
+        }
+        let elapsed = self.last_input.elapsed();
+        if !self.idle
+            && CONFIG.idle_timeout != 0
+            && elapsed >= Duration::from_secs(CONFIG.idle_timeout)
+        {
+            self.idle = true;
+            info!("Session is idle");
+            if let Some(command) = &CONFIG.idle_command {
+                self.spawn(command, None);
+            }
+        }
+        if self.outputs_powered
+            && CONFIG.dpms_timeout != 0
+            && elapsed >= Duration::from_secs(CONFIG.dpms_timeout)
+        {
+            self.outputs_powered = false;
+            info!("Turning outputs off");
+            self.backend_data.set_outputs_powered(false);
+        }
+    }
+
+    /// Whether `surface` belongs to a layer surface or a window on a shown workspace.
+    fn is_surface_shown(&self, surface: &WlSurface) -> bool {
+        let mut root = surface.clone();
+        while let Some(parent) = get_parent(&root) {
+            root = parent;
+        }
+        let on_layer = self.workspaces.outputs().any(|output| {
+            layer_map_for_output(output)
+                .layer_for_surface(&root, WindowSurfaceType::TOPLEVEL)
+                .is_some()
+        });
+        on_layer
+            || self
+                .workspaces
+                .all_windows()
+                .find(|w| w.toplevel().wl_surface() == &root)
+                .is_some_and(|w| self.workspaces.is_shown(&w))
+    }
+
+    /// Reset the idle timeout and wake the outputs, called on every input event.
+    pub fn notify_activity(&mut self) {
+        self.last_input = Instant::now();
+        if self.idle {
+            self.idle = false;
+            info!("Session is active again");
+        }
+        if !self.outputs_powered {
+            self.outputs_powered = true;
+            info!("Turning outputs on");
+            self.backend_data.set_outputs_powered(true);
+        }
+    }
+
+    /// Run `command` connected to this compositor, passing `activation_token` on so its window
+    /// may take the focus once it maps. Returns the pid of the shell, failures are logged.
+    pub fn spawn(&mut self, command: &SpawnCommand, activation_token: Option<&str>) -> Option<u32> {
+        let mut shell = Command::new("/bin/sh");
+        shell
+            .arg("-c")
+            .arg(command.command())
+            .env("WAYLAND_DISPLAY", &self.socket_name);
+        if let Some(token) = activation_token {
+            shell
+                .env("XDG_ACTIVATION_TOKEN", token)
+                .env("DESKTOP_STARTUP_ID", token);
+        }
+        if let SpawnCommand::WithOptions { env, cwd, .. } = command {
+            shell.envs(env);
+            if let Some(cwd) = cwd {
+                shell.current_dir(cwd);
+            }
+        }
+        match shell.spawn() {
+            Ok(child) => {
+                let pid = child.id();
+                // kept to be reaped on SIGCHLD, double forking would lose the pid
+                // SpawnOnWorkspace matches windows against
+                self.children.push(child);
+                // a SIGCHLD delivered to another thread is missed, so also reap here
+                self.reap_children();
+                Some(pid)
+            }
+            Err(err) => {
+                warn!("Failed to spawn \"{}\": {}", command.command(), err);
+                None
+            }
+        }
+    }
+
+    /// Reap spawned processes that exited, only waiting on their own pids so other
+    /// processes that are waited for, like `fc-match`, are left alone.
+    fn reap_children(&mut self) {
+        let spawn_workspaces = &mut self.spawn_workspaces;
+        self.children.retain_mut(|child| match child.try_wait() {
+            Ok(None) => true,
+            // exited, or already reaped
+            _ => {
+                spawn_workspaces.remove(&child.id());
+                false
+            }
+        });
+    }
+
+    fn init_seat_devices(seat: &mut Seat<Self>) {
+        let conf = CONFIG.xkb.clone();
+        let (repeat_delay, repeat_rate) = CONFIG.repeat_info();
+        if let Err(err) = seat.add_keyboard((&conf).into(), repeat_delay, repeat_rate) {
+            warn!(
+                ?err,
+                "Failed to load provided xkb config. Trying default...",
+            );
+            seat.add_keyboard(XkbConfig::default(), repeat_delay, repeat_rate)
+                .expect("Failed to load xkb configuration files");
+        }
+        seat.add_pointer();
+        seat.add_touch();
+    }
+
+    /// Make `pointer_location` and `cursor_status` refer to the pointer of `seat`, keeping
+    /// those of the previous seat until its pointer is used again.
+    pub fn activate_pointer_seat(&mut self, seat: &Seat<Self>) {
+        if seat.name() == self.pointer_seat {
+            return;
+        }
+        let (location, cursor_status) = self
+            .seat_pointers
+            .remove(seat.name())
+            .unwrap_or((self.pointer_location, CursorImageStatus::Default));
+        let previous = std::mem::replace(&mut self.pointer_seat, seat.name().to_string());
+        self.seat_pointers.insert(
+            previous,
+            (
+                std::mem::replace(&mut self.pointer_location, location),
+                std::mem::replace(&mut self.cursor_status, cursor_status),
+            ),
+        );
+    }
+
+    /// Location and cursor of the pointers of all seats, cursor surfaces that are gone
+    /// fall back to the default cursor.
+    pub fn pointers(&self) -> Vec<(Point<f64, Logical>, CursorImageStatus)> {
+        std::iter::once((self.pointer_location, self.cursor_status.clone()))
+            .chain(self.seat_pointers.values().cloned())
+            .map(|(location, cursor_status)| match cursor_status {
+                CursorImageStatus::Surface(surface) if !surface.alive() => {
+                    (location, CursorImageStatus::Default)
+                }
+                cursor_status => (location, cursor_status),
+            })
+            .collect()
+    }
+
+    /// Forget an unplugged output, its workspace stays around to be shown on another one.
+    pub fn remove_output(&mut self, output: &Output) {
+        for layer in layer_map_for_output(output).layers() {
+            layer.layer_surface().send_close();
+        }
+        self.workspaces.remove_output(output);
+        if self.focused_output.as_ref() == Some(output) {
+            self.focused_output = None;
+        }
+        // bring the pointer back onto the remaining outputs
+        self.pointer_location = self.clamp_coords(self.pointer_location);
+        self.update_pointer_output();
+        self.set_input_focus_auto();
+    }
+
+    /// Returns the seat an input device is assigned to in `CONFIG.seats`,
+    /// falling back to the primary seat.
+    pub fn seat_for_device<D: Device>(&self, device: &D) -> Seat<Self> {
+        let device_name = device.name();
+        CONFIG
+            .seats
+            .iter()
+            .find(|(_, devices)| devices.contains(&device_name))
+            .and_then(|(seat_name, _)| self.seats.iter().find(|s| s.name() == seat_name.as_str()))
+            .unwrap_or(&self.seat)
+            .clone()
+    }
+
     fn init_wayland_listener(
         handle: &mut LoopHandle<'static, CalloopData<BackendData>>,
         display: &mut Display<MagmaState<BackendData>>,
@@ -173,6 +681,64 @@ impl<BackendData: Backend> MagmaState<BackendData> {
         socket_name
     }
 
+    pub fn focused_window(&self) -> Option<Window> {
+        match self.seat.get_keyboard()?.current_focus()? {
+            FocusTarget::Window(w) => Some(w),
+            _ => None,
+        }
+    }
+
+    /// The focused output, or the first one of the current workspace if none was focused.
+    pub fn active_output(&self) -> Option<Output> {
+        let workspace = self.workspaces.current();
+        self.focused_output
+            .as_ref()
+            .filter(|o| workspace.outputs().any(|wo| wo == *o))
+            .or_else(|| workspace.outputs().next())
+            .cloned()
+    }
+
+    /// A human readable snapshot of the compositor state, meant for bug reports.
+    pub fn debug_snapshot(&self) -> String {
+        let mut snapshot = String::from("MagmaWM state snapshot\n");
+        snapshot += &format!("pointer location: {:?}\n", self.pointer_location);
+        snapshot += &format!(
+            "focused: {:?}\n",
+            self.seat.get_keyboard().and_then(|k| k.current_focus())
+        );
+        for output in self.workspaces.outputs() {
+            snapshot += &format!(
+                "output {}: {:?} showing workspace {:?}\n",
+                output.name(),
+                self.workspaces.output_geometry(output),
+                self.workspaces.output_workspace(output)
+            );
+        }
+        for (id, workspace) in self.workspaces.enumerate() {
+            let current = if id == self.workspaces.current {
+                " (current)"
+            } else {
+                ""
+            };
+            let name = workspace
+                .name
+                .as_ref()
+                .map(|name| format!(" \"{}\"", name))
+                .unwrap_or_default();
+            snapshot += &format!("workspace {}{}{}:\n", id, name, current);
+            for window in workspace.magmawindows() {
+                snapshot += &format!(
+                    "  {:?} app_id={:?} title={:?} floating={}\n",
+                    window.rec,
+                    window.window.app_id(),
+                    window.window.title(),
+                    window.floating
+                );
+            }
+        }
+        snapshot
+    }
+
     pub fn window_under(&mut self) -> Option<(Window, Point<i32, Logical>)> {
         let pos = self.pointer_location;
         self.workspaces
@@ -181,31 +747,45 @@ impl<BackendData: Backend> MagmaState<BackendData> {
             .map(|(w, p)| (w.clone(), p))
     }
     pub fn surface_under(&self) -> Option<(FocusTarget, Point<i32, Logical>)> {
-        let pos = self.pointer_location;
-        let output = self.workspaces.current().outputs().find(|o| {
-            let geometry = self.workspaces.current().output_geometry(o).unwrap();
-            geometry.contains(pos.to_i32_round())
+        self.surface_under_at(self.pointer_location)
+    }
+
+    pub fn surface_under_at(
+        &self,
+        pos: Point<f64, Logical>,
+    ) -> Option<(FocusTarget, Point<i32, Logical>)> {
+        let output = self.workspaces.outputs().find(|o| {
+            self.workspaces
+                .output_geometry(o)
+                .is_some_and(|geometry| geometry.contains(pos.to_i32_round()))
         })?;
-        let output_geo = self.workspaces.current().output_geometry(output).unwrap();
+        let output_geo = self.workspaces.output_geometry(output).unwrap();
+        let workspace = self
+            .workspaces
+            .get(self.workspaces.output_workspace(output)?)?;
         let layers = layer_map_for_output(output);
 
-        let mut under = None;
-        if let Some(layer) = layers
-            .layer_under(WlrLayer::Overlay, pos)
-            .or_else(|| layers.layer_under(WlrLayer::Top, pos))
-        {
-            let layer_loc = layers.layer_geometry(layer).unwrap().loc;
-            under = Some((layer.clone().into(), output_geo.loc + layer_loc))
-        } else if let Some((window, location)) = self.workspaces.current().window_under(pos) {
-            under = Some((window.clone().into(), location));
-        } else if let Some(layer) = layers
-            .layer_under(WlrLayer::Bottom, pos)
-            .or_else(|| layers.layer_under(WlrLayer::Background, pos))
-        {
-            let layer_loc = layers.layer_geometry(layer).unwrap().loc;
-            under = Some((layer.clone().into(), output_geo.loc + layer_loc));
+        // only surfaces whose input region contains the pointer get it,
+        // clicks on e.g. transparent shadows fall through
+        let layer_under = |layer: WlrLayer| {
+            layers.layers_on(layer).rev().find_map(|surface| {
+                let layer_loc = layers.layer_geometry(surface)?.loc;
+                let local = pos - (output_geo.loc + layer_loc).to_f64();
+                surface
+                    .surface_under(local, WindowSurfaceType::ALL)
+                    .map(|_| (surface.clone().into(), output_geo.loc + layer_loc))
+            })
         };
-        under
+
+        layer_under(WlrLayer::Overlay)
+            .or_else(|| layer_under(WlrLayer::Top))
+            .or_else(|| {
+                workspace
+                    .window_under(pos)
+                    .map(|(window, location)| (window.clone().into(), location))
+            })
+            .or_else(|| layer_under(WlrLayer::Bottom))
+            .or_else(|| layer_under(WlrLayer::Background))
     }
 }
 
@@ -4,15 +4,17 @@ use std::{cell::RefCell, rc::Rc};
 
 use super::workspace::MagmaWindow;
 
+/// Layout tree of the tiled windows, generic over its leaves so layouts can be computed
+/// without any clients.
 #[derive(Clone)]
-pub enum BinaryTree {
+pub enum BinaryTree<T = Rc<RefCell<MagmaWindow>>> {
     Empty,
-    Window(Rc<RefCell<MagmaWindow>>),
+    Window(T),
     Split {
         split: HorizontalOrVertical,
         ratio: f32,
-        left: Box<BinaryTree>,
-        right: Box<BinaryTree>,
+        left: Box<BinaryTree<T>>,
+        right: Box<BinaryTree<T>>,
     },
 }
 
@@ -43,17 +45,12 @@ pub enum HorizontalOrVertical {
     Vertical,
 }
 
-impl BinaryTree {
+impl<T: Clone> BinaryTree<T> {
     pub fn new() -> Self {
         BinaryTree::Empty
     }
 
-    pub fn insert(
-        &mut self,
-        window: Rc<RefCell<MagmaWindow>>,
-        splitnew: HorizontalOrVertical,
-        rationew: f32,
-    ) {
+    pub fn insert(&mut self, window: T, splitnew: HorizontalOrVertical, rationew: f32) {
         match self {
             BinaryTree::Empty => {
                 *self = BinaryTree::Window(window);
@@ -77,6 +74,59 @@ impl BinaryTree {
         }
     }
 
+    /// The first window of the tree, which gets the master area in the layout.
+    pub fn first_window(&self) -> Option<T> {
+        match self {
+            BinaryTree::Empty => None,
+            BinaryTree::Window(w) => Some(w.clone()),
+            BinaryTree::Split { left, right, .. } => {
+                left.first_window().or_else(|| right.first_window())
+            }
+        }
+    }
+
+    /// All windows of the tree in layout order, the first one being the master.
+    pub fn windows(&self) -> Vec<T> {
+        match self {
+            BinaryTree::Empty => Vec::new(),
+            BinaryTree::Window(w) => vec![w.clone()],
+            BinaryTree::Split { left, right, .. } => {
+                let mut windows = left.windows();
+                windows.extend(right.windows());
+                windows
+            }
+        }
+    }
+
+    pub fn next_split(&self) -> HorizontalOrVertical {
+        match self {
+            BinaryTree::Empty => HorizontalOrVertical::Horizontal,
+            BinaryTree::Window(_w) => HorizontalOrVertical::Horizontal,
+            BinaryTree::Split {
+                left: _,
+                right,
+                split,
+                ratio: _,
+            } => {
+                if let BinaryTree::Split {
+                    left: _,
+                    right: _,
+                    split: _,
+                    ratio: _,
+                } = right.as_ref()
+                {
+                    right.next_split()
+                } else if *split == HorizontalOrVertical::Horizontal {
+                    HorizontalOrVertical::Vertical
+                } else {
+                    HorizontalOrVertical::Horizontal
+                }
+            }
+        }
+    }
+}
+
+impl BinaryTree {
     pub fn remove(&mut self, window: &Window) {
         match self {
             BinaryTree::Empty => {}
@@ -110,35 +160,26 @@ impl BinaryTree {
         }
     }
 
-    pub fn next_split(&self) -> HorizontalOrVertical {
+    /// Exchange the positions of two windows in the tree.
+    pub fn swap(&mut self, a: &Rc<RefCell<MagmaWindow>>, b: &Rc<RefCell<MagmaWindow>>) {
         match self {
-            BinaryTree::Empty => HorizontalOrVertical::Horizontal,
-            BinaryTree::Window(_w) => HorizontalOrVertical::Horizontal,
-            BinaryTree::Split {
-                left: _,
-                right,
-                split,
-                ratio: _,
-            } => {
-                if let BinaryTree::Split {
-                    left: _,
-                    right: _,
-                    split: _,
-                    ratio: _,
-                } = right.as_ref()
-                {
-                    right.next_split()
-                } else if *split == HorizontalOrVertical::Horizontal {
-                    HorizontalOrVertical::Vertical
-                } else {
-                    HorizontalOrVertical::Horizontal
+            BinaryTree::Empty => {}
+            BinaryTree::Window(w) => {
+                if Rc::ptr_eq(w, a) {
+                    *w = b.clone();
+                } else if Rc::ptr_eq(w, b) {
+                    *w = a.clone();
                 }
             }
+            BinaryTree::Split { left, right, .. } => {
+                left.swap(a, b);
+                right.swap(a, b);
+            }
         }
     }
 }
 
-impl Default for BinaryTree {
+impl<T: Clone> Default for BinaryTree<T> {
     fn default() -> Self {
         Self::new()
     }
@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, ErrorKind, Read, Write},
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::net::UnixStream,
+    },
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use smithay::reexports::{
+    calloop::channel::Sender,
+    nix::{
+        fcntl::{fcntl, FcntlArg, OFlag},
+        poll::{poll, PollFd, PollFlags},
+    },
+};
+use tracing::warn;
+
+/// Selections bigger than this are not kept around after their client exits
+const MAX_SELECTION_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Time a client has to hand over or take the whole contents of a selection
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transfers running at the same time, further ones are refused
+const MAX_TRANSFERS: usize = 16;
+
+static TRANSFERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Contents of a single mime type of a selection, read from its source client.
+pub struct SelectionData {
+    generation: u64,
+    mime_type: String,
+    data: Option<Vec<u8>>,
+}
+
+/// Copy of the current clipboard selection, owned by the compositor so it
+/// survives the client it was copied from.
+#[derive(Default)]
+pub struct ClipboardCache {
+    generation: u64,
+    pending: usize,
+    data: HashMap<String, Vec<u8>>,
+}
+
+impl ClipboardCache {
+    /// Start caching a new selection, returning a pipe for every mime type
+    /// that has to be handed to the source client.
+    ///
+    /// The contents are read on separate threads and sent through `sender`.
+    pub fn start(
+        &mut self,
+        mime_types: Vec<String>,
+        sender: &Sender<SelectionData>,
+    ) -> Vec<(String, OwnedFd)> {
+        self.generation += 1;
+        self.pending = 0;
+        self.data.clear();
+
+        mime_types
+            .into_iter()
+            .filter_map(|mime_type| {
+                let (reader, writer) = UnixStream::pair()
+                    .map_err(|err| warn!("Failed to create clipboard pipe: {}", err))
+                    .ok()?;
+                let sender = sender.clone();
+                let generation = self.generation;
+                let thread_mime_type = mime_type.clone();
+                let transfer = spawn_transfer(move || {
+                    sender
+                        .send(SelectionData {
+                            generation,
+                            mime_type: thread_mime_type,
+                            data: read_selection(reader),
+                        })
+                        .ok();
+                });
+                if !transfer {
+                    return None;
+                }
+                self.pending += 1;
+                Some((mime_type, OwnedFd::from(writer)))
+            })
+            .collect()
+    }
+
+    /// Store the contents of one mime type, returning the cached mime types
+    /// once the whole selection has been read.
+    pub fn insert(&mut self, selection: SelectionData) -> Option<Vec<String>> {
+        if selection.generation != self.generation || self.pending == 0 {
+            return None;
+        }
+        self.pending -= 1;
+        if let Some(data) = selection.data {
+            self.data.insert(selection.mime_type, data);
+        }
+
+        self.mime_types()
+    }
+
+    /// The cached mime types, `None` while the selection is still being read or if it is empty.
+    pub fn mime_types(&self) -> Option<Vec<String>> {
+        (self.pending == 0 && !self.data.is_empty()).then(|| self.data.keys().cloned().collect())
+    }
+
+    /// Abort caching of the selection currently being read.
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.pending = 0;
+        self.data.clear();
+    }
+
+    /// Write the cached contents of `mime_type` to `fd`.
+    pub fn send(&self, mime_type: &str, fd: OwnedFd) {
+        let Some(data) = self.data.get(mime_type).cloned() else {
+            return;
+        };
+        spawn_transfer(move || {
+            if let Err(err) = write_selection(fd, &data) {
+                warn!("Failed to send clipboard contents: {}", err);
+            }
+        });
+    }
+}
+
+/// Run `transfer` on its own thread, unless `MAX_TRANSFERS` are running already.
+fn spawn_transfer(transfer: impl FnOnce() + Send + 'static) -> bool {
+    if TRANSFERS.fetch_add(1, Ordering::SeqCst) >= MAX_TRANSFERS {
+        TRANSFERS.fetch_sub(1, Ordering::SeqCst);
+        warn!("Too many clipboard transfers running, dropping one");
+        return false;
+    }
+    thread::spawn(move || {
+        transfer();
+        TRANSFERS.fetch_sub(1, Ordering::SeqCst);
+    });
+    true
+}
+
+/// Read the contents of a selection until the source closes its end,
+/// `None` if it takes longer than `TRANSFER_TIMEOUT` or is too big to keep.
+fn read_selection(mut reader: UnixStream) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + TRANSFER_TIMEOUT;
+    let mut data = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())?;
+        reader.set_read_timeout(Some(remaining)).ok()?;
+        match reader.read(&mut buffer) {
+            Ok(0) => return Some(data),
+            Ok(len) => {
+                data.extend_from_slice(&buffer[..len]);
+                if data.len() as u64 > MAX_SELECTION_SIZE {
+                    return None;
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Write `data` to the pipe of a receiving client, giving up after `TRANSFER_TIMEOUT`.
+fn write_selection(fd: OwnedFd, data: &[u8]) -> io::Result<()> {
+    fcntl(fd.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+    let deadline = Instant::now() + TRANSFER_TIMEOUT;
+    let mut file = File::from(fd);
+    let mut written = 0;
+    while written < data.len() {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or(ErrorKind::TimedOut)?;
+        let mut fds = [PollFd::new(file.as_raw_fd(), PollFlags::POLLOUT)];
+        if poll(&mut fds, remaining.as_millis() as i32)? == 0 {
+            return Err(ErrorKind::TimedOut.into());
+        }
+        match file.write(&data[written..]) {
+            Ok(len) => written += len,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::{fs, path::Path};
+
+use tracing::warn;
+use xcursor::{
+    parser::{parse_xcursor, Image},
+    CursorTheme,
+};
+
+use crate::state::CONFIG;
+
+/// Default pointer of the XCursor theme from `CONFIG.cursor_theme` or `XCURSOR_THEME`.
+pub struct Cursor {
+    icons: Vec<Image>,
+    size: u32,
+}
+
+impl Cursor {
+    /// Load the default pointer of the configured theme, `None` if the theme has none.
+    pub fn load() -> Option<Cursor> {
+        let (theme, size) = theme_and_size();
+        let theme = CursorTheme::load(&theme);
+        let icons = ["default", "left_ptr"].into_iter().find_map(|name| {
+            let path = theme.load_icon(name)?;
+            load_icons(&path)
+        });
+        match icons {
+            Some(icons) => Some(Cursor { icons, size }),
+            None => {
+                warn!("Failed to load the cursor theme, using the builtin cursor");
+                None
+            }
+        }
+    }
+
+    /// The frame to show `millis` milliseconds after the start of the animation,
+    /// in the size closest to the configured one at `scale`, together with its index
+    /// that identifies it among all frames.
+    pub fn image(&self, scale: u32, millis: u32) -> (usize, &Image) {
+        let size = self.size * scale;
+        let total = nearest_images(size, &self.icons).fold(0, |acc, (_, image)| acc + image.delay);
+        let mut millis = if total == 0 { 0 } else { millis % total };
+
+        for (index, image) in nearest_images(size, &self.icons) {
+            if millis < image.delay {
+                return (index, image);
+            }
+            millis -= image.delay;
+        }
+        // cursors that are not animated have a delay of 0
+        nearest_images(size, &self.icons).next().unwrap()
+    }
+}
+
+/// Cursor theme and size, the config taking precedence over the environment.
+pub fn theme_and_size() -> (String, u32) {
+    let theme = CONFIG
+        .cursor_theme
+        .clone()
+        .or_else(|| std::env::var("XCURSOR_THEME").ok())
+        .unwrap_or_else(|| "default".into());
+    let size = CONFIG
+        .cursor_size
+        .or_else(|| std::env::var("XCURSOR_SIZE").ok()?.parse().ok())
+        .unwrap_or(24);
+    (theme, size)
+}
+
+fn load_icons(path: &Path) -> Option<Vec<Image>> {
+    let data = fs::read(path)
+        .map_err(|err| warn!("Failed to read cursor {}: {}", path.display(), err))
+        .ok()?;
+    parse_xcursor(&data).filter(|icons| !icons.is_empty())
+}
+
+/// All frames with the size closest to `size`, with their index in `images`.
+fn nearest_images(size: u32, images: &[Image]) -> impl Iterator<Item = (usize, &Image)> {
+    let nearest = images
+        .iter()
+        .min_by_key(|image| (size as i32 - image.size as i32).abs())
+        .unwrap();
+    images
+        .iter()
+        .enumerate()
+        .filter(move |(_, image)| image.width == nearest.width && image.height == nearest.height)
+}
@@ -1,4 +1,3 @@
-use smithay::desktop::Window;
 pub use smithay::{
     backend::input::KeyState,
     desktop::{LayerSurface, PopupKind},
@@ -11,6 +10,7 @@ pub use smithay::{
     utils::{IsAlive, Serial},
     wayland::seat::WaylandFocus,
 };
+use smithay::{desktop::Window, wayland::pointer_constraints::with_pointer_constraint};
 
 use crate::state::{Backend, MagmaState};
 
@@ -107,6 +107,14 @@ impl<BackendData: Backend> PointerTarget<MagmaState<BackendData>> for FocusTarge
         serial: Serial,
         time: u32,
     ) {
+        // constraints only apply while the surface has the pointer focus
+        if let (Some(surface), Some(pointer)) = (self.wl_surface(), seat.get_pointer()) {
+            with_pointer_constraint(&surface, &pointer, |constraint| {
+                if let Some(constraint) = constraint.filter(|c| c.is_active()) {
+                    constraint.deactivate();
+                }
+            });
+        }
         match self {
             FocusTarget::Window(w) => PointerTarget::leave(w, seat, data, serial, time),
             FocusTarget::LayerSurface(l) => PointerTarget::leave(l, seat, data, serial, time),
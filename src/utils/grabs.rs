@@ -0,0 +1,244 @@
+use smithay::{
+    desktop::Window,
+    input::pointer::{
+        AxisFrame, ButtonEvent, GrabStartData, MotionEvent, PointerGrab, PointerInnerHandle,
+        RelativeMotionEvent,
+    },
+    utils::{Logical, Point, Rectangle, Size},
+};
+
+use crate::{
+    config::Direction,
+    state::{Backend, MagmaState},
+    utils::{focus::FocusTarget, tiling::MIN_WINDOW_SIZE},
+};
+
+/// Moves a floating window along with the pointer until all buttons are released.
+pub struct MoveSurfaceGrab<BackendData: Backend + 'static> {
+    pub start_data: GrabStartData<MagmaState<BackendData>>,
+    pub window: Window,
+    /// Location of the window when the grab started
+    pub initial_window_location: Point<i32, Logical>,
+}
+
+impl<BackendData: Backend + 'static> PointerGrab<MagmaState<BackendData>>
+    for MoveSurfaceGrab<BackendData>
+{
+    fn motion(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        _focus: Option<(FocusTarget, Point<i32, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // no client gets pointer events while the window is moved
+        handle.motion(data, None, event);
+
+        let delta = event.location - self.start_data.location;
+        let location = self.initial_window_location + delta.to_i32_round();
+        if let Some(magmawindow) = data
+            .workspaces
+            .workspace_from_window(&self.window)
+            .and_then(|ws| ws.magmawindow(&self.window))
+        {
+            magmawindow.borrow_mut().rec.loc = location;
+        }
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        focus: Option<(FocusTarget, Point<i32, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, focus, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+        if handle.current_pressed().is_empty() {
+            // the pointer focus is picked up again with the next motion
+            handle.unset_grab(data, event.serial, event.time);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details)
+    }
+
+    fn start_data(&self) -> &GrabStartData<MagmaState<BackendData>> {
+        &self.start_data
+    }
+}
+
+/// Resizes a window from the edges closest to the pointer until all buttons are released.
+/// Floating windows change their size, tiled ones move the splits next to them.
+pub struct ResizeSurfaceGrab<BackendData: Backend + 'static> {
+    pub start_data: GrabStartData<MagmaState<BackendData>>,
+    pub window: Window,
+    /// The horizontal and vertical edge being dragged, if any
+    pub edges: (Option<Direction>, Option<Direction>),
+    /// Geometry of the window when the grab started
+    pub initial_window_rec: Rectangle<i32, Logical>,
+    /// Pointer location of the last motion, tiled windows are resized step by step
+    pub last_location: Point<f64, Logical>,
+}
+
+/// The edges of `rec` closest to `location`, the bottom right corner if it is in the middle.
+pub fn edges_at(
+    rec: Rectangle<i32, Logical>,
+    location: Point<f64, Logical>,
+) -> (Option<Direction>, Option<Direction>) {
+    let third = |loc: i32, size: i32, pos: f64| {
+        let pos = (pos - loc as f64) * 3.0 / size.max(1) as f64;
+        if pos < 1.0 {
+            Some(false)
+        } else if pos >= 2.0 {
+            Some(true)
+        } else {
+            None
+        }
+    };
+    let horizontal = third(rec.loc.x, rec.size.w, location.x).map(|right| {
+        if right {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    });
+    let vertical = third(rec.loc.y, rec.size.h, location.y).map(|down| {
+        if down {
+            Direction::Down
+        } else {
+            Direction::Up
+        }
+    });
+    match (horizontal, vertical) {
+        (None, None) => (Some(Direction::Right), Some(Direction::Down)),
+        edges => edges,
+    }
+}
+
+impl<BackendData: Backend + 'static> PointerGrab<MagmaState<BackendData>>
+    for ResizeSurfaceGrab<BackendData>
+{
+    fn motion(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        _focus: Option<(FocusTarget, Point<i32, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // no client gets pointer events while the window is resized
+        handle.motion(data, None, event);
+
+        let Some(workspace) = data.workspaces.workspace_from_window(&self.window) else {
+            return;
+        };
+        let Some(magmawindow) = workspace.magmawindow(&self.window) else {
+            return;
+        };
+
+        if !magmawindow.borrow().floating {
+            let step = (event.location - self.last_location).to_i32_round::<i32>();
+            if step.x == 0 && step.y == 0 {
+                return;
+            }
+            self.last_location = event.location;
+            for (edge, amount) in [(self.edges.0, step.x), (self.edges.1, step.y)] {
+                match edge {
+                    // dragging towards the edge grows the window
+                    Some(edge @ (Direction::Right | Direction::Down)) if amount != 0 => {
+                        workspace.resize_window(&self.window, edge, amount)
+                    }
+                    Some(edge @ (Direction::Left | Direction::Up)) if amount != 0 => {
+                        workspace.resize_window(&self.window, edge, -amount)
+                    }
+                    _ => {}
+                }
+            }
+            return;
+        }
+
+        let delta = (event.location - self.start_data.location).to_i32_round::<i32>();
+        let initial = self.initial_window_rec;
+        let mut magmawindow = magmawindow.borrow_mut();
+        let (dw, dh) = (
+            match self.edges.0 {
+                Some(Direction::Left) => -delta.x,
+                Some(Direction::Right) => delta.x,
+                _ => 0,
+            },
+            match self.edges.1 {
+                Some(Direction::Up) => -delta.y,
+                Some(Direction::Down) => delta.y,
+                _ => 0,
+            },
+        );
+        let size = magmawindow.clamp_size(Size::from((
+            (initial.size.w + dw).max(MIN_WINDOW_SIZE.min(initial.size.w)),
+            (initial.size.h + dh).max(MIN_WINDOW_SIZE.min(initial.size.h)),
+        )));
+        // dragging the left or top edge keeps the opposite edge in place
+        let mut loc = initial.loc;
+        if self.edges.0 == Some(Direction::Left) {
+            loc.x += initial.size.w - size.w;
+        }
+        if self.edges.1 == Some(Direction::Up) {
+            loc.y += initial.size.h - size.h;
+        }
+        magmawindow.rec = Rectangle::from_loc_and_size(loc, size);
+
+        let xdg_toplevel = self.window.toplevel();
+        xdg_toplevel.with_pending_state(|state| {
+            state.size = Some(size);
+        });
+        xdg_toplevel.send_configure();
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        focus: Option<(FocusTarget, Point<i32, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, focus, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+        if handle.current_pressed().is_empty() {
+            handle.unset_grab(data, event.serial, event.time);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut MagmaState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, MagmaState<BackendData>>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details)
+    }
+
+    fn start_data(&self) -> &GrabStartData<MagmaState<BackendData>> {
+        &self.start_data
+    }
+}
@@ -0,0 +1,579 @@
+//! Unix socket for scripts and status bars, speaking one JSON request, reply or event per line.
+//!
+//! Replies are `{"Ok": ...}` or `{"Err": "..."}`. Connections that sent `Subscribe` are
+//! told about the kinds of changes they subscribed to:
+//! - `Workspace`: the workspaces, whenever one is activated, added, renamed or changes windows
+//! - `Focus`: the focused window, also when its title changes
+//! - `Window`: windows opening, closing or changing their title
+//! - `Passthrough`: keyboard passthrough being toggled
+
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use smithay::{
+    desktop::Window,
+    reexports::calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction},
+};
+use tracing::{info, warn};
+
+use crate::{
+    config::Action,
+    state::{Backend, CalloopData, MagmaState},
+    utils::window::WindowExt,
+};
+
+/// Longest request line a client may send before it is dropped
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+/// Most bytes queued for a client that doesn't read its replies and events before it is dropped
+const MAX_QUEUED_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub enum Request {
+    GetWorkspaces,
+    GetOutputs,
+    GetFocusedWindow,
+    GetFrameStats,
+    /// Whether keyboard passthrough is active
+    GetPassthrough,
+    Action(Action),
+    Subscribe(Vec<EventKind>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    Workspace,
+    Focus,
+    Window,
+    Passthrough,
+}
+
+#[derive(Debug, Serialize)]
+enum Event {
+    Workspace(Vec<WorkspaceInfo>),
+    Focus(Option<WindowInfo>),
+    WindowOpened(WindowInfo),
+    WindowClosed(WindowInfo),
+    WindowTitle(WindowInfo),
+    Passthrough(bool),
+}
+
+impl Event {
+    fn kind(&self) -> EventKind {
+        match self {
+            Event::Workspace(_) => EventKind::Workspace,
+            Event::Focus(_) => EventKind::Focus,
+            Event::WindowOpened(_) | Event::WindowClosed(_) | Event::WindowTitle(_) => {
+                EventKind::Window
+            }
+            Event::Passthrough(_) => EventKind::Passthrough,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct WorkspaceInfo {
+    id: u8,
+    name: Option<String>,
+    /// Output the workspace is shown on
+    output: Option<String>,
+    focused: bool,
+    windows: usize,
+    /// Whether a window on the workspace requested attention
+    urgent: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct OutputInfo {
+    name: String,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    scale: f64,
+    workspace: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+struct FrameStatsInfo {
+    output: String,
+    fps: usize,
+    /// Average render time of the recent frames in microseconds
+    average_render_time: u128,
+    /// Render times of the recent frames in microseconds, oldest first
+    render_times: Vec<u128>,
+    dropped_frames: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct WindowInfo {
+    app_id: Option<String>,
+    title: Option<String>,
+    workspace: Option<u8>,
+    floating: bool,
+    urgent: bool,
+}
+
+/// A connection, the part of a request read so far and what is still to be written.
+struct IpcClient {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    outgoing: Vec<u8>,
+    subscriptions: Vec<EventKind>,
+}
+
+impl IpcClient {
+    /// Read everything available and return the complete request lines,
+    /// or `None` if the connection was closed or the client misbehaves.
+    fn receive(&mut self) -> Option<Vec<Vec<u8>>> {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return None,
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return None,
+            }
+        }
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            lines.push(self.buffer.drain(..=end).collect());
+        }
+        (self.buffer.len() <= MAX_REQUEST_LEN).then_some(lines)
+    }
+
+    /// Queue a line and write as much as the socket takes,
+    /// returns false if the client should be dropped.
+    fn send<T: Serialize>(&mut self, value: &T) -> bool {
+        let Ok(line) = serde_json::to_vec(value) else {
+            return false;
+        };
+        self.outgoing.extend_from_slice(&line);
+        self.outgoing.push(b'\n');
+        self.outgoing.len() <= MAX_QUEUED_LEN && self.flush()
+    }
+
+    /// Write queued lines until the socket would block, returns false if the client is gone.
+    fn flush(&mut self) -> bool {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return false,
+                Ok(len) => {
+                    self.outgoing.drain(..len);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Drop for IpcClient {
+    fn drop(&mut self) {
+        // wakes up the event source of the connection, which then removes itself
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+pub struct IpcState {
+    path: PathBuf,
+    clients: HashMap<usize, IpcClient>,
+    next_client_id: usize,
+    /// Last state sent to subscribers, to only send changes
+    last_workspaces: Vec<WorkspaceInfo>,
+    last_focus: Option<WindowInfo>,
+    last_windows: Vec<(Window, WindowInfo)>,
+    last_passthrough: bool,
+}
+
+impl IpcState {
+    /// Listen on `$XDG_RUNTIME_DIR/magmawm-<socket_name>.sock`, exported as `MAGMAWM_SOCK`.
+    pub fn new<BackendData: Backend + 'static>(
+        handle: &LoopHandle<'static, CalloopData<BackendData>>,
+        socket_name: &str,
+    ) -> Option<Self> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")?;
+        let path = PathBuf::from(runtime_dir).join(format!("magmawm-{}.sock", socket_name));
+        // a previous instance with the same wayland socket is gone by now
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)
+            .map_err(|err| warn!("Failed to create the ipc socket: {}", err))
+            .ok()?;
+        listener.set_nonblocking(true).ok()?;
+
+        let loop_handle = handle.clone();
+        handle
+            .insert_source(
+                Generic::new(listener, Interest::READ, Mode::Level),
+                move |_, listener, data| {
+                    while let Ok((stream, _)) = listener.accept() {
+                        if let Some(ipc) = &mut data.state.ipc {
+                            ipc.add_client(&loop_handle, stream);
+                        }
+                    }
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|err| warn!("Failed to init the ipc event source: {}", err))
+            .ok()?;
+
+        info!("Listening for ipc connections on {}", path.display());
+        std::env::set_var("MAGMAWM_SOCK", &path);
+        Some(IpcState {
+            path,
+            clients: HashMap::new(),
+            next_client_id: 0,
+            last_workspaces: Vec::new(),
+            last_focus: None,
+            last_windows: Vec::new(),
+            last_passthrough: false,
+        })
+    }
+
+    fn has_subscribers(&self) -> bool {
+        self.clients
+            .values()
+            .any(|client| !client.subscriptions.is_empty())
+    }
+
+    fn add_client<BackendData: Backend + 'static>(
+        &mut self,
+        handle: &LoopHandle<'static, CalloopData<BackendData>>,
+        stream: UnixStream,
+    ) {
+        // a slow reader must not block us, its replies and events are queued instead
+        let Ok(source) = stream
+            .set_nonblocking(true)
+            .and_then(|_| stream.try_clone())
+        else {
+            return;
+        };
+        let id = self.next_client_id;
+        self.next_client_id += 1;
+
+        // edge triggered, so writability only wakes us up once the client read what was queued
+        let result = handle.insert_source(
+            Generic::new(source, Interest::BOTH, Mode::Edge),
+            move |_, _, data| {
+                let Some(client) = data
+                    .state
+                    .ipc
+                    .as_mut()
+                    .and_then(|ipc| ipc.clients.get_mut(&id))
+                else {
+                    return Ok(PostAction::Remove);
+                };
+                let Some(lines) = client.receive() else {
+                    data.state.ipc_drop_client(id);
+                    return Ok(PostAction::Remove);
+                };
+
+                for line in lines {
+                    let reply = match serde_json::from_slice::<Request>(&line) {
+                        Ok(Request::Subscribe(kinds)) => {
+                            data.state.ipc_subscribe(id, kinds);
+                            Ok(Value::Null)
+                        }
+                        Ok(request) => data.state.handle_ipc_request(request),
+                        Err(err) => Err(err.to_string()),
+                    };
+                    if !data.state.ipc_send(id, &reply) {
+                        return Ok(PostAction::Remove);
+                    }
+                }
+                let flushed = data
+                    .state
+                    .ipc
+                    .as_mut()
+                    .and_then(|ipc| ipc.clients.get_mut(&id))
+                    .is_some_and(|client| client.flush());
+                if !flushed {
+                    data.state.ipc_drop_client(id);
+                    return Ok(PostAction::Remove);
+                }
+                Ok(PostAction::Continue)
+            },
+        );
+        match result {
+            Ok(_) => {
+                self.clients.insert(
+                    id,
+                    IpcClient {
+                        stream,
+                        buffer: Vec::new(),
+                        outgoing: Vec::new(),
+                        subscriptions: Vec::new(),
+                    },
+                );
+            }
+            Err(err) => warn!("Failed to add an ipc client: {}", err),
+        }
+    }
+}
+
+impl Drop for IpcState {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl<BackendData: Backend> MagmaState<BackendData> {
+    pub fn handle_ipc_request(&mut self, request: Request) -> Result<Value, String> {
+        let value = match request {
+            Request::GetWorkspaces => serde_json::to_value(self.workspace_infos()),
+            Request::GetOutputs => serde_json::to_value(self.output_infos()),
+            Request::GetFocusedWindow => serde_json::to_value(self.focused_window_info()),
+            Request::GetFrameStats => serde_json::to_value(self.frame_stats_infos()),
+            Request::GetPassthrough => serde_json::to_value(self.passthrough),
+            Request::Action(Action::VTSwitch(_)) => {
+                return Err("VTSwitch is not supported over ipc".into())
+            }
+            Request::Action(action) => {
+                self.handle_action(action);
+                Ok(Value::Null)
+            }
+            Request::Subscribe(_) => return Err("Subscribe is handled by the connection".into()),
+        };
+        value.map_err(|err| err.to_string())
+    }
+
+    /// Queue a reply for a client, dropping it if it can't keep up.
+    fn ipc_send<T: Serialize>(&mut self, id: usize, value: &T) -> bool {
+        let Some(ipc) = &mut self.ipc else {
+            return false;
+        };
+        let sent = ipc
+            .clients
+            .get_mut(&id)
+            .is_some_and(|client| client.send(value));
+        if !sent {
+            ipc.clients.remove(&id);
+        }
+        sent
+    }
+
+    fn ipc_drop_client(&mut self, id: usize) {
+        if let Some(ipc) = &mut self.ipc {
+            ipc.clients.remove(&id);
+        }
+    }
+
+    fn ipc_subscribe(&mut self, id: usize, kinds: Vec<EventKind>) {
+        let Some(ipc) = &mut self.ipc else {
+            return;
+        };
+        let first = !ipc.has_subscribers();
+        let Some(client) = ipc.clients.get_mut(&id) else {
+            return;
+        };
+        client.subscriptions.extend(kinds);
+        // nothing was tracked without subscribers, so only changes from now on are sent
+        if first {
+            let (workspaces, focus, windows) = self.ipc_snapshot();
+            let passthrough = self.passthrough;
+            let ipc = self.ipc.as_mut().unwrap();
+            ipc.last_workspaces = workspaces;
+            ipc.last_focus = focus;
+            ipc.last_windows = windows;
+            ipc.last_passthrough = passthrough;
+        }
+    }
+
+    /// Tell subscribers about changes since the last call.
+    pub fn send_ipc_events(&mut self) {
+        if self.ipc.as_ref().map_or(true, |ipc| !ipc.has_subscribers()) {
+            return;
+        }
+        let (workspaces, focus, windows) = self.ipc_snapshot();
+        let passthrough = self.passthrough;
+        let ipc = self.ipc.as_mut().unwrap();
+
+        let mut events = Vec::new();
+        if workspaces != ipc.last_workspaces {
+            events.push(Event::Workspace(workspaces.clone()));
+        }
+        if focus != ipc.last_focus {
+            events.push(Event::Focus(focus.clone()));
+        }
+        for (window, info) in &windows {
+            match ipc.last_windows.iter().find(|(last, _)| last == window) {
+                None => events.push(Event::WindowOpened(info.clone())),
+                Some((_, last)) if last.title != info.title => {
+                    events.push(Event::WindowTitle(info.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for (window, info) in &ipc.last_windows {
+            if !windows.iter().any(|(current, _)| current == window) {
+                events.push(Event::WindowClosed(info.clone()));
+            }
+        }
+        if passthrough != ipc.last_passthrough {
+            events.push(Event::Passthrough(passthrough));
+        }
+
+        ipc.clients.retain(|_, client| {
+            events
+                .iter()
+                .filter(|event| client.subscriptions.contains(&event.kind()))
+                .all(|event| client.send(event))
+        });
+        ipc.last_workspaces = workspaces;
+        ipc.last_focus = focus;
+        ipc.last_windows = windows;
+        ipc.last_passthrough = passthrough;
+    }
+
+    /// Everything subscribers are told about changes of.
+    #[allow(clippy::type_complexity)]
+    fn ipc_snapshot(
+        &self,
+    ) -> (
+        Vec<WorkspaceInfo>,
+        Option<WindowInfo>,
+        Vec<(Window, WindowInfo)>,
+    ) {
+        let windows = self
+            .workspaces
+            .all_windows()
+            .map(|window| (window.clone(), self.window_info(&window)))
+            .collect();
+        (self.workspace_infos(), self.focused_window_info(), windows)
+    }
+
+    fn workspace_infos(&self) -> Vec<WorkspaceInfo> {
+        self.workspaces
+            .enumerate()
+            .map(|(id, workspace)| WorkspaceInfo {
+                id,
+                name: workspace.name.clone(),
+                output: self
+                    .workspaces
+                    .outputs()
+                    .find(|o| self.workspaces.output_workspace(o) == Some(id))
+                    .map(|o| o.name()),
+                focused: id == self.workspaces.current,
+                windows: workspace.windows().count(),
+                urgent: workspace.magmawindows().any(|w| w.urgent.is_some()),
+            })
+            .collect()
+    }
+
+    fn output_infos(&self) -> Vec<OutputInfo> {
+        self.workspaces
+            .outputs()
+            .filter_map(|output| {
+                let geo = self.workspaces.output_geometry(output)?;
+                Some(OutputInfo {
+                    name: output.name(),
+                    x: geo.loc.x,
+                    y: geo.loc.y,
+                    width: geo.size.w,
+                    height: geo.size.h,
+                    scale: output.current_scale().fractional_scale(),
+                    workspace: self.workspaces.output_workspace(output),
+                })
+            })
+            .collect()
+    }
+
+    fn frame_stats_infos(&self) -> Vec<FrameStatsInfo> {
+        self.frame_stats
+            .iter()
+            .map(|(output, stats)| FrameStatsInfo {
+                output: output.clone(),
+                fps: stats.fps(),
+                average_render_time: stats.average_render_time().as_micros(),
+                render_times: stats.render_times().map(|t| t.as_micros()).collect(),
+                dropped_frames: stats.dropped_frames,
+            })
+            .collect()
+    }
+
+    fn focused_window_info(&self) -> Option<WindowInfo> {
+        self.focused_window()
+            .map(|window| self.window_info(&window))
+    }
+
+    fn window_info(&self, window: &Window) -> WindowInfo {
+        let workspace = self.workspaces.workspace_id(window);
+        let magmawindow = workspace.and_then(|id| self.workspaces.get(id)?.magmawindow(window));
+        let magmawindow = magmawindow.as_ref().map(|w| w.borrow());
+        WindowInfo {
+            app_id: window.app_id(),
+            title: window.title(),
+            workspace,
+            floating: magmawindow.as_ref().is_some_and(|w| w.floating),
+            urgent: magmawindow.as_ref().is_some_and(|w| w.urgent.is_some()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> (IpcClient, UnixStream) {
+        let (stream, peer) = UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let client = IpcClient {
+            stream,
+            buffer: Vec::new(),
+            outgoing: Vec::new(),
+            subscriptions: Vec::new(),
+        };
+        (client, peer)
+    }
+
+    #[test]
+    fn receive_splits_lines() {
+        let (mut client, mut peer) = client();
+        peer.write_all(b"\"GetOutputs\"\n\"GetWork").unwrap();
+        assert_eq!(client.receive(), Some(vec![b"\"GetOutputs\"\n".to_vec()]));
+        peer.write_all(b"spaces\"\n").unwrap();
+        assert_eq!(
+            client.receive(),
+            Some(vec![b"\"GetWorkspaces\"\n".to_vec()])
+        );
+    }
+
+    #[test]
+    fn receive_drops_overlong_lines() {
+        let (mut client, mut peer) = client();
+        peer.write_all(&[b'a'; MAX_REQUEST_LEN + 1]).unwrap();
+        assert_eq!(client.receive(), None);
+    }
+
+    #[test]
+    fn send_drops_clients_that_dont_read() {
+        let (mut client, _peer) = client();
+        let line = "a".repeat(4096);
+        let mut sent = 0;
+        while client.send(&line) {
+            sent += 1;
+            assert!(sent * line.len() <= 2 * MAX_QUEUED_LEN);
+        }
+        assert!(client.outgoing.len() > MAX_QUEUED_LEN);
+    }
+
+    #[test]
+    fn send_flushes_once_read() {
+        let (mut client, mut peer) = client();
+        assert!(client.send(&"hello"));
+        assert!(client.outgoing.is_empty());
+        let mut reply = [0; 8];
+        peer.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"\"hello\"\n");
+    }
+}
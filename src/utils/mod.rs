@@ -1,5 +1,12 @@
 pub mod binarytree;
+pub mod clipboard;
+pub mod cursor;
 pub mod focus;
+pub mod grabs;
+pub mod ipc;
 pub mod render;
+pub mod stats;
+pub mod text;
 pub mod tiling;
+pub mod window;
 pub mod workspace;
@@ -1,14 +1,392 @@
+use std::{
+    cell::{Ref, RefCell},
+    time::Duration,
+};
+
 use smithay::{
-    backend::renderer::{
-        element::{surface::WaylandSurfaceRenderElement, texture::TextureRenderElement},
-        ImportAll, ImportMem, Renderer,
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                default_primary_scanout_output_compare,
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                solid::SolidColorRenderElement,
+                surface::WaylandSurfaceRenderElement,
+                texture::TextureRenderElement,
+                Id, RenderElementStates,
+            },
+            utils::CommitCounter,
+            ImportAll, ImportMem, Renderer,
+        },
+    },
+    desktop::{
+        layer_map_for_output,
+        utils::{
+            surface_presentation_feedback_flags_from_states, surface_primary_scanout_output,
+            update_surface_primary_scanout_output, OutputPresentationFeedback,
+        },
+        LayerSurface, Window,
     },
+    output::Output,
     render_elements,
+    utils::{Buffer, Logical, Rectangle, Size, Transform},
+    wayland::compositor::with_states,
 };
 
+use super::{
+    text::{line_width, render_line},
+    workspace::{border_sides, Workspace},
+};
+
+/// Height of messages in logical pixels, and of the text in them
+const MESSAGE_HEIGHT: i32 = 40;
+const MESSAGE_TEXT_HEIGHT: i32 = 24;
+const MESSAGE_BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 0.9];
+const MESSAGE_TEXT: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+
+/// Width of the frame drawn around outputs while keyboard passthrough is active
+pub const PASSTHROUGH_FRAME_WIDTH: i32 = 3;
+
+/// Size of `output` in logical pixels, `None` without a mode.
+fn output_size(output: &Output) -> Option<Size<i32, Logical>> {
+    let scale = output.current_scale().fractional_scale();
+    output
+        .current_mode()
+        .map(|mode| mode.size.to_f64().to_logical(scale).to_i32_round())
+}
+
 render_elements! {
     pub CustomRenderElements<R> where
         R: ImportAll + ImportMem;
     Texture=TextureRenderElement<<R as Renderer>::TextureId>,
     Surface=WaylandSurfaceRenderElement<R>,
+    Solid=SolidColorRenderElement,
+    Memory=MemoryRenderBufferRenderElement<R>,
+}
+
+/// A line of text on a solid background, drawn in the center of outputs above everything.
+#[derive(Debug)]
+pub struct Message {
+    text: String,
+    background_id: Id,
+    /// Text rasterized at each scale of the outputs it was drawn on
+    rendered: RefCell<Vec<(f64, Size<i32, Logical>, MemoryRenderBuffer)>>,
+}
+
+impl Message {
+    pub fn new(text: impl Into<String>) -> Self {
+        Message {
+            text: text.into(),
+            background_id: Id::new(),
+            rendered: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// The rasterized text for `scale` and its size in logical pixels.
+    fn rendered(&self, scale: f64) -> Option<(Size<i32, Logical>, MemoryRenderBuffer)> {
+        let mut rendered = self.rendered.borrow_mut();
+        if let Some((_, size, buffer)) = rendered.iter().find(|(s, _, _)| *s == scale) {
+            return Some((*size, buffer.clone()));
+        }
+        let height = (MESSAGE_TEXT_HEIGHT as f64 * scale).round() as i32;
+        let width = line_width(&self.text, height)?;
+        let buffer_size = Size::<i32, Buffer>::from((width, height));
+        let pixels = render_line(&self.text, buffer_size, MESSAGE_TEXT)?;
+        let buffer = MemoryRenderBuffer::from_memory(
+            pixels.as_slice(),
+            Fourcc::Abgr8888,
+            buffer_size,
+            1,
+            Transform::Normal,
+            None,
+        );
+        let size = Size::from(((width as f64 / scale).ceil() as i32, MESSAGE_TEXT_HEIGHT));
+        rendered.push((scale, size, buffer.clone()));
+        Some((size, buffer))
+    }
+
+    /// Elements of the message centered on `output`, the text comes first to be on top.
+    pub fn render_elements<R, C>(&self, renderer: &mut R, output: &Output) -> Vec<C>
+    where
+        R: Renderer + ImportMem,
+        <R as Renderer>::TextureId: 'static,
+        C: From<SolidColorRenderElement> + From<MemoryRenderBufferRenderElement<R>>,
+    {
+        let scale = output.current_scale().fractional_scale();
+        let Some(output_size) = output_size(output) else {
+            return Vec::new();
+        };
+        let Some((text_size, buffer)) = self.rendered(scale) else {
+            return Vec::new();
+        };
+        let padding = (MESSAGE_HEIGHT - MESSAGE_TEXT_HEIGHT) / 2;
+        let size = Size::<i32, Logical>::from((text_size.w + 2 * padding, MESSAGE_HEIGHT));
+        let background = Rectangle::from_loc_and_size(
+            ((output_size.w - size.w) / 2, (output_size.h - size.h) / 2),
+            size,
+        );
+        let text_loc = background.loc + (padding, padding).into();
+
+        let mut elements = Vec::new();
+        if let Ok(text) = MemoryRenderBufferRenderElement::from_buffer(
+            renderer,
+            text_loc.to_physical_precise_round(scale).to_f64(),
+            &buffer,
+            None,
+            None,
+            Some(text_size),
+        ) {
+            elements.push(C::from(text));
+        }
+        elements.push(C::from(SolidColorRenderElement::new(
+            self.background_id.clone(),
+            background.to_physical_precise_round(scale),
+            CommitCounter::default(),
+            MESSAGE_BACKGROUND,
+        )));
+        elements
+    }
+}
+
+/// A frame along the edges of outputs, drawn above everything.
+#[derive(Debug)]
+pub struct OutputFrame {
+    ids: [Id; 4],
+}
+
+impl Default for OutputFrame {
+    fn default() -> Self {
+        OutputFrame {
+            ids: std::array::from_fn(|_| Id::new()),
+        }
+    }
+}
+
+impl OutputFrame {
+    /// Elements of a frame of `width` pixels along the edges of `output`.
+    pub fn render_elements<C: From<SolidColorRenderElement>>(
+        &self,
+        output: &Output,
+        width: i32,
+        color: [f32; 4],
+    ) -> Vec<C> {
+        let scale = output.current_scale().fractional_scale();
+        let Some(size) = output_size(output) else {
+            return Vec::new();
+        };
+        // the sides surround the inside, so they end at the edges of the output
+        let inside =
+            Rectangle::from_loc_and_size((width, width), (size.w - 2 * width, size.h - 2 * width));
+        self.ids
+            .iter()
+            .zip(border_sides(inside, width))
+            .map(|(id, side)| {
+                C::from(SolidColorRenderElement::new(
+                    id.clone(),
+                    side.to_physical_precise_round(scale),
+                    CommitCounter::default(),
+                    color,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Remember `output` as the primary output of the surfaces of `workspace` and its layer
+/// surfaces that are visible in the frame rendered with `states`.
+pub fn update_primary_scanout_output(
+    output: &Output,
+    workspace: Option<&Workspace>,
+    states: &RenderElementStates,
+) {
+    for window in workspace.into_iter().flat_map(|ws| ws.windows()) {
+        window.with_surfaces(|surface, data| {
+            update_surface_primary_scanout_output(
+                surface,
+                output,
+                data,
+                states,
+                default_primary_scanout_output_compare,
+            );
+        });
+    }
+    for layer in layer_map_for_output(output).layers() {
+        layer.with_surfaces(|surface, data| {
+            update_surface_primary_scanout_output(
+                surface,
+                output,
+                data,
+                states,
+                default_primary_scanout_output_compare,
+            );
+        });
+    }
+}
+
+/// A window or layer surface getting the frame callbacks of its primary output.
+enum FrameTarget<'a> {
+    Window(Ref<'a, Window>),
+    Layer(&'a LayerSurface),
+}
+
+impl FrameTarget<'_> {
+    fn primary_output(&self) -> Option<Output> {
+        let surface = match self {
+            FrameTarget::Window(window) => window.toplevel().wl_surface(),
+            FrameTarget::Layer(layer) => layer.wl_surface(),
+        };
+        with_states(surface, |states| {
+            surface_primary_scanout_output(surface, states)
+        })
+    }
+}
+
+/// Send frame callbacks to the surfaces of `workspace` and the layer surfaces whose primary
+/// output is `output`, once per frame of it. Hidden or occluded surfaces, and the windows of
+/// workspaces that aren't shown, get none until they become visible again.
+pub fn send_frames(output: &Output, workspace: Option<&Workspace>, time: Duration) {
+    let layer_map = layer_map_for_output(output);
+    let windows = workspace
+        .into_iter()
+        .flat_map(|ws| ws.windows())
+        .map(FrameTarget::Window);
+    let layers = layer_map.layers().map(FrameTarget::Layer);
+    for target in frame_targets(output, windows, layers, FrameTarget::primary_output) {
+        match target {
+            FrameTarget::Window(window) => {
+                window.send_frame(output, time, None, surface_primary_scanout_output)
+            }
+            FrameTarget::Layer(layer) => {
+                layer.send_frame(output, time, None, surface_primary_scanout_output)
+            }
+        }
+    }
+}
+
+/// The surfaces getting the frame callbacks of `output` out of the `windows` of the workspace
+/// shown on it and its `layers`: those whose primary output is `output`. Surfaces that
+/// weren't drawn on any output have none.
+fn frame_targets<'a, S: 'a, O: PartialEq + 'a>(
+    output: &'a O,
+    windows: impl Iterator<Item = S> + 'a,
+    layers: impl Iterator<Item = S> + 'a,
+    primary_output: impl Fn(&S) -> Option<O> + 'a,
+) -> impl Iterator<Item = S> + 'a {
+    windows
+        .chain(layers)
+        .filter(move |surface| primary_output(surface).as_ref() == Some(output))
+}
+
+/// Collect the presentation feedback of the windows of `workspace` and the layer surfaces
+/// drawn on `output` in the frame rendered with `states`, to be sent once it is presented.
+pub fn take_presentation_feedback(
+    output: &Output,
+    workspace: Option<&Workspace>,
+    states: &RenderElementStates,
+) -> OutputPresentationFeedback {
+    let mut feedback = OutputPresentationFeedback::new(output);
+    for window in workspace.into_iter().flat_map(|ws| ws.windows()) {
+        window.take_presentation_feedback(
+            &mut feedback,
+            surface_primary_scanout_output,
+            |surface, _| surface_presentation_feedback_flags_from_states(surface, states),
+        );
+    }
+    for layer in layer_map_for_output(output).layers() {
+        layer.take_presentation_feedback(
+            &mut feedback,
+            surface_primary_scanout_output,
+            |surface, _| surface_presentation_feedback_flags_from_states(surface, states),
+        );
+    }
+    feedback
+}
+
+/// Duration of one refresh cycle of `output` in nanoseconds, 0 if it has no mode.
+pub fn refresh_interval(output: &Output) -> u32 {
+    output
+        .current_mode()
+        .filter(|mode| mode.refresh > 0)
+        .map(|mode| (1_000_000_000_000 / mode.refresh as u64) as u32)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A surface and its primary output at the time of a frame
+    type Surface = (usize, Option<&'static str>);
+
+    /// Frame callbacks each surface gets when `frames` are rendered, given for each frame the
+    /// output, the windows of the workspace shown on it and its layer surfaces.
+    fn dispatches(frames: &[(&'static str, Vec<Surface>, Vec<Surface>)]) -> [usize; 4] {
+        let mut sent = [0; 4];
+        for (output, windows, layers) in frames {
+            let targets = frame_targets(
+                output,
+                windows.iter().copied(),
+                layers.iter().copied(),
+                |(_, primary)| *primary,
+            );
+            for (surface, _) in targets {
+                sent[surface] += 1;
+            }
+        }
+        sent
+    }
+
+    #[test]
+    fn frames_only_reach_surfaces_on_their_primary_output() {
+        // one window on each output and one that isn't drawn anywhere
+        let windows = vec![(0, Some("left")), (1, Some("right")), (2, None)];
+        let frames = [
+            ("left", windows.clone(), vec![]),
+            ("right", windows.clone(), vec![]),
+            ("left", windows.clone(), vec![]),
+            ("left", windows, vec![]),
+        ];
+        assert_eq!(dispatches(&frames), [3, 1, 0, 0]);
+    }
+
+    #[test]
+    fn frames_follow_surfaces_between_outputs() {
+        // the first window moves to the right output, the last one gets shown on the left
+        let before = vec![(0, Some("left")), (1, Some("right")), (2, None)];
+        let after = vec![(0, Some("right")), (1, Some("right")), (2, Some("left"))];
+        let frames = [
+            ("left", before.clone(), vec![]),
+            ("right", before, vec![]),
+            ("left", after.clone(), vec![]),
+            ("right", after, vec![]),
+        ];
+        assert_eq!(dispatches(&frames), [2, 2, 1, 0]);
+    }
+
+    #[test]
+    fn hidden_workspaces_get_no_frames() {
+        // the windows of the first workspace keep their primary output once it is hidden
+        let first = vec![(0, Some("left")), (1, Some("left"))];
+        let second = vec![(2, Some("left"))];
+        let frames = [
+            ("left", first.clone(), vec![]),
+            ("left", first, vec![]),
+            ("left", second.clone(), vec![]),
+            ("left", second.clone(), vec![]),
+            ("left", second, vec![]),
+        ];
+        assert_eq!(dispatches(&frames), [2, 2, 3, 0]);
+    }
+
+    #[test]
+    fn layer_surfaces_get_frames_of_their_output() {
+        // a panel on the left output, drawn over a fullscreen window on some frames
+        let frames = [
+            ("left", vec![], vec![(3, Some("left"))]),
+            ("left", vec![(0, Some("left"))], vec![(3, None)]),
+            ("left", vec![(0, Some("left"))], vec![(3, Some("left"))]),
+            ("right", vec![(1, Some("right"))], vec![]),
+        ];
+        assert_eq!(dispatches(&frames), [2, 1, 0, 2]);
+    }
 }
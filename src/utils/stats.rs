@@ -0,0 +1,49 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Amount of frames kept to compute the statistics from
+const FRAME_HISTORY: usize = 120;
+
+/// Render timings of a single output.
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    frames: VecDeque<(Instant, Duration)>,
+    pub dropped_frames: u64,
+}
+
+impl FrameStats {
+    /// Record a rendered frame and how long rendering it took.
+    pub fn record_frame(&mut self, render_time: Duration) {
+        if self.frames.len() == FRAME_HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((Instant::now(), render_time));
+    }
+
+    pub fn record_dropped_frame(&mut self) {
+        self.dropped_frames += 1;
+    }
+
+    /// Frames rendered during the last second.
+    pub fn fps(&self) -> usize {
+        self.frames
+            .iter()
+            .rev()
+            .take_while(|(time, _)| time.elapsed() < Duration::from_secs(1))
+            .count()
+    }
+
+    /// Render times of the recent frames, oldest first.
+    pub fn render_times(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.frames.iter().map(|(_, render_time)| *render_time)
+    }
+
+    pub fn average_render_time(&self) -> Duration {
+        if self.frames.is_empty() {
+            return Duration::ZERO;
+        }
+        self.render_times().sum::<Duration>() / self.frames.len() as u32
+    }
+}
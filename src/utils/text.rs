@@ -0,0 +1,91 @@
+//! Rasterizing window titles for the title bars and messages drawn over the outputs.
+
+use std::process::Command;
+
+use fontdue::{Font, FontSettings};
+use once_cell::sync::Lazy;
+use smithay::utils::{Buffer, Size};
+use tracing::warn;
+
+use crate::state::CONFIG;
+
+/// Font of title bars and messages, loaded on first use and `None` if that failed
+static FONT: Lazy<Option<Font>> = Lazy::new(load_font);
+
+/// Size of the glyphs relative to the height of a line
+const GLYPH_SIZE: f32 = 0.7;
+
+fn load_font() -> Option<Font> {
+    let path = match CONFIG.title_bar.as_ref().and_then(|t| t.font.clone()) {
+        Some(path) => path,
+        // the sans serif font fontconfig picks for other applications
+        None => {
+            let output = Command::new("fc-match")
+                .args(["-f", "%{file}", "sans-serif"])
+                .output()
+                .map_err(|err| warn!("Failed to look up the title bar font: {}", err))
+                .ok()?;
+            String::from_utf8(output.stdout).ok()?
+        }
+    };
+    let bytes = std::fs::read(&path)
+        .map_err(|err| warn!("Failed to read font {}: {}", path, err))
+        .ok()?;
+    Font::from_bytes(bytes, FontSettings::default())
+        .map_err(|err| warn!("Failed to load font {}: {}", path, err))
+        .ok()
+}
+
+/// Draw `text` as a single line filling `size` vertically, cut off at its right end.
+/// Returns premultiplied RGBA pixels, or `None` without a font.
+pub fn render_line(text: &str, size: Size<i32, Buffer>, color: [f32; 4]) -> Option<Vec<u8>> {
+    let font = FONT.as_ref()?;
+    let (width, height) = (size.w.max(0) as usize, size.h.max(0) as usize);
+    let px = height as f32 * GLYPH_SIZE;
+    let metrics = font.horizontal_line_metrics(px)?;
+    // the descent is negative, this centers the line between ascent and descent
+    let baseline = (height as f32 + metrics.ascent + metrics.descent) / 2.0;
+
+    let mut pixels = vec![0; width * height * 4];
+    let mut pen = 0.0;
+    for c in text.chars() {
+        if pen >= width as f32 {
+            break;
+        }
+        let (glyph, coverage) = font.rasterize(c, px);
+        // rows go top down, `ymin` is the offset of the bottom row from the baseline
+        let left = (pen + glyph.xmin as f32).round() as i32;
+        let top = (baseline - glyph.ymin as f32 - glyph.height as f32).round() as i32;
+        for (i, coverage) in coverage.into_iter().enumerate() {
+            let x = left + (i % glyph.width) as i32;
+            let y = top + (i / glyph.width) as i32;
+            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                continue;
+            }
+            let offset = (y as usize * width + x as usize) * 4;
+            let alpha = coverage as f32 / 255.0 * color[3];
+            // glyphs may overlap, e.g. in italics
+            if (alpha * 255.0).round() as u8 <= pixels[offset + 3] {
+                continue;
+            }
+            for (channel, value) in color[..3].iter().enumerate() {
+                pixels[offset + channel] = (value * alpha * 255.0).round() as u8;
+            }
+            pixels[offset + 3] = (alpha * 255.0).round() as u8;
+        }
+        pen += glyph.advance_width;
+    }
+    Some(pixels)
+}
+
+/// Width in pixels `render_line` needs to draw all of `text` in a line of `height` pixels,
+/// or `None` without a font.
+pub fn line_width(text: &str, height: i32) -> Option<i32> {
+    let font = FONT.as_ref()?;
+    let px = height as f32 * GLYPH_SIZE;
+    let width: f32 = text
+        .chars()
+        .map(|c| font.metrics(c, px).advance_width)
+        .sum();
+    Some(width.ceil() as i32)
+}
@@ -1,9 +1,8 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::state::CONFIG;
 use smithay::{
-    desktop::layer_map_for_output,
-    utils::{Logical, Physical, Point, Rectangle, Size},
+    desktop::{layer_map_for_output, Window},
+    utils::{Logical, Point, Rectangle, Size},
 };
 use tracing::debug;
 
@@ -11,61 +10,82 @@ use super::{
     binarytree::{BinaryTree, HorizontalOrVertical},
     workspace::{MagmaWindow, Workspace},
 };
+use crate::{
+    config::{Direction, TilingLayout},
+    state::CONFIG,
+};
 
-pub fn bsp_update_layout(workspace: &mut Workspace) {
-    let gaps = CONFIG.gaps;
-    //recalculate the size and location of the windows
+/// Smallest width or height a window can be resized to
+pub const MIN_WINDOW_SIZE: i32 = 50;
+
+/// The area available to the tiled windows of `workspace`, inside the outer gaps.
+pub fn layout_area(workspace: &Workspace) -> Option<Rectangle<i32, Logical>> {
+    // without an output there is nothing to lay out against,
+    // the layout is recalculated once an output is added
+    let output = match workspace.outputs().next() {
+        // outputs without a mode can't show anything yet
+        Some(output) if output.current_mode().is_some() => {
+            // the zone is relative to the output, windows are placed in global coordinates
+            let mut zone = layer_map_for_output(output).non_exclusive_zone();
+            zone.loc += output.current_location();
+            zone
+        }
+        _ => return None,
+    };
 
-    let output = layer_map_for_output(workspace.outputs().next().unwrap()).non_exclusive_zone();
-    let output_full = workspace
-        .outputs()
-        .next()
-        .unwrap()
-        .current_mode()
-        .unwrap()
-        .size;
+    // the outer gaps surround the whole layout, the inner gaps every single window
+    Some(shrink(output, workspace.gaps.0))
+}
 
-    match &mut workspace.layout_tree {
-        BinaryTree::Empty => {}
-        BinaryTree::Window(w) => {
-            w.borrow_mut().rec = Rectangle {
-                loc: Point::from((
-                    gaps.0 + gaps.1 + output.loc.x,
-                    gaps.0 + gaps.1 + output.loc.y,
-                )),
-                size: Size::from((
-                    output.size.w - ((gaps.0 + gaps.1) * 2),
-                    output.size.h - ((gaps.0 + gaps.1) * 2),
-                )),
-            };
+pub fn update_layout(workspace: &mut Workspace) {
+    // borders are drawn within the inner gaps, outside of the window
+    let inner_gap = workspace.gaps.1 + CONFIG.border.width.max(0);
+    //recalculate the size and location of the windows
+    let Some(area) = layout_area(workspace) else {
+        return;
+    };
+    let mut too_large = None;
+    for (window, cell) in workspace
+        .layout
+        .algorithm()
+        .arrange(&workspace.layout_tree, area)
+    {
+        // tiles are clamped to the min/max size of the window, so e.g. dialogs don't stretch
+        let cell = below_title_bar(&window.borrow(), shrink(cell, inner_gap));
+        if !window.borrow_mut().fit_in(cell) && too_large.is_none() {
+            too_large = Some(window);
         }
-        BinaryTree::Split {
-            left,
-            right,
-            split,
-            ratio,
-        } => {
-            if let BinaryTree::Window(w) = left.as_mut() {
-                generate_layout(
-                    right.as_mut(),
-                    w,
-                    Rectangle {
-                        loc: Point::from((gaps.0 + output.loc.x, gaps.0 + output.loc.y)),
-                        size: Size::from((
-                            output.size.w - (gaps.0 * 2),
-                            output.size.h - (gaps.0 * 2),
-                        )),
-                    },
-                    *split,
-                    *ratio,
-                    Size::from((output_full.w - gaps.0, output_full.h - gaps.0)),
-                    gaps,
-                )
-            }
+    }
+
+    // a window that can't shrink to its tile would cover its neighbors, so it floats instead,
+    // which lays out the remaining windows again
+    if let Some(window) = too_large {
+        let window = window.borrow().window.clone();
+        workspace.set_floating(&window, true);
+        if let Some(magmawindow) = workspace.magmawindow(&window) {
+            let mut magmawindow = magmawindow.borrow_mut();
+            magmawindow.center_in(area);
+            let size = magmawindow.rec.size;
+            window.toplevel().with_pending_state(|state| {
+                state.size = Some(size);
+            });
+            window.toplevel().send_configure();
         }
+        return;
+    }
+
+    // in focus mode the only shown window takes the space of the whole layout
+    if let Some(window) = workspace
+        .focused_only()
+        .and_then(|w| workspace.magmawindow(w))
+        .filter(|w| !w.borrow().floating)
+    {
+        let area = below_title_bar(&window.borrow(), shrink(area, inner_gap));
+        window.borrow_mut().fit_in(area);
     }
+
     debug!("{:#?}", workspace.layout_tree);
-    for magmawindow in workspace.magmawindows() {
+    for magmawindow in workspace.magmawindows().filter(|w| !w.floating) {
         let xdg_toplevel = magmawindow.window.toplevel();
         xdg_toplevel.with_pending_state(|state| {
             state.size = Some(magmawindow.rec.size);
@@ -74,59 +94,404 @@ pub fn bsp_update_layout(workspace: &mut Workspace) {
     }
 }
 
-pub fn generate_layout(
-    tree: &mut BinaryTree,
-    lastwin: &Rc<RefCell<MagmaWindow>>,
-    lastgeo: Rectangle<i32, Logical>,
+/// The part of `cell` left for the window below its title bar.
+fn below_title_bar(
+    window: &MagmaWindow,
+    mut cell: Rectangle<i32, Logical>,
+) -> Rectangle<i32, Logical> {
+    let height = window.title_bar_height();
+    cell.loc.y += height;
+    cell.size.h -= height;
+    cell
+}
+
+fn shrink(rec: Rectangle<i32, Logical>, by: i32) -> Rectangle<i32, Logical> {
+    Rectangle {
+        loc: Point::from((rec.loc.x + by, rec.loc.y + by)),
+        size: Size::from((rec.size.w - by * 2, rec.size.h - by * 2)),
+    }
+}
+
+/// A tiling algorithm placing the tiled windows of a workspace.
+pub trait Layout {
+    /// The cell of every window of `tree` inside `area`, before inner gaps are applied.
+    fn arrange(
+        &self,
+        tree: &BinaryTree,
+        area: Rectangle<i32, Logical>,
+    ) -> Vec<(Rc<RefCell<MagmaWindow>>, Rectangle<i32, Logical>)>;
+}
+
+impl TilingLayout {
+    pub fn algorithm(self) -> &'static dyn Layout {
+        match self {
+            TilingLayout::Bsp => &Bsp,
+            TilingLayout::MasterStack => &MasterStack,
+            TilingLayout::Grid => &Grid,
+        }
+    }
+}
+
+/// Every window splits the space of the previous one, alternating between
+/// horizontal and vertical splits.
+pub struct Bsp;
+
+impl Layout for Bsp {
+    fn arrange(
+        &self,
+        tree: &BinaryTree,
+        area: Rectangle<i32, Logical>,
+    ) -> Vec<(Rc<RefCell<MagmaWindow>>, Rectangle<i32, Logical>)> {
+        let mut cells = Vec::new();
+        bsp_cells(tree, area, &mut cells);
+        cells
+    }
+}
+
+fn bsp_cells<T: Clone>(
+    tree: &BinaryTree<T>,
+    area: Rectangle<i32, Logical>,
+    cells: &mut Vec<(T, Rectangle<i32, Logical>)>,
+) {
+    match tree {
+        BinaryTree::Empty => {}
+        BinaryTree::Window(w) => cells.push((w.clone(), area)),
+        BinaryTree::Split {
+            split,
+            ratio,
+            left,
+            right,
+        } => {
+            let (first, second) = split_area(area, *split, *ratio);
+            bsp_cells(left, first, cells);
+            bsp_cells(right, second, cells);
+        }
+    }
+}
+
+fn split_area(
+    area: Rectangle<i32, Logical>,
     split: HorizontalOrVertical,
     ratio: f32,
-    output: Size<i32, Physical>,
-    gaps: (i32, i32),
-) {
-    let size = match split {
+) -> (Rectangle<i32, Logical>, Rectangle<i32, Logical>) {
+    match split {
         HorizontalOrVertical::Horizontal => {
-            Size::from(((lastgeo.size.w as f32 * ratio) as i32, lastgeo.size.h))
+            let w = (area.size.w as f32 * ratio) as i32;
+            (
+                Rectangle::from_loc_and_size(area.loc, (w, area.size.h)),
+                Rectangle::from_loc_and_size(
+                    (area.loc.x + w, area.loc.y),
+                    (area.size.w - w, area.size.h),
+                ),
+            )
         }
         HorizontalOrVertical::Vertical => {
-            Size::from((lastgeo.size.w, (lastgeo.size.h as f32 * ratio) as i32))
+            let h = (area.size.h as f32 * ratio) as i32;
+            (
+                Rectangle::from_loc_and_size(area.loc, (area.size.w, h)),
+                Rectangle::from_loc_and_size(
+                    (area.loc.x, area.loc.y + h),
+                    (area.size.w, area.size.h - h),
+                ),
+            )
         }
-    };
-
-    let loc: Point<i32, Logical> = match split {
-        HorizontalOrVertical::Horizontal => Point::from((lastgeo.loc.x, output.h - size.h)),
-        HorizontalOrVertical::Vertical => Point::from((output.w - size.w, lastgeo.loc.y)),
-    };
-
-    let recgapped = Rectangle {
-        size: Size::from((size.w - (gaps.1 * 2), (size.h - (gaps.1 * 2)))),
-        loc: Point::from((loc.x + gaps.1, loc.y + gaps.1)),
-    };
-
-    lastwin.borrow_mut().rec = recgapped;
-
-    let loc = match split {
-        HorizontalOrVertical::Horizontal => Point::from((output.w - size.w, lastgeo.loc.y)),
-        HorizontalOrVertical::Vertical => Point::from((lastgeo.loc.x, output.h - size.h)),
-    };
+    }
+}
 
-    let rec = Rectangle { size, loc };
-    let recgapped = Rectangle {
-        size: Size::from((size.w - (gaps.1 * 2), (size.h - (gaps.1 * 2)))),
-        loc: Point::from((loc.x + gaps.1, loc.y + gaps.1)),
-    };
+/// Grow `window` by `amount` pixels towards `direction`, shrinking its neighbor, by moving
+/// the nearest split between them. Returns `None` if the window is not part of `tree`,
+/// and `Some(false)` if it has no neighbor in that direction.
+pub fn bsp_resize(
+    tree: &mut BinaryTree,
+    area: Rectangle<i32, Logical>,
+    window: &Window,
+    direction: Direction,
+    amount: i32,
+) -> Option<bool> {
     match tree {
-        BinaryTree::Empty => {}
-        BinaryTree::Window(w) => w.borrow_mut().rec = recgapped,
+        BinaryTree::Empty => None,
+        BinaryTree::Window(w) => (w.borrow().window == *window).then_some(false),
         BinaryTree::Split {
             split,
             ratio,
             left,
             right,
         } => {
-            if let BinaryTree::Window(w) = left.as_mut() {
-                w.borrow_mut().rec = rec;
-                generate_layout(right.as_mut(), w, rec, *split, *ratio, output, gaps)
+            let (first, second) = split_area(area, *split, *ratio);
+            let (resized, in_first) = match bsp_resize(left, first, window, direction, amount) {
+                Some(resized) => (resized, true),
+                None => (bsp_resize(right, second, window, direction, amount)?, false),
+            };
+            if resized {
+                return Some(true);
             }
+
+            // the split has to run across the direction, with the window on the side facing it
+            let grows_first = matches!(direction, Direction::Right | Direction::Down);
+            let across = match split {
+                HorizontalOrVertical::Horizontal => {
+                    matches!(direction, Direction::Left | Direction::Right)
+                }
+                HorizontalOrVertical::Vertical => {
+                    matches!(direction, Direction::Up | Direction::Down)
+                }
+            };
+            if !across || grows_first != in_first {
+                return Some(false);
+            }
+
+            let size = match split {
+                HorizontalOrVertical::Horizontal => area.size.w,
+                HorizontalOrVertical::Vertical => area.size.h,
+            };
+            if size < MIN_WINDOW_SIZE * 2 {
+                return Some(true);
+            }
+            let first_size = (size as f32 * *ratio) as i32;
+            let first_size = if grows_first {
+                first_size + amount
+            } else {
+                first_size - amount
+            };
+            *ratio = first_size.clamp(MIN_WINDOW_SIZE, size - MIN_WINDOW_SIZE) as f32 / size as f32;
+            Some(true)
+        }
+    }
+}
+
+/// The first window takes the left half, the others are stacked on the right,
+/// in further columns once a column holds `CONFIG.column_capacity` windows.
+pub struct MasterStack;
+
+impl Layout for MasterStack {
+    fn arrange(
+        &self,
+        tree: &BinaryTree,
+        area: Rectangle<i32, Logical>,
+    ) -> Vec<(Rc<RefCell<MagmaWindow>>, Rectangle<i32, Logical>)> {
+        let capacity = CONFIG.column_capacity(TilingLayout::MasterStack);
+        master_stack_cells(&tree.windows(), area, capacity)
+    }
+}
+
+fn master_stack_cells<T: Clone>(
+    windows: &[T],
+    area: Rectangle<i32, Logical>,
+    capacity: Option<usize>,
+) -> Vec<(T, Rectangle<i32, Logical>)> {
+    let Some((master, stack)) = windows.split_first() else {
+        return Vec::new();
+    };
+    if stack.is_empty() {
+        return vec![(master.clone(), area)];
+    }
+
+    let master_w = area.size.w / 2;
+    let stack_w = area.size.w - master_w;
+    let mut cells = vec![(
+        master.clone(),
+        Rectangle::from_loc_and_size(area.loc, (master_w, area.size.h)),
+    )];
+    let column_len = capacity.unwrap_or(stack.len());
+    let columns = stack.len().div_ceil(column_len);
+    for (column, column_windows) in stack.chunks(column_len).enumerate() {
+        let (x, w) = split_evenly(stack_w, columns, column);
+        cells.extend(column_windows.iter().enumerate().map(|(i, window)| {
+            let (y, h) = split_evenly(area.size.h, column_windows.len(), i);
+            (
+                window.clone(),
+                Rectangle::from_loc_and_size((area.loc.x + master_w + x, area.loc.y + y), (w, h)),
+            )
+        }));
+    }
+    cells
+}
+
+/// Windows are arranged in rows of equal height, the last row may hold fewer windows.
+/// Columns hold at most `CONFIG.column_capacity` windows, adding columns instead of rows.
+pub struct Grid;
+
+impl Layout for Grid {
+    fn arrange(
+        &self,
+        tree: &BinaryTree,
+        area: Rectangle<i32, Logical>,
+    ) -> Vec<(Rc<RefCell<MagmaWindow>>, Rectangle<i32, Logical>)> {
+        let capacity = CONFIG.column_capacity(TilingLayout::Grid);
+        grid_cells(&tree.windows(), area, capacity)
+    }
+}
+
+fn grid_cells<T: Clone>(
+    windows: &[T],
+    area: Rectangle<i32, Logical>,
+    capacity: Option<usize>,
+) -> Vec<(T, Rectangle<i32, Logical>)> {
+    if windows.is_empty() {
+        return Vec::new();
+    }
+    let columns = (windows.len() as f64).sqrt().ceil() as usize;
+    // a column holds one window of every row, so the capacity limits the rows
+    let columns = capacity.map_or(columns, |capacity| {
+        columns.max(windows.len().div_ceil(capacity))
+    });
+    let rows = windows.len().div_ceil(columns);
+
+    windows
+        .chunks(columns)
+        .enumerate()
+        .flat_map(|(row, row_windows)| {
+            let (y, h) = split_evenly(area.size.h, rows, row);
+            row_windows.iter().enumerate().map(move |(column, window)| {
+                let (x, w) = split_evenly(area.size.w, row_windows.len(), column);
+                (
+                    window.clone(),
+                    Rectangle::from_loc_and_size((area.loc.x + x, area.loc.y + y), (w, h)),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Offset and length of part `index` when dividing `length` into `parts`,
+/// the last part taking the remainder.
+fn split_evenly(length: i32, parts: usize, index: usize) -> (i32, i32) {
+    let part = length / parts as i32;
+    let offset = part * index as i32;
+    if index + 1 == parts {
+        (offset, length - offset)
+    } else {
+        (offset, part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    /// A tree of `count` windows, inserted like `Workspace::add_window` does.
+    fn tree(count: usize) -> BinaryTree<usize> {
+        let mut tree = BinaryTree::new();
+        for window in 0..count {
+            let split = tree.next_split();
+            tree.insert(window, split, 0.5);
         }
+        tree
+    }
+
+    fn area() -> Rectangle<i32, Logical> {
+        rec(0, 0, 1000, 800)
+    }
+
+    fn bsp(count: usize) -> Vec<(usize, Rectangle<i32, Logical>)> {
+        let mut cells = Vec::new();
+        bsp_cells(&tree(count), area(), &mut cells);
+        cells
+    }
+
+    #[test]
+    fn bsp_layout() {
+        assert_eq!(bsp(1), vec![(0, area())]);
+        assert_eq!(
+            bsp(2),
+            vec![(0, rec(0, 0, 500, 800)), (1, rec(500, 0, 500, 800))]
+        );
+        assert_eq!(
+            bsp(3),
+            vec![
+                (0, rec(0, 0, 500, 800)),
+                (1, rec(500, 0, 500, 400)),
+                (2, rec(500, 400, 500, 400)),
+            ]
+        );
+    }
+
+    #[test]
+    fn master_stack_layout() {
+        let cells = |count| master_stack_cells(&tree(count).windows(), area(), None);
+        assert_eq!(cells(1), vec![(0, area())]);
+        assert_eq!(
+            cells(2),
+            vec![(0, rec(0, 0, 500, 800)), (1, rec(500, 0, 500, 800))]
+        );
+        assert_eq!(
+            cells(3),
+            vec![
+                (0, rec(0, 0, 500, 800)),
+                (1, rec(500, 0, 500, 400)),
+                (2, rec(500, 400, 500, 400)),
+            ]
+        );
+    }
+
+    #[test]
+    fn grid_layout() {
+        let cells = |count| grid_cells(&tree(count).windows(), area(), None);
+        assert_eq!(cells(1), vec![(0, area())]);
+        assert_eq!(
+            cells(2),
+            vec![(0, rec(0, 0, 500, 800)), (1, rec(500, 0, 500, 800))]
+        );
+        assert_eq!(
+            cells(3),
+            vec![
+                (0, rec(0, 0, 500, 400)),
+                (1, rec(500, 0, 500, 400)),
+                (2, rec(0, 400, 1000, 400)),
+            ]
+        );
+    }
+
+    #[test]
+    fn master_stack_column_capacity() {
+        let cells = |count| master_stack_cells(&tree(count).windows(), area(), Some(2));
+        assert_eq!(
+            cells(3),
+            vec![
+                (0, rec(0, 0, 500, 800)),
+                (1, rec(500, 0, 500, 400)),
+                (2, rec(500, 400, 500, 400)),
+            ]
+        );
+        assert_eq!(
+            cells(4),
+            vec![
+                (0, rec(0, 0, 500, 800)),
+                (1, rec(500, 0, 250, 400)),
+                (2, rec(500, 400, 250, 400)),
+                (3, rec(750, 0, 250, 800)),
+            ]
+        );
+    }
+
+    #[test]
+    fn grid_column_capacity() {
+        let cells = |count, capacity| grid_cells(&tree(count).windows(), area(), Some(capacity));
+        // below the capacity the grid is unchanged
+        assert_eq!(cells(3, 2), grid_cells(&tree(3).windows(), area(), None));
+        assert_eq!(
+            cells(3, 1),
+            vec![
+                (0, rec(0, 0, 333, 800)),
+                (1, rec(333, 0, 333, 800)),
+                (2, rec(666, 0, 334, 800)),
+            ]
+        );
+        assert_eq!(
+            cells(7, 2),
+            vec![
+                (0, rec(0, 0, 250, 400)),
+                (1, rec(250, 0, 250, 400)),
+                (2, rec(500, 0, 250, 400)),
+                (3, rec(750, 0, 250, 400)),
+                (4, rec(0, 400, 333, 400)),
+                (5, rec(333, 400, 333, 400)),
+                (6, rec(666, 400, 334, 400)),
+            ]
+        );
     }
 }
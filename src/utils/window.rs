@@ -0,0 +1,38 @@
+use std::sync::Mutex;
+
+use smithay::{
+    desktop::Window,
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceRoleAttributes},
+};
+
+/// Access to the xdg-toplevel attributes of a window.
+pub trait WindowExt {
+    fn app_id(&self) -> Option<String>;
+    fn title(&self) -> Option<String>;
+}
+
+impl WindowExt for Window {
+    fn app_id(&self) -> Option<String> {
+        with_states(self.toplevel().wl_surface(), |states| {
+            states
+                .data_map
+                .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()?
+                .lock()
+                .unwrap()
+                .app_id
+                .clone()
+        })
+    }
+
+    fn title(&self) -> Option<String> {
+        with_states(self.toplevel().wl_surface(), |states| {
+            states
+                .data_map
+                .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()?
+                .lock()
+                .unwrap()
+                .title
+                .clone()
+        })
+    }
+}
@@ -1,26 +1,369 @@
 use std::{
     cell::{Ref, RefCell},
+    collections::BTreeMap,
+    fmt,
     rc::Rc,
+    time::Instant,
 };
 
 use smithay::{
-    backend::renderer::{
-        element::{surface::WaylandSurfaceRenderElement, AsRenderElements},
-        ImportAll, Renderer, Texture,
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                solid::SolidColorRenderElement,
+                surface::WaylandSurfaceRenderElement,
+                AsRenderElements, Id,
+            },
+            utils::CommitCounter,
+            ImportAll, ImportMem, Renderer, Texture,
+        },
     },
     desktop::{space::SpaceElement, Window},
     output::Output,
-    utils::{Logical, Point, Rectangle, Scale, Transform},
+    reexports::wayland_protocols::xdg::{
+        decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as DecorationMode,
+        shell::server::xdg_toplevel::State as ToplevelState,
+    },
+    utils::{Buffer, Logical, Point, Rectangle, Scale, Size, Transform},
+    wayland::{compositor::with_states, shell::xdg::SurfaceCachedState},
+};
+
+use super::{
+    binarytree::BinaryTree,
+    text::render_line,
+    tiling::{bsp_resize, layout_area, update_layout, MIN_WINDOW_SIZE},
+    window::WindowExt,
+};
+use crate::{
+    config::{Config, Direction, TilingLayout},
+    state::CONFIG,
 };
 
-use super::{binarytree::BinaryTree, tiling::bsp_update_layout};
+/// Upper limit for each of the inner and outer gaps
+const MAX_GAPS: i32 = 200;
+
+/// The top, bottom, left and right side of a border of `width` around `rec`.
+pub fn border_sides(rec: Rectangle<i32, Logical>, width: i32) -> [Rectangle<i32, Logical>; 4] {
+    let (x, y, w, h) = (rec.loc.x, rec.loc.y, rec.size.w, rec.size.h);
+    [
+        Rectangle::from_loc_and_size((x - width, y - width), (w + 2 * width, width)),
+        Rectangle::from_loc_and_size((x - width, y + h), (w + 2 * width, width)),
+        Rectangle::from_loc_and_size((x - width, y), (width, h)),
+        Rectangle::from_loc_and_size((x + w, y), (width, h)),
+    ]
+}
+
+/// Clamp a size to min/max size hints, a hint of 0 meaning no limit.
+fn clamp_to_hints(
+    size: Size<i32, Logical>,
+    min_size: Size<i32, Logical>,
+    max_size: Size<i32, Logical>,
+) -> Size<i32, Logical> {
+    let mut w = size.w.max(min_size.w).max(1);
+    let mut h = size.h.max(min_size.h).max(1);
+    if max_size.w > 0 {
+        w = w.min(max_size.w);
+    }
+    if max_size.h > 0 {
+        h = h.min(max_size.h);
+    }
+    Size::from((w, h))
+}
+
+/// The largest part of `area` the size hints allow, centered in it,
+/// or `None` if the min size doesn't fit into `area`.
+fn fit_to_hints(
+    area: Rectangle<i32, Logical>,
+    min_size: Size<i32, Logical>,
+    max_size: Size<i32, Logical>,
+) -> Option<Rectangle<i32, Logical>> {
+    if min_size.w > area.size.w || min_size.h > area.size.h {
+        return None;
+    }
+    let size = clamp_to_hints(area.size, min_size, max_size);
+    let loc = area.loc + Point::from(((area.size.w - size.w) / 2, (area.size.h - size.h) / 2));
+    Some(Rectangle::from_loc_and_size(loc, size))
+}
+
+/// The nearest of `candidates` next to `rec` in `direction`, measured between the centers.
+/// On a tie the first candidate wins.
+fn nearest_in_direction<T>(
+    rec: Rectangle<i32, Logical>,
+    candidates: impl Iterator<Item = (Rectangle<i32, Logical>, T)>,
+    direction: Direction,
+) -> Option<T> {
+    let center =
+        |rec: Rectangle<i32, Logical>| (rec.loc.x + rec.size.w / 2, rec.loc.y + rec.size.h / 2);
+    let (x, y) = center(rec);
+    candidates
+        .filter_map(|(rec, item)| {
+            let (cx, cy) = center(rec);
+            // distance along the direction, and how far the candidate is off to the side
+            let (distance, offset) = match direction {
+                Direction::Left => (x - cx, (y - cy).abs()),
+                Direction::Right => (cx - x, (y - cy).abs()),
+                Direction::Up => (y - cy, (x - cx).abs()),
+                Direction::Down => (cy - y, (x - cx).abs()),
+            };
+            (distance > 0).then_some((distance + offset, item))
+        })
+        .min_by_key(|(score, _)| *score)
+        .map(|(_, item)| item)
+}
+
+/// The title drawn in a title bar, drawn again once the title, its size or its color change.
+#[derive(Clone)]
+struct TitleText {
+    title: String,
+    size: Size<i32, Buffer>,
+    color: [f32; 4],
+    buffer: MemoryRenderBuffer,
+}
+
+impl TitleText {
+    fn matches(&self, title: &str, size: Size<i32, Buffer>, color: [f32; 4]) -> bool {
+        self.title == title && self.size == size && self.color == color
+    }
+}
+
+impl PartialEq for TitleText {
+    // the buffer is drawn from the other fields
+    fn eq(&self, other: &Self) -> bool {
+        other.matches(&self.title, self.size, self.color)
+    }
+}
+
+impl fmt::Debug for TitleText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TitleText")
+            .field("title", &self.title)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct MagmaWindow {
     pub window: Window,
     pub rec: Rectangle<i32, Logical>,
+    pub floating: bool,
+    /// Geometry and floating state to restore, set while the window is maximized
+    pub unmaximized: Option<(Rectangle<i32, Logical>, bool)>,
+    /// Geometry and floating state to restore, set while the window is fullscreen
+    pub unfullscreened: Option<(Rectangle<i32, Logical>, bool)>,
+    /// Stacked above all other windows
+    pub always_on_top: bool,
+    /// When the window requested attention, cleared once it is focused
+    pub urgent: Option<Instant>,
+    /// Geometry of the parent a dialog is centered over, once its first buffer tells its size
+    pub center_over: Option<Rectangle<i32, Logical>>,
+    /// Id of the overlay dimming the window while it is unfocused
+    dim_id: Id,
+    /// Ids of the border sides drawn while the window is unfocused and while it is focused,
+    /// separate so a change of the focus damages the border
+    border_ids: ([Id; 4], [Id; 4]),
+    /// Ids of the title bar and its close and maximize buttons, while the window is
+    /// unfocused and while it is focused
+    title_bar_ids: ([Id; 3], [Id; 3]),
+    /// Rasterized title, cached between frames
+    title_text: RefCell<Option<TitleText>>,
 }
 impl MagmaWindow {
+    pub fn new(window: Window) -> Self {
+        let rec = window.geometry();
+        MagmaWindow {
+            window,
+            rec,
+            floating: false,
+            unmaximized: None,
+            unfullscreened: None,
+            always_on_top: false,
+            urgent: None,
+            center_over: None,
+            dim_id: Id::new(),
+            border_ids: (
+                std::array::from_fn(|_| Id::new()),
+                std::array::from_fn(|_| Id::new()),
+            ),
+            title_bar_ids: (
+                std::array::from_fn(|_| Id::new()),
+                std::array::from_fn(|_| Id::new()),
+            ),
+            title_text: RefCell::new(None),
+        }
+    }
+
+    /// The min and max size requested by the client, 0 meaning unbounded.
+    pub fn size_hints(&self) -> (Size<i32, Logical>, Size<i32, Logical>) {
+        with_states(self.window.toplevel().wl_surface(), |states| {
+            let data = states.cached_state.current::<SurfaceCachedState>();
+            (data.min_size, data.max_size)
+        })
+    }
+
+    /// Clamp a size to the min/max size hints of the toplevel.
+    /// A hint of 0 means the client did not set a limit.
+    pub fn clamp_size(&self, size: Size<i32, Logical>) -> Size<i32, Logical> {
+        let (min_size, max_size) = self.size_hints();
+        clamp_to_hints(size, min_size, max_size)
+    }
+
+    /// Take as much of `area` as the size hints allow, centered in it. Returns `false` if the
+    /// window can't shrink to `area`, it then overflows it evenly on both sides.
+    pub fn fit_in(&mut self, area: Rectangle<i32, Logical>) -> bool {
+        let (min_size, max_size) = self.size_hints();
+        match fit_to_hints(area, min_size, max_size) {
+            Some(rec) => {
+                self.rec = rec;
+                true
+            }
+            None => {
+                self.rec.size = clamp_to_hints(area.size, min_size, max_size);
+                self.center_in(area);
+                false
+            }
+        }
+    }
+
+    /// Move the window to the center of `area`, keeping its size.
+    pub fn center_in(&mut self, area: Rectangle<i32, Logical>) {
+        self.rec.loc = area.loc
+            + Point::from((
+                (area.size.w - self.rec.size.w) / 2,
+                (area.size.h - self.rec.size.h) / 2,
+            ));
+    }
+
+    /// Resize a floating window around its center and send the new size to the client.
+    pub fn resize_centered(&mut self, delta: i32) {
+        let size = self.clamp_size(Size::from((
+            self.rec.size.w + delta,
+            self.rec.size.h + delta,
+        )));
+        let center = self.rec.loc + Point::from((self.rec.size.w / 2, self.rec.size.h / 2));
+        self.rec =
+            Rectangle::from_loc_and_size(center - Point::from((size.w / 2, size.h / 2)), size);
+        let xdg_toplevel = self.window.toplevel();
+        xdg_toplevel.with_pending_state(|state| {
+            state.size = Some(size);
+        });
+        xdg_toplevel.send_configure();
+    }
+
+    /// Grow a floating window by `amount` pixels towards `direction` and send the new size
+    /// to the client, shrinking it for negative amounts.
+    pub fn resize_towards(&mut self, direction: Direction, amount: i32) {
+        let (dw, dh) = match direction {
+            Direction::Left | Direction::Right => (amount, 0),
+            Direction::Up | Direction::Down => (0, amount),
+        };
+        let size = self.clamp_size(Size::from((
+            (self.rec.size.w + dw).max(MIN_WINDOW_SIZE.min(self.rec.size.w)),
+            (self.rec.size.h + dh).max(MIN_WINDOW_SIZE.min(self.rec.size.h)),
+        )));
+        // growing to the left or up moves the window so the opposite edge stays in place
+        match direction {
+            Direction::Left => self.rec.loc.x -= size.w - self.rec.size.w,
+            Direction::Up => self.rec.loc.y -= size.h - self.rec.size.h,
+            Direction::Right | Direction::Down => {}
+        }
+        self.rec.size = size;
+        let xdg_toplevel = self.window.toplevel();
+        xdg_toplevel.with_pending_state(|state| {
+            state.size = Some(size);
+        });
+        xdg_toplevel.send_configure();
+    }
+
+    /// Height of the title bar above the window, 0 unless it uses server side decorations.
+    pub fn title_bar_height(&self) -> i32 {
+        let Some(title_bar) = &CONFIG.title_bar else {
+            return 0;
+        };
+        // the configured state is used, so the layout doesn't wait for the client
+        let (decoration_mode, fullscreen) = self.window.toplevel().with_pending_state(|state| {
+            (
+                state.decoration_mode,
+                state.states.contains(ToplevelState::Fullscreen),
+            )
+        });
+        if decoration_mode == Some(DecorationMode::ServerSide) && !fullscreen {
+            title_bar.height.max(0)
+        } else {
+            0
+        }
+    }
+
+    /// The title bar above the window, if it has one.
+    pub fn title_bar(&self) -> Option<Rectangle<i32, Logical>> {
+        let height = self.title_bar_height();
+        (height > 0).then(|| {
+            Rectangle::from_loc_and_size(
+                (self.rec.loc.x, self.rec.loc.y - height),
+                (self.rec.size.w, height),
+            )
+        })
+    }
+
+    /// The close and the maximize button at the right end of the title bar.
+    pub fn title_bar_buttons(&self) -> Option<(Rectangle<i32, Logical>, Rectangle<i32, Logical>)> {
+        let bar = self.title_bar()?;
+        let padding = (bar.size.h / 5).max(1);
+        let size = bar.size.h - 2 * padding;
+        let close = Rectangle::from_loc_and_size(
+            (bar.loc.x + bar.size.w - padding - size, bar.loc.y + padding),
+            (size, size),
+        );
+        let maximize =
+            Rectangle::from_loc_and_size((close.loc.x - padding - size, close.loc.y), (size, size));
+        Some((close, maximize))
+    }
+
+    /// The part of the title bar left of the buttons the title is drawn in.
+    pub fn title_area(&self) -> Option<Rectangle<i32, Logical>> {
+        let bar = self.title_bar()?;
+        let (_, maximize) = self.title_bar_buttons()?;
+        let padding = maximize.loc.y - bar.loc.y;
+        let width = maximize.loc.x - bar.loc.x - 2 * padding;
+        (width > 0).then(|| {
+            Rectangle::from_loc_and_size((bar.loc.x + padding, bar.loc.y), (width, bar.size.h))
+        })
+    }
+
+    /// The title rasterized to fill `area` at `scale`, reused while nothing changed.
+    fn title_text(
+        &self,
+        area: Rectangle<i32, Logical>,
+        scale: f64,
+        color: [f32; 4],
+    ) -> Option<MemoryRenderBuffer> {
+        let title = self.window.title().unwrap_or_default();
+        let size = area.size.to_physical_precise_round(scale);
+        let size = Size::<i32, Buffer>::from((size.w, size.h));
+        let mut cache = self.title_text.borrow_mut();
+        if !cache
+            .as_ref()
+            .is_some_and(|text| text.matches(&title, size, color))
+        {
+            *cache = render_line(&title, size, color).map(|pixels| TitleText {
+                buffer: MemoryRenderBuffer::from_memory(
+                    pixels.as_slice(),
+                    Fourcc::Abgr8888,
+                    size,
+                    1,
+                    Transform::Normal,
+                    None,
+                ),
+                title,
+                size,
+                color,
+            });
+        }
+        cache.as_ref().map(|text| text.buffer.clone())
+    }
+
     fn bbox(&self) -> Rectangle<i32, Logical> {
         let mut bbox = self.window.bbox();
         bbox.loc += self.rec.loc - self.window.geometry().loc;
@@ -32,17 +375,61 @@ impl MagmaWindow {
     }
 }
 pub struct Workspace {
+    /// Optional name shown next to the id, unique among all workspaces
+    pub name: Option<String>,
     windows: Vec<Rc<RefCell<MagmaWindow>>>,
     outputs: Vec<Output>,
     pub layout_tree: BinaryTree,
+    pub layout: TilingLayout,
+    /// Outer and inner gaps of the tiling layout
+    pub gaps: (i32, i32),
+    /// Gaps to restore once gaps are toggled back on
+    hidden_gaps: Option<(i32, i32)>,
+    /// The only window shown while focus mode is active
+    focused_only: Option<Window>,
 }
 
 impl Workspace {
     pub fn new() -> Self {
         Workspace {
+            name: None,
             windows: Vec::new(),
             outputs: Vec::new(),
             layout_tree: BinaryTree::new(),
+            layout: CONFIG.layout,
+            gaps: CONFIG.gaps,
+            hidden_gaps: None,
+            focused_only: None,
+        }
+    }
+
+    /// Create a workspace using the defaults from `CONFIG.workspace_defaults` for `id`.
+    pub fn with_id(id: u8) -> Self {
+        let mut workspace = Self::new();
+        workspace.apply_config(id);
+        workspace
+    }
+
+    /// Take over the settings of `CONFIG` for the workspace `id`, e.g. after a reload.
+    pub fn apply_config(&mut self, id: u8) {
+        self.hidden_gaps = None;
+        self.gaps = CONFIG.workspace_gaps(id);
+        self.layout = CONFIG.workspace_layout(id);
+    }
+
+    /// Apply the gaps and layout of the reloaded config that differ from `old`,
+    /// so changes made at runtime, e.g. by CycleLayout or ToggleGaps, are kept otherwise.
+    pub fn update_config(&mut self, id: u8, old: &Config) {
+        let gaps = CONFIG.workspace_gaps(id);
+        if gaps != old.workspace_gaps(id) {
+            match &mut self.hidden_gaps {
+                Some(hidden_gaps) => *hidden_gaps = gaps,
+                None => self.gaps = gaps,
+            }
+        }
+        let layout = CONFIG.workspace_layout(id);
+        if layout != old.workspace_layout(id) {
+            self.layout = layout;
         }
     }
 
@@ -61,9 +448,291 @@ impl Workspace {
         self.windows
             .retain(|w| w.borrow().window != window.borrow().window);
         self.windows.push(window.clone());
+        if window.borrow().always_on_top {
+            let always_on_top = window.borrow().window.clone();
+            self.raise_window(&always_on_top);
+        }
+        // let the client know which outputs it is shown on, and so their scale
+        for output in &self.outputs {
+            let magmawindow = window.borrow();
+            magmawindow
+                .window
+                .output_enter(output, magmawindow.window.bbox());
+        }
+        if !window.borrow().floating {
+            self.layout_tree
+                .insert(window, self.layout_tree.next_split(), 0.5);
+        }
+        update_layout(self);
+    }
+
+    /// Move a window to the top of the stacking order, below any always on top windows.
+    /// Windows are stacked from the front of the list to the back.
+    pub fn raise_window(&mut self, window: &Window) {
+        if let Some(index) = self
+            .windows
+            .iter()
+            .position(|w| &w.borrow().window == window)
+        {
+            let magmawindow = self.windows.remove(index);
+            let top = if magmawindow.borrow().always_on_top {
+                0
+            } else {
+                self.windows
+                    .iter()
+                    .position(|w| !w.borrow().always_on_top)
+                    .unwrap_or(self.windows.len())
+            };
+            self.windows.insert(top, magmawindow);
+
+            // dialogs stay above the window they belong to
+            let dialogs: Vec<Window> = self
+                .windows()
+                .filter(|w| w.toplevel().parent().as_ref() == Some(window.toplevel().wl_surface()))
+                .map(|w| w.clone())
+                .collect();
+            for dialog in dialogs {
+                self.raise_window(&dialog);
+            }
+        }
+    }
+
+    /// Move a floating window below all other floating windows,
+    /// but still above the tiled ones.
+    /// Always on top windows only move below the other always on top windows.
+    pub fn lower_window(&mut self, window: &Window) {
+        if let Some(index) = self
+            .windows
+            .iter()
+            .position(|w| &w.borrow().window == window)
+        {
+            let magmawindow = self.windows.remove(index);
+            let always_on_top = magmawindow.borrow().always_on_top;
+            let bottom = self
+                .windows
+                .iter()
+                .position(|w| {
+                    if always_on_top {
+                        !w.borrow().always_on_top
+                    } else {
+                        !w.borrow().floating
+                    }
+                })
+                .unwrap_or(self.windows.len());
+            self.windows.insert(bottom, magmawindow);
+        }
+    }
+
+    /// The nearest visible window next to `window` in `direction`, only looking at tiled
+    /// windows from a tiled one so it can be swapped with.
+    pub fn window_in_direction(&self, window: &Window, direction: Direction) -> Option<Window> {
+        let (rec, floating) = {
+            let magmawindow = self.magmawindow(window)?;
+            let magmawindow = magmawindow.borrow();
+            (magmawindow.rec, magmawindow.floating)
+        };
+        nearest_in_direction(
+            rec,
+            self.magmawindows()
+                .filter(|w| &w.window != window && self.is_visible(&w.window))
+                .filter(|w| floating || !w.floating)
+                .map(|w| (w.rec, w.window.clone())),
+            direction,
+        )
+    }
+
+    /// Make a window cover `area` above all other windows,
+    /// or restore its previous geometry for `None`.
+    pub fn set_fullscreen(&mut self, window: &Window, area: Option<Rectangle<i32, Logical>>) {
+        let Some(magmawindow) = self.magmawindow(window) else {
+            return;
+        };
+        let toplevel = window.toplevel();
+        match area {
+            Some(area) => {
+                if magmawindow.borrow().unfullscreened.is_some() {
+                    return;
+                }
+                let floating = magmawindow.borrow().floating;
+                self.set_floating(window, true);
+                self.raise_window(window);
+                let mut magmawindow = magmawindow.borrow_mut();
+                magmawindow.unfullscreened = Some((magmawindow.rec, floating));
+                magmawindow.rec = area;
+                toplevel.with_pending_state(|state| {
+                    state.states.set(ToplevelState::Fullscreen);
+                    state.size = Some(area.size);
+                });
+            }
+            None => {
+                let Some((rec, floating)) = magmawindow.borrow_mut().unfullscreened.take() else {
+                    return;
+                };
+                toplevel.with_pending_state(|state| {
+                    state.states.unset(ToplevelState::Fullscreen);
+                });
+                if floating {
+                    magmawindow.borrow_mut().rec = rec;
+                    toplevel.with_pending_state(|state| {
+                        state.size = Some(rec.size);
+                    });
+                } else {
+                    self.set_floating(window, false);
+                }
+            }
+        }
+        toplevel.send_configure();
+    }
+
+    /// The fullscreen window covering the whole output, if no other window is stacked above it.
+    pub fn fullscreen_window(&self) -> Option<Window> {
+        let geometry = self.output_geometry(self.outputs.first()?)?;
+        let top = self
+            .windows
+            .iter()
+            .map(|w| w.borrow())
+            .find(|w| self.is_visible(&w.window))?;
+        let fullscreen = top
+            .window
+            .toplevel()
+            .current_state()
+            .states
+            .contains(ToplevelState::Fullscreen);
+        (fullscreen && top.rec.contains_rect(geometry)).then(|| top.window.clone())
+    }
+
+    /// Keep a window above all others, floating it if it is tiled.
+    pub fn set_always_on_top(&mut self, window: &Window, always_on_top: bool) {
+        if let Some(magmawindow) = self.magmawindow(window) {
+            magmawindow.borrow_mut().always_on_top = always_on_top;
+            if always_on_top {
+                self.set_floating(window, true);
+            }
+            self.raise_window(window);
+        }
+    }
+
+    /// Zero the gaps, or restore the previous ones if they are already hidden.
+    pub fn toggle_gaps(&mut self) {
+        match self.hidden_gaps.take() {
+            Some(gaps) => self.gaps = gaps,
+            None => self.hidden_gaps = Some(std::mem::replace(&mut self.gaps, (0, 0))),
+        }
+        update_layout(self);
+    }
+
+    /// Grow or shrink the inner and outer gaps by `delta`. Gaps stay positive and small
+    /// enough to leave room for the windows on the output.
+    pub fn change_gaps(&mut self, delta: i32) {
+        let gaps = self.hidden_gaps.take().unwrap_or(self.gaps);
+        let max = self
+            .outputs
+            .first()
+            .and_then(|o| self.output_geometry(o))
+            .map(|geo| geo.size.w.min(geo.size.h) / 8)
+            .unwrap_or(MAX_GAPS)
+            .min(MAX_GAPS);
+        self.gaps = (
+            (gaps.0 + delta).clamp(0, max),
+            (gaps.1 + delta).clamp(0, max),
+        );
+        update_layout(self);
+    }
+
+    /// Grow `window` by `amount` pixels towards `direction`. Tiled windows take the space
+    /// from their neighbor, which is only supported by the BSP layout.
+    pub fn resize_window(&mut self, window: &Window, direction: Direction, amount: i32) {
+        let Some(magmawindow) = self.magmawindow(window) else {
+            return;
+        };
+        if magmawindow.borrow().floating {
+            magmawindow.borrow_mut().resize_towards(direction, amount);
+            return;
+        }
+        if self.layout != TilingLayout::Bsp {
+            return;
+        }
+        if let Some(area) = layout_area(self) {
+            if bsp_resize(&mut self.layout_tree, area, window, direction, amount) == Some(true) {
+                update_layout(self);
+            }
+        }
+    }
+
+    /// Switch to the next tiling layout.
+    pub fn cycle_layout(&mut self) {
+        self.layout = self.layout.next();
+        update_layout(self);
+    }
+
+    pub fn focused_only(&self) -> Option<&Window> {
+        self.focused_only.as_ref()
+    }
+
+    /// Show only `window`, covering the whole tiling area, or all windows again for `None`.
+    pub fn set_focused_only(&mut self, window: Option<Window>) {
+        if self.focused_only != window {
+            self.focused_only = window;
+            update_layout(self);
+        }
+    }
+
+    fn is_visible(&self, window: &Window) -> bool {
+        self.focused_only.as_ref().map_or(true, |w| w == window)
+    }
+
+    /// Take a window out of the tiling layout or put it back in.
+    pub fn set_floating(&mut self, window: &Window, floating: bool) {
+        if let Some(magmawindow) = self.magmawindow(window) {
+            if magmawindow.borrow().floating == floating {
+                return;
+            }
+            magmawindow.borrow_mut().floating = floating;
+            // tell the client whether it is tiled, so it can drop its shadows and rounded corners
+            window.toplevel().with_pending_state(|state| {
+                for tiled in [
+                    ToplevelState::TiledLeft,
+                    ToplevelState::TiledRight,
+                    ToplevelState::TiledTop,
+                    ToplevelState::TiledBottom,
+                ] {
+                    if floating {
+                        state.states.unset(tiled);
+                    } else {
+                        state.states.set(tiled);
+                    }
+                }
+            });
+            if floating {
+                self.layout_tree.remove(window);
+                self.raise_window(window);
+            } else {
+                // tiled windows stay below all floating windows
+                self.windows.retain(|w| !Rc::ptr_eq(w, &magmawindow));
+                self.windows.push(magmawindow.clone());
+                self.layout_tree
+                    .insert(magmawindow, self.layout_tree.next_split(), 0.5);
+            }
+            update_layout(self);
+        }
+    }
+
+    /// Exchange the tiling positions of two windows and re-tile.
+    pub fn swap_windows(&mut self, a: &Window, b: &Window) {
+        if let (Some(a), Some(b)) = (self.magmawindow(a), self.magmawindow(b)) {
+            if a.borrow().floating || b.borrow().floating {
+                return;
+            }
+            self.layout_tree.swap(&a, &b);
+            update_layout(self);
+        }
+    }
+
+    /// The tiled window in the master position.
+    pub fn master(&self) -> Option<Window> {
         self.layout_tree
-            .insert(window, self.layout_tree.next_split(), 0.5);
-        bsp_update_layout(self);
+            .first_window()
+            .map(|w| w.borrow().window.clone())
     }
 
     pub fn remove_window(&mut self, window: &Window) -> Option<Rc<RefCell<MagmaWindow>>> {
@@ -76,25 +745,134 @@ impl Workspace {
                 true
             }
         });
+        if removed.is_some() {
+            for output in &self.outputs {
+                window.output_leave(output);
+            }
+        }
         self.layout_tree.remove(window);
-        bsp_update_layout(self);
+        if self.focused_only.as_ref() == Some(window) {
+            self.focused_only = None;
+        }
+        update_layout(self);
         removed
     }
 
-    pub fn render_elements<R: Renderer + ImportAll, C: From<WaylandSurfaceRenderElement<R>>>(
-        &self,
-        renderer: &mut R,
-    ) -> Vec<C>
+    /// Render elements of all visible windows, dimming all but `focused`
+    /// according to `CONFIG.inactive_dim`.
+    pub fn render_elements<R, C>(&self, renderer: &mut R, focused: Option<&Window>) -> Vec<C>
     where
+        R: Renderer + ImportAll + ImportMem,
         <R as Renderer>::TextureId: Texture + 'static,
+        C: From<WaylandSurfaceRenderElement<R>>
+            + From<SolidColorRenderElement>
+            + From<MemoryRenderBufferRenderElement<R>>,
     {
+        // windows are placed in global coordinates, elements are relative to the output
+        let output_loc = self
+            .outputs
+            .first()
+            .map(|o| o.current_location())
+            .unwrap_or_default();
+        let scale = self
+            .outputs
+            .first()
+            .map(|o| o.current_scale().fractional_scale())
+            .unwrap_or(1.0);
         let mut render_elements: Vec<C> = Vec::new();
-        for element in &self.windows {
-            render_elements.append(&mut element.borrow().window.render_elements(
+        // a fullscreen window hides all others, leaving it alone to be scanned out directly
+        let fullscreen_window = self.fullscreen_window();
+        for element in self.windows.iter().filter(|w| {
+            let window = &w.borrow().window;
+            match &fullscreen_window {
+                Some(fullscreen) => fullscreen == window,
+                None => self.is_visible(window),
+            }
+        }) {
+            let element = element.borrow();
+            let fullscreen = element
+                .window
+                .toplevel()
+                .current_state()
+                .states
+                .contains(ToplevelState::Fullscreen);
+            if CONFIG.inactive_dim > 0.0 && Some(&element.window) != focused && !fullscreen {
+                // the overlay has to come first to be drawn on top of the window
+                render_elements.push(C::from(SolidColorRenderElement::new(
+                    element.dim_id.clone(),
+                    Rectangle::from_loc_and_size(element.rec.loc - output_loc, element.rec.size)
+                        .to_physical_precise_round(scale),
+                    CommitCounter::default(),
+                    [0.0, 0.0, 0.0, CONFIG.inactive_dim],
+                )));
+            }
+            render_elements.append(&mut element.window.render_elements(
                 renderer,
-                element.borrow().render_location().to_physical(1),
-                Scale::from(1.0),
+                (element.render_location() - output_loc).to_physical_precise_round(scale),
+                Scale::from(scale),
             ));
+            let title_bar = element.title_bar();
+            if let (Some(bar), Some((close, maximize)), Some(config)) =
+                (title_bar, element.title_bar_buttons(), &CONFIG.title_bar)
+            {
+                let (ids, color) = if Some(&element.window) == focused {
+                    (&element.title_bar_ids.1, config.active)
+                } else {
+                    (&element.title_bar_ids.0, config.inactive)
+                };
+                if let Some(area) = element.title_area() {
+                    let text = element
+                        .title_text(area, scale, config.text)
+                        .and_then(|text| {
+                            MemoryRenderBufferRenderElement::from_buffer(
+                                renderer,
+                                (area.loc - output_loc)
+                                    .to_physical_precise_round(scale)
+                                    .to_f64(),
+                                &text,
+                                None,
+                                None,
+                                Some(area.size),
+                            )
+                            .ok()
+                        });
+                    render_elements.extend(text.map(C::from));
+                }
+                // the title and the buttons come first to be drawn on top of the bar
+                let parts = [
+                    (maximize, config.maximize_button),
+                    (close, config.close_button),
+                    (bar, color),
+                ];
+                for (id, (rec, color)) in ids.iter().zip(parts) {
+                    render_elements.push(C::from(SolidColorRenderElement::new(
+                        id.clone(),
+                        Rectangle::from_loc_and_size(rec.loc - output_loc, rec.size)
+                            .to_physical_precise_round(scale),
+                        CommitCounter::default(),
+                        color,
+                    )));
+                }
+            }
+            let border = &CONFIG.border;
+            if border.width > 0 && !element.floating && !fullscreen {
+                let (ids, color) = if Some(&element.window) == focused {
+                    (&element.border_ids.1, border.active)
+                } else {
+                    (&element.border_ids.0, border.inactive)
+                };
+                // the border surrounds the title bar as well
+                let rec = title_bar.map_or(element.rec, |bar| bar.merge(element.rec));
+                let rec = Rectangle::from_loc_and_size(rec.loc - output_loc, rec.size);
+                for (id, side) in ids.iter().zip(border_sides(rec, border.width)) {
+                    render_elements.push(C::from(SolidColorRenderElement::new(
+                        id.clone(),
+                        side.to_physical_precise_round(scale),
+                        CommitCounter::default(),
+                        color,
+                    )));
+                }
+            }
         }
         render_elements
     }
@@ -104,10 +882,41 @@ impl Workspace {
     }
 
     pub fn add_output(&mut self, output: Output) {
+        for window in self.windows() {
+            window.output_enter(&output, window.bbox());
+        }
         self.outputs.push(output);
     }
 
+    /// Bring floating and fullscreen windows placed on another output, e.g. one that was
+    /// unplugged, onto the output of the workspace. Tiled windows follow the layout.
+    fn fit_floating_windows(&mut self) {
+        let Some(area) = self.outputs().next().and_then(|o| self.output_geometry(o)) else {
+            return;
+        };
+        for window in &self.windows {
+            let mut window = window.borrow_mut();
+            if let Some((rec, floating)) = window.unfullscreened {
+                if floating && !area.overlaps(rec) {
+                    window.rec = rec;
+                    window.center_in(area);
+                    window.unfullscreened = Some((window.rec, floating));
+                }
+                window.rec = area;
+                window.window.toplevel().with_pending_state(|state| {
+                    state.size = Some(area.size);
+                });
+                window.window.toplevel().send_configure();
+            } else if window.floating && !area.overlaps(window.rec) {
+                window.center_in(area);
+            }
+        }
+    }
+
     pub fn remove_output(&mut self, output: &Output) {
+        for window in self.windows() {
+            window.output_leave(output);
+        }
         self.outputs.retain(|o| o != output);
     }
 
@@ -119,7 +928,7 @@ impl Workspace {
         let transform: Transform = o.current_transform();
         o.current_mode().map(|mode| {
             Rectangle::from_loc_and_size(
-                (0, 0),
+                o.current_location(),
                 transform
                     .transform_size(mode.size)
                     .to_f64()
@@ -137,6 +946,7 @@ impl Workspace {
         self.windows
             .iter()
             .filter(|e| e.borrow().bbox().to_f64().contains(point))
+            .filter(|e| self.is_visible(&e.borrow().window))
             .find_map(|e| {
                 // we need to offset the point to the location where the surface is actually drawn
                 let render_location = e.borrow().render_location();
@@ -151,6 +961,42 @@ impl Workspace {
             })
     }
 
+    pub fn magmawindow_under<P: Into<Point<f64, Logical>>>(
+        &self,
+        point: P,
+    ) -> Option<Rc<RefCell<MagmaWindow>>> {
+        let (window, _) = self.window_under(point)?;
+        self.magmawindow(&window)
+    }
+
+    /// The window whose title bar is at `point`, unless another window covers it.
+    pub fn title_bar_under<P: Into<Point<f64, Logical>>>(
+        &self,
+        point: P,
+    ) -> Option<Rc<RefCell<MagmaWindow>>> {
+        let point = point.into();
+        let on_title_bar = |w: &MagmaWindow| {
+            w.title_bar()
+                .is_some_and(|bar| bar.to_f64().contains(point))
+        };
+        self.windows
+            .iter()
+            .filter(|w| self.is_visible(&w.borrow().window))
+            .find(|w| {
+                let w = w.borrow();
+                w.bbox().to_f64().contains(point) || on_title_bar(&w)
+            })
+            .filter(|w| on_title_bar(&w.borrow()))
+            .cloned()
+    }
+
+    pub fn magmawindow(&self, window: &Window) -> Option<Rc<RefCell<MagmaWindow>>> {
+        self.windows
+            .iter()
+            .find(|w| &w.borrow().window == window)
+            .cloned()
+    }
+
     pub fn contains_window(&self, window: &Window) -> bool {
         self.windows.iter().any(|w| &w.borrow().window == window)
     }
@@ -163,56 +1009,329 @@ impl Default for Workspace {
 }
 
 pub struct Workspaces {
-    workspaces: Vec<Workspace>,
+    workspaces: BTreeMap<u8, Workspace>,
+    /// The workspace shown on every output
+    shown: Vec<(Output, u8)>,
+    /// The workspace of the focused output
     pub current: u8,
 }
 
 impl Workspaces {
     pub fn new(workspaceamount: u8) -> Self {
         Workspaces {
-            workspaces: (0..workspaceamount).map(|_| Workspace::new()).collect(),
+            workspaces: (0..workspaceamount)
+                .map(|id| (id, Workspace::with_id(id)))
+                .collect(),
+            shown: Vec::new(),
             current: 0,
         }
     }
 
+    /// All outputs, each showing its own workspace.
     pub fn outputs(&self) -> impl Iterator<Item = &Output> {
-        self.workspaces.iter().flat_map(|w| w.outputs())
+        self.shown.iter().map(|(o, _)| o)
+    }
+
+    /// Id of the workspace shown on `output`.
+    pub fn output_workspace(&self, output: &Output) -> Option<u8> {
+        self.shown
+            .iter()
+            .find(|(o, _)| o == output)
+            .map(|(_, id)| *id)
+    }
+
+    /// Geometry of any output, not just the ones of the current workspace.
+    pub fn output_geometry(&self, output: &Output) -> Option<Rectangle<i32, Logical>> {
+        self.workspaces
+            .get(&self.output_workspace(output)?)?
+            .output_geometry(output)
+    }
+
+    /// Show the first workspace that isn't shown yet on a new output.
+    pub fn add_output(&mut self, output: Output) {
+        let id = if self.shown.is_empty() {
+            self.current
+        } else {
+            (0..=u8::MAX)
+                .find(|id| self.shown.iter().all(|(_, shown)| shown != id))
+                .unwrap_or(self.current)
+        };
+        self.shown.push((output.clone(), id));
+        self.show(id, output);
+    }
+
+    /// Stop showing anything on `output`. The current workspace moves to a remaining output,
+    /// other workspaces of it are shown again once they are activated.
+    pub fn remove_output(&mut self, output: &Output) {
+        let removed = self.output_workspace(output);
+        self.shown.retain(|(o, _)| o != output);
+        for workspace in self.workspaces.values_mut() {
+            workspace.remove_output(output);
+        }
+        if removed == Some(self.current) {
+            if let Some((output, shown)) = self.shown.first_mut() {
+                *shown = self.current;
+                let output = output.clone();
+                self.show(self.current, output);
+            }
+        }
+        self.remove_unused();
+    }
+
+    /// Whether `window` is on a shown workspace and not hidden by focus mode.
+    pub fn is_shown(&self, window: &Window) -> bool {
+        self.shown.iter().any(|(_, id)| {
+            self.workspaces
+                .get(id)
+                .is_some_and(|ws| ws.contains_window(window) && ws.is_visible(window))
+        })
+    }
+
+    fn output_workspace_of(&self, id: u8) -> Option<&Output> {
+        self.shown
+            .iter()
+            .find(|(_, shown)| *shown == id)
+            .map(|(o, _)| o)
+    }
+
+    /// Move workspace `id` to `output` and lay it out there.
+    fn show(&mut self, id: u8, output: Output) {
+        let workspace = self.get_or_create(id);
+        for old in workspace.outputs.clone() {
+            workspace.remove_output(&old);
+        }
+        workspace.add_output(output);
+        workspace.fit_floating_windows();
+        update_layout(workspace);
     }
 
     pub fn iter(&mut self) -> impl Iterator<Item = &mut Workspace> {
-        self.workspaces.iter_mut()
+        self.workspaces.values_mut()
     }
 
     pub fn current_mut(&mut self) -> &mut Workspace {
-        &mut self.workspaces[self.current as usize]
+        self.get_or_create(self.current)
     }
 
     pub fn current(&self) -> &Workspace {
-        &self.workspaces[self.current as usize]
+        &self.workspaces[&self.current]
+    }
+
+    /// All workspaces together with their ids.
+    pub fn enumerate(&self) -> impl Iterator<Item = (u8, &Workspace)> {
+        self.workspaces.iter().map(|(id, ws)| (*id, ws))
+    }
+
+    pub fn enumerate_mut(&mut self) -> impl Iterator<Item = (u8, &mut Workspace)> {
+        self.workspaces.iter_mut().map(|(id, ws)| (*id, ws))
+    }
+
+    pub fn get(&self, id: u8) -> Option<&Workspace> {
+        self.workspaces.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u8) -> Option<&mut Workspace> {
+        self.workspaces.get_mut(&id)
+    }
+
+    /// Get the workspace with `id`, creating it if it doesn't exist.
+    /// New workspaces get an output once they are activated.
+    pub fn get_or_create(&mut self, id: u8) -> &mut Workspace {
+        self.workspaces
+            .entry(id)
+            .or_insert_with(|| Workspace::with_id(id))
+    }
+
+    /// Remove workspaces beyond `CONFIG.workspaces` that are empty and not shown.
+    pub fn remove_unused(&mut self) {
+        let current = self.current;
+        let shown: Vec<u8> = self.shown.iter().map(|(_, id)| *id).collect();
+        self.workspaces.retain(|id, ws| {
+            *id < CONFIG.workspaces
+                || *id == current
+                || shown.contains(id)
+                || !ws.windows.is_empty()
+        });
     }
 
     pub fn all_windows(&self) -> impl Iterator<Item = Ref<'_, Window>> {
-        self.workspaces.iter().flat_map(|w| w.windows())
+        self.workspaces.values().flat_map(|w| w.windows())
     }
 
     pub fn workspace_from_window(&mut self, window: &Window) -> Option<&mut Workspace> {
         self.workspaces
-            .iter_mut()
+            .values_mut()
             .find(|w| w.contains_window(window))
     }
 
+    pub fn workspace_id(&self, window: &Window) -> Option<u8> {
+        self.enumerate()
+            .find(|(_, w)| w.contains_window(window))
+            .map(|(id, _)| id)
+    }
+
+    /// Find the first window matching `predicate` and the id of its workspace.
+    pub fn find_window<F: Fn(&Window) -> bool>(&self, predicate: F) -> Option<(u8, Window)> {
+        self.enumerate()
+            .find_map(|(id, ws)| ws.windows().find(|w| predicate(w)).map(|w| (id, w.clone())))
+    }
+
+    /// Exchange the windows of two workspaces and re-tile both.
+    pub fn swap_workspaces(&mut self, a: u8, b: u8) {
+        if a == b {
+            return;
+        }
+        self.get_or_create(a);
+        self.get_or_create(b);
+        let mut first = self.workspaces.remove(&a).unwrap();
+        let second = self.workspaces.get_mut(&b).unwrap();
+        for workspace in [&first, &*second] {
+            for output in workspace.outputs() {
+                for window in workspace.windows() {
+                    window.output_leave(output);
+                }
+            }
+        }
+        std::mem::swap(&mut first.windows, &mut second.windows);
+        std::mem::swap(&mut first.layout_tree, &mut second.layout_tree);
+        std::mem::swap(&mut first.focused_only, &mut second.focused_only);
+        for workspace in [&first, &*second] {
+            for output in workspace.outputs() {
+                for window in workspace.windows() {
+                    window.output_enter(output, window.bbox());
+                }
+            }
+        }
+        // floating windows may now be placed on another output
+        first.fit_floating_windows();
+        second.fit_floating_windows();
+        update_layout(&mut first);
+        update_layout(second);
+        self.workspaces.insert(a, first);
+        self.remove_unused();
+    }
+
+    /// Id of the workspace called `name`.
+    pub fn find_by_name(&self, name: &str) -> Option<u8> {
+        self.enumerate()
+            .find(|(_, ws)| ws.name.as_deref() == Some(name))
+            .map(|(id, _)| id)
+    }
+
+    /// Rename workspace `id`, `None` removing its name.
+    /// Fails if another workspace already has the name.
+    pub fn rename(&mut self, id: u8, name: Option<String>) -> Result<(), u8> {
+        if let Some(other) = name
+            .as_deref()
+            .and_then(|name| self.find_by_name(name))
+            .filter(|other| *other != id)
+        {
+            return Err(other);
+        }
+        self.get_or_create(id).name = name;
+        Ok(())
+    }
+
+    /// Focus workspace `id`, showing it on the focused output unless another output shows it.
     pub fn activate(&mut self, id: u8) {
+        if self.output_workspace_of(id).is_none() {
+            let output = self
+                .shown
+                .iter_mut()
+                .find(|(_, shown)| *shown == self.current);
+            match output {
+                Some((output, shown)) => {
+                    *shown = id;
+                    let output = output.clone();
+                    self.show(id, output);
+                }
+                None => {
+                    self.get_or_create(id);
+                }
+            }
+        }
         self.current = id;
+        self.remove_unused();
     }
     pub fn move_window_to_workspace(&mut self, window: &Window, workspace: u8) {
         let mut removed = None;
         if let Some(ws) = self.workspace_from_window(window) {
             removed = ws.remove_window(window);
-            bsp_update_layout(ws)
+            update_layout(ws)
         }
         if let Some(removed) = removed {
-            self.workspaces[workspace as usize].add_window(removed);
-            bsp_update_layout(&mut self.workspaces[workspace as usize])
+            let ws = self.get_or_create(workspace);
+            ws.add_window(removed);
+            update_layout(ws)
         }
+        self.remove_unused();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    #[test]
+    fn fit_to_hints_centers_windows_smaller_than_their_tile() {
+        let tile = rec(100, 100, 800, 600);
+        assert_eq!(
+            fit_to_hints(tile, Size::from((0, 0)), Size::from((400, 300))),
+            Some(rec(300, 250, 400, 300))
+        );
+        // an unbounded max size takes the whole tile
+        assert_eq!(
+            fit_to_hints(tile, Size::from((200, 200)), Size::from((0, 0))),
+            Some(tile)
+        );
+    }
+
+    #[test]
+    fn fit_to_hints_rejects_windows_larger_than_their_tile() {
+        let tile = rec(100, 100, 800, 600);
+        assert_eq!(
+            fit_to_hints(tile, Size::from((1000, 200)), Size::from((0, 0))),
+            None
+        );
+        assert_eq!(
+            fit_to_hints(tile, Size::from((200, 700)), Size::from((0, 0))),
+            None
+        );
+    }
+
+    #[test]
+    fn nearest_in_direction_follows_the_layout() {
+        // master on the left, two stacked windows on the right
+        let master = rec(0, 0, 500, 1000);
+        let top = rec(500, 0, 500, 500);
+        let bottom = rec(500, 500, 500, 500);
+        let windows = || [(master, "master"), (top, "top"), (bottom, "bottom")].into_iter();
+
+        let from = |rec, direction| {
+            nearest_in_direction(rec, windows().filter(|(r, _)| *r != rec), direction)
+        };
+        assert_eq!(from(master, Direction::Right), Some("top"));
+        assert_eq!(from(master, Direction::Left), None);
+        assert_eq!(from(top, Direction::Left), Some("master"));
+        assert_eq!(from(top, Direction::Down), Some("bottom"));
+        assert_eq!(from(bottom, Direction::Up), Some("top"));
+        assert_eq!(from(bottom, Direction::Down), None);
+    }
+
+    #[test]
+    fn nearest_in_direction_prefers_aligned_windows() {
+        let from = rec(0, 0, 100, 100);
+        let candidates = [
+            (rec(200, 300, 100, 100), "off to the side"),
+            (rec(400, 0, 100, 100), "aligned"),
+        ];
+        assert_eq!(
+            nearest_in_direction(from, candidates.into_iter(), Direction::Right),
+            Some("aligned")
+        );
     }
 }